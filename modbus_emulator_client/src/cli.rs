@@ -1,21 +1,105 @@
+use std::time::Duration;
+
 use clap::Parser;
 
+use modbus_serial_line::{FlowControl, Parity, SerialParams};
+
+use crate::{frame::Framing, output::OutputFormat};
+
 #[derive(Debug, Parser)]
 #[command(name = "modbus emulator client")]
 pub struct Args {
-    /// windows serial - COMX, linux serial - /dev/X, tcp - host:port
+    /// windows serial - COMX, linux serial - /dev/X, tcp - host:port, udp - udp://host:port
     #[arg(long, default_value = "127.0.0.1:5052")]
     pub addr: String,
 
+    /// framing of requests and responses, rtu on a serial port and mbap on tcp by default
+    #[arg(long, value_enum)]
+    pub framing: Option<Framing>,
+
     /// serial port baud rate
     #[arg(long, default_value_t = 0)]
     pub baud_rate: u32,
 
+    /// serial data bits
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(5..=8))]
+    pub data_bits: u8,
+
+    /// serial parity
+    #[arg(long, value_enum, default_value_t = Parity::None)]
+    pub parity: Parity,
+
+    /// serial stop bits
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub stop_bits: u8,
+
+    /// serial flow control
+    #[arg(long, value_enum, default_value_t = FlowControl::None)]
+    pub flow_control: FlowControl,
+
+    /// raise RTS while sending, for rs-485 transceivers without automatic direction control
+    #[arg(long, default_value_t = false)]
+    pub rts_toggle: bool,
+
+    /// microseconds of silence kept between rtu frames, 3.5 characters by default
+    #[arg(long)]
+    pub inter_frame_us: Option<u64>,
+
     /// register schema toml file
     #[arg(long, default_value = "schema.toml")]
     pub schema: String,
 
-    /// serial port baud rate
-    #[arg(long, default_value_t = 0)]
+    /// slave id of the serial device, or unit id of the device behind a tcp or udp server
+    #[arg(long, default_value_t = 1)]
     pub slave: u8,
+
+    /// connect with modbus/tcp security, the server certificate must be issued by a PEM certificate authority of this file
+    #[arg(long)]
+    pub tls_ca: Option<String>,
+
+    /// PEM client certificate chain presented to the server, its role extension sets the access of the client
+    #[arg(long, requires_all = ["tls_ca", "tls_key"])]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// name the server certificate must be valid for, the ip address of --addr by default
+    #[arg(long, requires = "tls_ca")]
+    pub tls_server_name: Option<String>,
+
+    /// run a command instead of the interactive prompt, repeatable, e.g. --exec "r h 3"
+    #[arg(long)]
+    pub exec: Vec<String>,
+
+    /// run the commands of a file, one per line, after those of --exec
+    #[arg(long)]
+    pub script: Option<String>,
+
+    /// unused addresses one read request may span to cover several registers
+    #[arg(long, default_value_t = 8)]
+    pub max_gap: u16,
+
+    /// print the values read as records on stdout, the log moves to stderr
+    #[arg(long, value_enum)]
+    pub output: Option<OutputFormat>,
+
+    /// validate the register schema, print the problems found and exit
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+}
+
+impl Args {
+    pub fn serial_params(&self) -> SerialParams {
+        SerialParams {
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            flow_control: self.flow_control,
+            rts_toggle: self.rts_toggle,
+            inter_frame: self.inter_frame_us.map(Duration::from_micros),
+        }
+    }
 }
@@ -0,0 +1,427 @@
+use modbus_register_schema::*;
+
+use tracing;
+
+use crate::{output::Printer, read, watch, write};
+
+/// One line of the command grammar shared by the interactive prompt,
+/// `--exec` and `--script`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Exit,
+    Help,
+    Query {
+        target: Target,
+    },
+    Read {
+        target: Target,
+    },
+    Write {
+        target: Target,
+        values: Vec<String>,
+    },
+    ReadWrite {
+        read: Target,
+        write: Target,
+        values: Vec<String>,
+    },
+    Mask {
+        target: Target,
+        and_mask: u16,
+        or_mask: u16,
+    },
+    Watch {
+        target: Target,
+        options: watch::WatchOptions,
+    },
+}
+
+/// The descriptions a command applies to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// `<type> <index>`, the position in the table of the schema
+    Index(RegisterTable, Selector),
+    /// a description name, `*` and `?` match any text and any character
+    Name(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selector {
+    All,
+    Index(usize),
+}
+
+/// Parse one command line, `Ok(None)` for a blank line or a `#` comment.
+pub fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let params = line.split_whitespace().collect::<Vec<&str>>();
+    let command = match params[0] {
+        "e" | "exit" => Command::Exit,
+        "h" | "help" => Command::Help,
+        "q" | "query" => Command::Query {
+            target: parse_target(&params, "query <type> <index> | query <name>")?.0,
+        },
+        "r" | "read" => Command::Read {
+            target: parse_target(&params, "read <type> <index> | read <name>")?.0,
+        },
+        "w" | "write" => {
+            let usage = "write <type> <index> <value> | write <name> <value>";
+            let (target, rest) = parse_target(&params, usage)?;
+            if rest.is_empty() {
+                return Err(format!("args missing, {usage}"));
+            }
+            if let Target::Index(table, Selector::All) = target {
+                return Err(format!("write needs one {table} index"));
+            }
+            Command::Write {
+                target,
+                values: rest.iter().map(|s| s.to_string()).collect(),
+            }
+        }
+        "rw" | "read_write" => {
+            if params.len() < 4 {
+                return Err("args missing, read_write <index> <index> <value>".to_string());
+            }
+            Command::ReadWrite {
+                read: parse_holding(params[1]),
+                write: parse_holding(params[2]),
+                values: params[3..].iter().map(|s| s.to_string()).collect(),
+            }
+        }
+        "m" | "mask" => {
+            if params.len() < 4 {
+                return Err("args missing, mask <index> <and> <or>".to_string());
+            }
+            Command::Mask {
+                target: parse_holding(params[1]),
+                and_mask: write::parse_mask(params[2])
+                    .map_err(|err| format!("invalid and mask {}: {err}", params[2]))?,
+                or_mask: write::parse_mask(params[3])
+                    .map_err(|err| format!("invalid or mask {}: {err}", params[3]))?,
+            }
+        }
+        "wa" | "watch" => {
+            let usage = "watch <type> <index> | watch <name> [--interval <time>] [--csv <file>] [--count <n>]";
+            let (target, rest) = parse_target(&params, usage)?;
+            Command::Watch {
+                target,
+                options: parse_watch_options(rest)?,
+            }
+        }
+        action => return Err(format!("unknown command {action}, h for help")),
+    };
+    Ok(Some(command))
+}
+
+/// Target of `params[1..]`, either `<type> <index>` or a name, followed by
+/// the remaining params.
+fn parse_target<'a>(params: &'a [&'a str], usage: &str) -> Result<(Target, &'a [&'a str]), String> {
+    if params.len() < 2 {
+        return Err(format!("args missing, {usage}"));
+    }
+    match parse_table(params[1]) {
+        Some(table) => {
+            if params.len() < 3 {
+                return Err(format!("args missing, {usage}"));
+            }
+            let target = Target::Index(table, parse_selector(params[2])?);
+            Ok((target, &params[3..]))
+        }
+        None => Ok((Target::Name(params[1].to_string()), &params[2..])),
+    }
+}
+
+/// Holding register index or name of read_write and mask.
+fn parse_holding(text: &str) -> Target {
+    match text.parse::<usize>() {
+        Ok(index) => Target::Index(RegisterTable::HoldingRegisters, Selector::Index(index)),
+        Err(_) => Target::Name(text.to_string()),
+    }
+}
+
+fn parse_watch_options(params: &[&str]) -> Result<watch::WatchOptions, String> {
+    let mut options = watch::WatchOptions::default();
+    let mut params = params.iter();
+    while let Some(option) = params.next() {
+        let value = params
+            .next()
+            .ok_or(format!("args missing, {option} needs a value"))?;
+        match *option {
+            "--interval" => options.interval = watch::parse_interval(value)?,
+            "--csv" => options.csv = Some(value.to_string()),
+            "--count" => {
+                options.count = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|err| format!("invalid count {value}: {err}"))?,
+                )
+            }
+            _ => return Err(format!("unknown watch option {option}")),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_table(text: &str) -> Option<RegisterTable> {
+    match text {
+        "c" | "coils" => Some(RegisterTable::Coils),
+        "d" | "discrete" => Some(RegisterTable::DiscreteInputs),
+        "i" | "input" => Some(RegisterTable::InputRegisters),
+        "h" | "holding" => Some(RegisterTable::HoldingRegisters),
+        _ => None,
+    }
+}
+
+fn parse_selector(text: &str) -> Result<Selector, String> {
+    match text {
+        "a" | "all" => Ok(Selector::All),
+        _ => text
+            .parse::<usize>()
+            .map(Selector::Index)
+            .map_err(|err| format!("invalid index {text}: {err}")),
+    }
+}
+
+/// `*` matches any text, `?` any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    // position after the last `*` and the name position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if let Some((after_star, tried)) = star {
+            // let the last `*` swallow one more character
+            p = after_star;
+            n = tried + 1;
+            star = Some((after_star, tried + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Descriptions picked by `target` with their tables, in schema order.
+fn resolve<'a>(
+    schema: &'a RegisterSchema,
+    target: &Target,
+) -> Result<Vec<(RegisterTable, &'a RegisterDescription)>, String> {
+    match target {
+        Target::Index(table, Selector::All) => Ok(schema
+            .registers(*table)
+            .iter()
+            .map(|desc| (*table, desc))
+            .collect()),
+        Target::Index(table, Selector::Index(index)) => schema
+            .registers(*table)
+            .get(*index)
+            .map(|desc| vec![(*table, desc)])
+            .ok_or(format!("{table} index out of range")),
+        Target::Name(pattern) => {
+            let found = RegisterTable::ALL
+                .iter()
+                .flat_map(|&table| {
+                    schema
+                        .registers(table)
+                        .iter()
+                        .filter(|desc| glob_match(pattern, &desc.name))
+                        .map(move |desc| (table, desc))
+                })
+                .collect::<Vec<_>>();
+            if found.is_empty() {
+                return Err(format!("no register matches {pattern}"));
+            }
+            Ok(found)
+        }
+    }
+}
+
+/// The single description picked by `target`.
+fn resolve_one<'a>(
+    schema: &'a RegisterSchema,
+    target: &Target,
+) -> Result<(RegisterTable, &'a RegisterDescription), String> {
+    let found = resolve(schema, target)?;
+    if found.len() > 1 {
+        let names = found.iter().map(|(_table, desc)| desc.name.as_str());
+        return Err(format!(
+            "{} registers match, pick one of {}",
+            found.len(),
+            names.collect::<Vec<&str>>().join(", ")
+        ));
+    }
+    Ok(found[0])
+}
+
+/// The single holding register picked by `target`.
+fn resolve_holding<'a>(
+    schema: &'a RegisterSchema,
+    target: &Target,
+) -> Result<&'a RegisterDescription, String> {
+    match resolve_one(schema, target)? {
+        (RegisterTable::HoldingRegisters, desc) => Ok(desc),
+        (table, desc) => Err(format!(
+            "{} is in {table}, not holding_registers",
+            desc.name
+        )),
+    }
+}
+
+/// Run one command against the device, reads of several registers share
+/// requests spanning up to `max_gap` unused addresses and the records read
+/// are passed to `printer` if any. Modbus exceptions, constraint violations and unknown
+/// registers are errors.
+pub async fn execute(
+    ctx: &mut tokio_modbus::client::Context,
+    schema: &RegisterSchema,
+    command: &Command,
+    max_gap: u16,
+    printer: Option<&mut Printer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Exit => {}
+        Command::Help => tracing::info!("{}", help_text(schema)),
+        Command::Query { target } => {
+            for (_table, desc) in resolve(schema, target)? {
+                tracing::info!("{:?}", desc);
+            }
+        }
+        Command::Read { target } => {
+            let registers = resolve(schema, target)?;
+            let mut records = vec![];
+            let raws = read::fetch_registers(ctx, &registers, max_gap).await;
+            for ((table, desc), raw) in registers.into_iter().zip(raws) {
+                records.push(read::decode_record(desc, table, raw?));
+            }
+            if let Some(printer) = printer {
+                printer.print(&records);
+            }
+        }
+        Command::Write { target, values } => {
+            let (table, desc) = resolve_one(schema, target)?;
+            if !matches!(
+                table,
+                RegisterTable::Coils | RegisterTable::HoldingRegisters
+            ) {
+                return Err(format!("{} is in {table}, which is read only", desc.name).into());
+            }
+            let values = values.iter().map(String::as_str).collect::<Vec<&str>>();
+            write::write_register(ctx, desc, &values).await?
+        }
+        Command::ReadWrite {
+            read,
+            write,
+            values,
+        } => {
+            let read_desc = resolve_holding(schema, read)?;
+            let write_desc = resolve_holding(schema, write)?;
+            let values = values.iter().map(String::as_str).collect::<Vec<&str>>();
+            let record = write::read_write_register(ctx, read_desc, write_desc, &values).await?;
+            if let Some(printer) = printer {
+                printer.print(&[record]);
+            }
+        }
+        Command::Mask {
+            target,
+            and_mask,
+            or_mask,
+        } => {
+            let desc = resolve_holding(schema, target)?;
+            write::mask_write_register(ctx, desc, *and_mask, *or_mask).await?
+        }
+        Command::Watch { target, options } => {
+            let registers = resolve(schema, target)?;
+            watch::watch(ctx, &registers, options, max_gap, printer).await?
+        }
+    }
+
+    Ok(())
+}
+
+pub fn help_text(schema: &RegisterSchema) -> String {
+    format!(
+        r#"
+------------------------------------------------------------
+e | exit                         : Exit the program
+h | help                         : Show this help message
+q | query <type> <index>         : Query register schema
+r | read  <type> <index>         : Read register data
+w | write <type> <index> <value> : Write data to register
+rw | read_write <index> <index> <value>
+                                 : Write the second holding register,
+                                   then read the first one back
+m | mask <index> <and> <or>      : Mask write a holding register
+wa | watch <type> <index> [--interval 500ms] [--csv <file>] [--count <n>]
+                                 : Poll registers, print the changes
+                                   until ctrl-c or <n> polls
+         <type> <index> | <name> : a register by position or by name,
+                                   q, r and wa take globs like i_u16_*,
+                                   rw and m a holding index or name
+                         <value> : the value to write
+                     <and> <or>  : masks, decimal or 0x hex
+                          <type> : c | coils
+                                   d | discrete
+                                   i | input
+                                   h | holding
+                         <index> : a | all
+                     coils index : [0, {}) // read + write
+            discrete input index : [0, {}) // read only
+            input register index : [0, {}) // read only
+          holding register index : [0, {}) // read + write
+------------------------------------------------------------"#,
+        schema.coils.len(),
+        schema.discrete_inputs.len(),
+        schema.input_registers.len(),
+        schema.holding_registers.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_wildcards_matches_the_whole_name() {
+        assert!(glob_match("h_speed", "h_speed"));
+        assert!(!glob_match("h_speed", "h_speed_max"));
+        assert!(!glob_match("h_speed", "h_spee"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_text() {
+        assert!(glob_match("h_*", "h_speed"));
+        assert!(glob_match("h_*", "h_"));
+        assert!(glob_match("*_max", "h_speed_max"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("h_*_max", "h_speed_max"));
+        assert!(!glob_match("h_*_max", "h_speed_min"));
+        assert!(!glob_match("i_*", "h_speed"));
+    }
+
+    #[test]
+    fn glob_star_backtracks() {
+        // the first `_` after the star is not the one that matches
+        assert!(glob_match("*_max", "h_max_speed_max"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_one_character() {
+        assert!(glob_match("h_temp?", "h_temp1"));
+        assert!(!glob_match("h_temp?", "h_temp"));
+        assert!(!glob_match("h_temp?", "h_temp12"));
+        assert!(glob_match("h_temp??*", "h_temp12"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use modbus_register_schema::*;
+
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+
+const COMMANDS: [&str; 13] = [
+    "exit",
+    "help",
+    "query",
+    "read",
+    "write",
+    "read_write",
+    "mask",
+    "watch",
+    "coils",
+    "discrete",
+    "input",
+    "holding",
+    "all",
+];
+
+/// Tab completion of the prompt: command words, register names and the
+/// names of enum values after `write <name>`.
+pub struct CommandHelper {
+    names: Vec<String>,
+    enums: BTreeMap<String, Vec<String>>,
+}
+
+impl CommandHelper {
+    pub fn new(schema: &RegisterSchema) -> Self {
+        let mut names = vec![];
+        let mut enums = BTreeMap::new();
+        for table in RegisterTable::ALL {
+            for desc in schema.registers(table) {
+                names.push(desc.name.clone());
+                if let RegisterValueType::Enum(constraints) = &desc.value {
+                    let mut values = constraints.kv.keys().cloned().collect::<Vec<String>>();
+                    values.sort();
+                    enums.insert(desc.name.clone(), values);
+                }
+            }
+        }
+        Self { names, enums }
+    }
+
+    fn candidates(&self, words: &[&str]) -> Vec<String> {
+        match words {
+            [] => COMMANDS[..8].iter().map(|s| s.to_string()).collect(),
+            ["w" | "write", name] => self.enums.get(*name).cloned().unwrap_or_default(),
+            [_] | ["rw" | "read_write", _] => {
+                let mut candidates = self.names.clone();
+                candidates.extend(COMMANDS[8..12].iter().map(|s| s.to_string()));
+                candidates
+            }
+            [_, "c" | "coils" | "d" | "discrete" | "i" | "input" | "h" | "holding"] => {
+                vec!["all".to_string()]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+impl Completer for CommandHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words = line[..start].split_whitespace().collect::<Vec<&str>>();
+        let prefix = &line[start..];
+        let matches = self
+            .candidates(&words)
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(prefix))
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
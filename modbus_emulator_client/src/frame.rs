@@ -0,0 +1,12 @@
+use clap::ValueEnum;
+
+/// How requests and responses are framed on the line.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Framing {
+    /// modbus tcp, the MBAP header
+    Mbap,
+    /// slave id, pdu and crc
+    Rtu,
+    /// `:`, hex of slave id, pdu and LRC, CRLF
+    Ascii,
+}
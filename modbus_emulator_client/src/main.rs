@@ -1,218 +1,259 @@
-use std::{io::Write, net::SocketAddr};
+use std::net::SocketAddr;
 
 use clap::Parser;
 
+use tokio::net::TcpStream;
+
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+
 use modbus_register_schema::*;
 
 use tokio_modbus::prelude::*;
 
-use tokio_serial::SerialStream;
-
 use time::{macros::format_description, UtcOffset};
 
-use tracing;
 use tracing_subscriber::{self, fmt::time::OffsetTime};
 
 pub mod cli;
+pub mod command;
+pub mod complete;
+pub mod frame;
+pub mod output;
+pub mod plan;
 pub mod read;
+pub mod tls;
+pub mod udp;
+pub mod watch;
 pub mod write;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // init stdout tracing log
-    tracing_subscriber::fmt()
+    // parse command line args
+    let args = cli::Args::parse();
+
+    // init stdout tracing log, stderr when stdout carries the output records
+    let subscriber = tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .with_line_number(true)
         .with_timer(OffsetTime::new(
             UtcOffset::from_hms(8, 0, 0).unwrap(),
             format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]"),
-        ))
-        .init();
-
-    // parse command line args
-    let args = cli::Args::parse();
+        ));
+    if args.output.is_some() {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
     tracing::info!("{:?}", args);
-    let schema = RegisterSchema::load(&args.schema, false).unwrap();
-
-    let mut ctx =
-        if (args.addr.starts_with("COM") || args.addr.starts_with("/dev/")) && args.baud_rate > 0 {
-            // connect serial
-            let serial_builder = tokio_serial::new(args.addr, args.baud_rate);
-            let serial_stream = SerialStream::open(&serial_builder).unwrap();
-            let salve = Slave(args.slave);
-            rtu::attach_slave(serial_stream, salve)
-        } else {
-            // connect tcp
-            let socket_addr: SocketAddr = args.addr.parse().unwrap();
-            tcp::connect(socket_addr).await?
+    let Some(schema) = RegisterSchema::load(&args.schema, false) else {
+        tracing::error!("failed to load register schema {}", args.schema);
+        std::process::exit(1);
+    };
+    if args.check {
+        std::process::exit(check_schema(&args.schema, &schema));
+    }
+    for error in schema.validate().err().unwrap_or_default() {
+        tracing::warn!("{}: {error}", args.schema);
+    }
+
+    // batch commands are parsed up front, a typo fails before touching the device
+    let mut batch = vec![];
+    for line in &args.exec {
+        batch.push(("--exec".to_string(), line.clone()));
+    }
+    if let Some(path) = &args.script {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                tracing::error!("failed to read script {}: {}", path, err);
+                std::process::exit(1);
+            }
         };
+        for (n, line) in text.lines().enumerate() {
+            batch.push((format!("{}:{}", path, n + 1), line.to_string()));
+        }
+    }
+    let mut commands = vec![];
+    for (source, line) in &batch {
+        match command::parse(line) {
+            Ok(Some(command)) => commands.push((source, line, command)),
+            Ok(None) => {}
+            Err(err) => {
+                tracing::error!("{source}: {line}: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
 
-    // show help manual
-    let help_text = format!(
-        r#"
-------------------------------------------------------------
-e | exit                         : Exit the program
-h | help                         : Show this help message
-q | query <type> <index>         : Query register schema
-r | read  <type> <index>         : Read register data
-w | write <type> <index> <value> : Write data to register
-                         <value> : the value to write
-                          <type> : c | coils
-                                   d | discrete
-                                   i | input
-                                   h | holding
-                         <index> : a | all
-                     coils index : [0, {}) // read + write
-            discrete input index : [0, {}) // read only
-            input register index : [0, {}) // read only
-          holding register index : [0, {}) // read + write
-------------------------------------------------------------"#,
-        schema.coils.len(),
-        schema.discrete_inputs.len(),
-        schema.input_registers.len(),
-        schema.holding_registers.len()
-    );
-    tracing::info!("{}", help_text);
-
-    // user interaction
-    let mut input = String::new();
-    loop {
-        input.clear();
-        print!("\x1b[1m\x1b[32m> \x1b[0m");
-        std::io::stdout().flush().unwrap();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let params = input.split_whitespace().collect::<Vec<&str>>();
-        if params.len() > 0 {
-            let action = params[0];
-            if action == "e" || action == "exit" {
-                break;
+    // udp is picked by the scheme, tcp is the default
+    let (udp, addr) = match args.addr.strip_prefix("udp://") {
+        Some(addr) => (true, addr),
+        None => (false, args.addr.trim_start_matches("tcp://")),
+    };
+    let serial = (addr.starts_with("COM") || addr.starts_with("/dev/")) && args.baud_rate > 0;
+    let framing = args.framing.unwrap_or(if serial {
+        frame::Framing::Rtu
+    } else {
+        frame::Framing::Mbap
+    });
+    if args.tls_ca.is_some() && (serial || udp || framing != frame::Framing::Mbap) {
+        tracing::error!("tls needs a tcp address and mbap framing");
+        std::process::exit(1);
+    }
+    let salve = Slave(args.slave);
+    let mut ctx = if serial {
+        // connect serial
+        let serial_stream =
+            modbus_serial_line::SerialLine::open(addr, &args.serial_params()).unwrap();
+        match framing {
+            frame::Framing::Mbap => {
+                tracing::error!("mbap framing needs a tcp address");
+                std::process::exit(1);
             }
-            if action == "h" || action == "help" {
-                tracing::info!("{}", help_text);
-            } else if action == "q" || action == "query" {
-                if params.len() < 3 {
-                    tracing::warn!("args missing, query <type> <index>");
-                    continue;
-                }
+            frame::Framing::Rtu => rtu::attach_slave(serial_stream, salve),
+            frame::Framing::Ascii => {
+                rtu::attach_slave(modbus_serial_line::AsciiLine::new(serial_stream), salve)
+            }
+        }
+    } else if udp {
+        // connect udp
+        if framing != frame::Framing::Mbap {
+            tracing::error!("udp carries mbap framing only");
+            std::process::exit(1);
+        }
+        let socket_addr: SocketAddr = addr.parse().unwrap();
+        tcp::attach_slave(udp::UdpLine::connect(socket_addr).await?, salve)
+    } else {
+        // connect tcp
+        let socket_addr: SocketAddr = addr.parse().unwrap();
+        match framing {
+            frame::Framing::Mbap if args.tls_ca.is_some() => {
+                let identity = args.tls_cert.as_deref().zip(args.tls_key.as_deref());
+                let config = tls::client_config(args.tls_ca.as_deref().unwrap(), identity)?;
+                let tls_stream =
+                    tls::connect(config, socket_addr, args.tls_server_name.as_deref()).await?;
+                tcp::attach_slave(tls_stream, salve)
+            }
+            frame::Framing::Mbap => tcp::connect_slave(socket_addr, salve).await?,
+            frame::Framing::Rtu => rtu::attach_slave(TcpStream::connect(socket_addr).await?, salve),
+            frame::Framing::Ascii => {
+                let tcp_stream = TcpStream::connect(socket_addr).await?;
+                rtu::attach_slave(modbus_serial_line::AsciiLine::new(tcp_stream), salve)
+            }
+        }
+    };
 
-                let type_ = params[1];
-                let index = params[2];
-                if index != "a" && index != "all" {
-                    let index = index.parse::<usize>()?;
-                    let desc = if type_ == "c" || type_ == "coils" {
-                        if index >= schema.coils.len() {
-                            tracing::warn!("coils index out of range");
-                            continue;
-                        }
-                        &schema.coils[index]
-                    } else if type_ == "d" || type_ == "discrete" {
-                        if index >= schema.discrete_inputs.len() {
-                            tracing::warn!("discrete_inputs index out of range");
-                            continue;
-                        }
-                        &schema.discrete_inputs[index]
-                    } else if type_ == "i" || type_ == "input" {
-                        if index >= schema.input_registers.len() {
-                            tracing::warn!("input_registers index out of range");
-                            continue;
-                        }
-                        &schema.input_registers[index]
-                    } else {
-                        if index >= schema.holding_registers.len() {
-                            tracing::warn!("holding_registers index out of range");
-                            continue;
-                        }
-                        &schema.holding_registers[index]
-                    };
-
-                    tracing::info!("{:?}", desc);
-                } else {
-                    let registers = if type_ == "c" || type_ == "coils" {
-                        &schema.coils
-                    } else if type_ == "d" || type_ == "discrete" {
-                        &schema.discrete_inputs
-                    } else if type_ == "i" || type_ == "input" {
-                        &schema.input_registers
-                    } else {
-                        &schema.holding_registers
-                    };
-                    for desc in registers {
-                        tracing::info!("{:?}", desc);
-                    }
-                }
-            } else if action == "r" || action == "read" {
-                if params.len() < 3 {
-                    tracing::warn!("args missing, read <type> <index>");
-                    continue;
-                }
+    let mut printer = args.output.map(output::Printer::new);
+    let code = if batch.is_empty() {
+        interact(&mut ctx, &schema, args.max_gap, &mut printer).await?;
+        0
+    } else {
+        run_batch(&mut ctx, &schema, &commands, args.max_gap, &mut printer).await
+    };
 
-                let type_ = params[1];
-                let index = params[2];
-                if index != "a" && index != "all" {
-                    let index = index.parse::<usize>()?;
-                    let desc = if type_ == "c" || type_ == "coils" {
-                        if index >= schema.coils.len() {
-                            tracing::warn!("coils index out of range");
-                            continue;
-                        }
-                        &schema.coils[index]
-                    } else if type_ == "d" || type_ == "discrete" {
-                        if index >= schema.discrete_inputs.len() {
-                            tracing::warn!("discrete_inputs index out of range");
-                            continue;
-                        }
-                        &schema.discrete_inputs[index]
-                    } else if type_ == "i" || type_ == "input" {
-                        if index >= schema.input_registers.len() {
-                            tracing::warn!("input_registers index out of range");
-                            continue;
-                        }
-                        &schema.input_registers[index]
-                    } else {
-                        if index >= schema.holding_registers.len() {
-                            tracing::warn!("holding_registers index out of range");
-                            continue;
-                        }
-                        &schema.holding_registers[index]
-                    };
-
-                    read::read_register(&mut ctx, desc, type_ == "i" || type_ == "input").await?
-                } else {
-                    let registers = if type_ == "c" || type_ == "c" {
-                        &schema.coils
-                    } else if type_ == "d" || type_ == "discrete" {
-                        &schema.discrete_inputs
-                    } else if type_ == "i" || type_ == "input" {
-                        &schema.input_registers
-                    } else {
-                        &schema.holding_registers
-                    };
-                    for desc in registers {
-                        read::read_register(&mut ctx, desc, type_ == "i" || type_ == "input")
-                            .await?
-                    }
-                }
-            } else if action == "w" || action == "write" {
-                if params.len() < 3 {
-                    tracing::warn!("args missing, write <type> <index> <value>");
-                    continue;
-                }
+    ctx.disconnect().await?;
+    std::process::exit(code);
+}
 
-                let type_ = params[1];
-                let index = params[2];
-                let index = index.parse::<usize>()?;
-                if type_ == "c" || type_ == "coils" {
-                    let desc = &schema.coils[index];
-                    write::write_register(&mut ctx, desc, params).await?
-                } else if type_ == "h" || type_ == "holding" {
-                    let desc = &schema.holding_registers[index];
-                    write::write_register(&mut ctx, desc, params).await?
+/// Run the commands in order, stopping at the first failure. Returns the
+/// process exit code, 1 if a command failed.
+async fn run_batch(
+    ctx: &mut tokio_modbus::client::Context,
+    schema: &RegisterSchema,
+    commands: &[(&String, &String, command::Command)],
+    max_gap: u16,
+    printer: &mut Option<output::Printer>,
+) -> i32 {
+    for (source, line, command) in commands {
+        if *command == command::Command::Exit {
+            break;
+        }
+        if let Err(err) = command::execute(ctx, schema, command, max_gap, printer.as_mut()).await {
+            tracing::error!("{source}: {line}: {err}");
+            return 1;
+        }
+    }
+    0
+}
+
+/// Read commands from stdin until `exit` or end of input, failed commands
+/// are reported and the session goes on.
+async fn interact(
+    ctx: &mut tokio_modbus::client::Context,
+    schema: &RegisterSchema,
+    max_gap: u16,
+    printer: &mut Option<output::Printer>,
+) -> rustyline::Result<()> {
+    // show help manual
+    tracing::info!("{}", command::help_text(schema));
+
+    let mut editor = Editor::<complete::CommandHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(complete::CommandHelper::new(schema)));
+    loop {
+        let input = match editor.readline("\x1b[1m\x1b[32m> \x1b[0m") {
+            Ok(input) => input,
+            // ctrl-c drops the current line
+            Err(ReadlineError::Interrupted) => continue,
+            // end of input, e.g. ctrl-d or a closed pipe
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+        match command::parse(&input) {
+            Ok(None) => {}
+            Ok(Some(command::Command::Exit)) => break,
+            Ok(Some(command)) => {
+                editor.add_history_entry(input.as_str())?;
+                if let Err(err) =
+                    command::execute(ctx, schema, &command, max_gap, printer.as_mut()).await
+                {
+                    tracing::error!("{err}");
                 }
             }
+            Err(err) => tracing::warn!("{err}"),
         }
     }
 
-    ctx.disconnect().await?;
-
     Ok(())
 }
+
+/// Print the validation result of `schema`, returning the process exit code.
+fn check_schema(path: &str, schema: &RegisterSchema) -> i32 {
+    match schema.validate() {
+        Ok(()) => {
+            println!("{path}: ok");
+            0
+        }
+        Err(errors) => {
+            println!("{path}: {} problem(s)", errors.len());
+            for error in errors {
+                println!("  {error}");
+            }
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use modbus_register_schema::*;
+
+    /// A description of `value` called `name` at `address`, readable and
+    /// writable and without simulation or rules.
+    pub fn description(
+        name: &str,
+        address: u16,
+        count: u16,
+        value: RegisterValueType,
+    ) -> RegisterDescription {
+        RegisterDescription {
+            name: name.to_string(),
+            address,
+            count,
+            value,
+            simulation: None,
+            access: Access::default(),
+            written: false,
+            rules: vec![],
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use clap::ValueEnum;
+
+use modbus_register_schema::*;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Format of the records printed to stdout by reads, the log lines move to
+/// stderr.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// one json object per line
+    Json,
+    /// comma separated values with a header line
+    Csv,
+    /// aligned columns per command
+    Table,
+}
+
+/// What was raw on the wire: bits of coils and discrete inputs, registers
+/// otherwise.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Raw {
+    Bits(Vec<bool>),
+    Registers(Vec<u16>),
+}
+
+/// One decoded register description.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReadRecord {
+    pub name: String,
+    pub table: RegisterTable,
+    pub address: u16,
+    pub count: u16,
+    pub endianness: Option<Endianness>,
+    /// the engineering value for scaled numbers
+    pub value: Value,
+    pub unit: Option<String>,
+    /// name of an enum value
+    pub label: Option<String>,
+    /// names of the flags set
+    pub flags: Vec<String>,
+    pub raw: Raw,
+}
+
+impl ReadRecord {
+    pub fn new(desc: &RegisterDescription, table: RegisterTable, raw: Raw) -> Self {
+        Self {
+            name: desc.name.clone(),
+            table,
+            address: desc.address,
+            count: desc.count,
+            endianness: None,
+            value: Value::Null,
+            unit: None,
+            label: None,
+            flags: vec![],
+            raw,
+        }
+    }
+
+    /// Store a formatted number, as a json number unless it is not one like
+    /// `NaN`.
+    pub fn set_number(&mut self, text: String) {
+        self.value = match text.parse::<serde_json::Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::String(text),
+        };
+    }
+
+    fn value_text(&self) -> String {
+        match &self.value {
+            Value::String(text) => text.clone(),
+            Value::Null => String::new(),
+            value => value.to_string(),
+        }
+    }
+
+    /// The value with its unit, enum label and flags, e.g. `21.5 °C` or
+    /// `1 (black)`, bits as `0110`.
+    pub fn display(&self) -> String {
+        let mut text = match &self.raw {
+            Raw::Bits(bits) => bits
+                .iter()
+                .map(|bit| if *bit { '1' } else { '0' })
+                .collect(),
+            Raw::Registers(_) => self.value_text(),
+        };
+        if let Some(unit) = &self.unit {
+            text.push_str(&format!(" {unit}"));
+        }
+        if let Some(label) = &self.label {
+            text.push_str(&format!(" ({label})"));
+        }
+        if !self.flags.is_empty() {
+            text.push_str(&format!(" [{}]", self.flags.join("|")));
+        }
+        text
+    }
+
+    fn columns(&self) -> [String; 10] {
+        let value = self.value_text();
+        let raw = match &self.raw {
+            Raw::Bits(bits) => bits
+                .iter()
+                .map(|bit| if *bit { "1" } else { "0" })
+                .collect::<Vec<&str>>()
+                .join(" "),
+            Raw::Registers(registers) => registers
+                .iter()
+                .map(|register| register.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
+        };
+        [
+            self.name.clone(),
+            self.table.to_string(),
+            self.address.to_string(),
+            self.count.to_string(),
+            // the schema spelling, ABCD rather than Abcd
+            serde_json::to_value(self.endianness)
+                .ok()
+                .and_then(|endianness| endianness.as_str().map(String::from))
+                .unwrap_or_default(),
+            value,
+            self.unit.clone().unwrap_or_default(),
+            self.label.clone().unwrap_or_default(),
+            self.flags.join("|"),
+            raw,
+        ]
+    }
+}
+
+const HEADER: [&str; 10] = [
+    "name",
+    "table",
+    "address",
+    "count",
+    "endianness",
+    "value",
+    "unit",
+    "label",
+    "flags",
+    "raw",
+];
+
+/// Prints the records of every read command in the selected format.
+pub struct Printer {
+    format: OutputFormat,
+    csv_header: bool,
+}
+
+impl Printer {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            csv_header: false,
+        }
+    }
+
+    /// Print the records of one command.
+    pub fn print(&mut self, records: &[ReadRecord]) {
+        match self.format {
+            OutputFormat::Json => {
+                for record in records {
+                    println!("{}", serde_json::to_string(record).unwrap());
+                }
+            }
+            OutputFormat::Csv => {
+                // one header for the whole run keeps the output one csv file
+                if !self.csv_header {
+                    println!("{}", HEADER.join(","));
+                    self.csv_header = true;
+                }
+                for record in records {
+                    let columns = record.columns().map(|column| csv_field(&column));
+                    println!("{}", columns.join(","));
+                }
+            }
+            OutputFormat::Table => {
+                let rows = records
+                    .iter()
+                    .map(|record| record.columns())
+                    .collect::<Vec<[String; 10]>>();
+                let mut widths = HEADER.map(|column| column.chars().count());
+                for row in &rows {
+                    for (width, column) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(column.chars().count());
+                    }
+                }
+                println!("{}", table_row(&HEADER.map(String::from), &widths));
+                for row in &rows {
+                    println!("{}", table_row(row, &widths));
+                }
+            }
+        }
+    }
+}
+
+pub fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn table_row(columns: &[String; 10], widths: &[usize; 10]) -> String {
+    columns
+        .iter()
+        .zip(widths)
+        .map(|(column, width)| format!("{column:<width$}"))
+        .collect::<Vec<String>>()
+        .join("  ")
+        .trim_end()
+        .to_string()
+}
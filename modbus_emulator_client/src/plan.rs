@@ -0,0 +1,167 @@
+use modbus_register_schema::*;
+
+/// Most coils or discrete inputs one FC01 or FC02 request may read.
+pub const MAX_READ_BITS: u16 = 2000;
+
+/// Most registers one FC03 or FC04 request may read.
+pub const MAX_READ_REGISTERS: u16 = 125;
+
+/// One read request covering the descriptions of `members`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub table: RegisterTable,
+    pub address: u16,
+    pub count: u16,
+    /// indexes into the planned registers
+    pub members: Vec<usize>,
+}
+
+/// Coalesce `registers` into as few read requests as possible. Descriptions
+/// of the same table share a request when at most `max_gap` unused addresses
+/// lie between them and the request stays within the limits of its function
+/// code.
+pub fn plan(registers: &[(RegisterTable, &RegisterDescription)], max_gap: u16) -> Vec<Block> {
+    let mut blocks = vec![];
+    for table in RegisterTable::ALL {
+        let limit = match table {
+            RegisterTable::Coils | RegisterTable::DiscreteInputs => MAX_READ_BITS,
+            RegisterTable::InputRegisters | RegisterTable::HoldingRegisters => MAX_READ_REGISTERS,
+        };
+        let mut indexes = (0..registers.len())
+            .filter(|&i| registers[i].0 == table)
+            .collect::<Vec<usize>>();
+        indexes.sort_by_key(|&i| registers[i].1.address);
+
+        let mut current: Option<Block> = None;
+        for i in indexes {
+            let desc = registers[i].1;
+            let start = desc.address as u32;
+            let end = start + desc.span() as u32;
+            if let Some(block) = &mut current {
+                let block_end = block.address as u32 + block.count as u32;
+                if start <= block_end + max_gap as u32
+                    && end.max(block_end) - block.address as u32 <= limit as u32
+                {
+                    // overlapping descriptions keep the block end
+                    block.count = (end.max(block_end) - block.address as u32) as u16;
+                    block.members.push(i);
+                    continue;
+                }
+            }
+            if let Some(block) = current.take() {
+                blocks.push(block);
+            }
+            current = Some(Block {
+                table,
+                address: desc.address,
+                count: desc.span(),
+                members: vec![i],
+            });
+        }
+        blocks.extend(current);
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::description;
+
+    fn register(address: u16, count: u16) -> RegisterDescription {
+        description(
+            &format!("h_{address}"),
+            address,
+            count,
+            RegisterValueType::U16(NumericConstraints::default()),
+        )
+    }
+
+    fn coils(address: u16, max_bits: u16) -> RegisterDescription {
+        description(
+            &format!("c_{address}"),
+            address,
+            max_bits.div_ceil(16),
+            RegisterValueType::Coils(BooleanConstraints::new(max_bits)),
+        )
+    }
+
+    fn holding(descs: &[RegisterDescription]) -> Vec<(RegisterTable, &RegisterDescription)> {
+        descs
+            .iter()
+            .map(|desc| (RegisterTable::HoldingRegisters, desc))
+            .collect()
+    }
+
+    #[test]
+    fn adjacent_registers_share_a_block() {
+        let descs = [register(0, 2), register(2, 1), register(3, 4)];
+        assert_eq!(
+            plan(&holding(&descs), 0),
+            [Block {
+                table: RegisterTable::HoldingRegisters,
+                address: 0,
+                count: 7,
+                members: vec![0, 1, 2],
+            }]
+        );
+    }
+
+    #[test]
+    fn gaps_split_blocks_unless_allowed() {
+        let descs = [register(0, 2), register(5, 1)];
+        let blocks = plan(&holding(&descs), 2);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[1].address, blocks[1].count), (5, 1));
+
+        let blocks = plan(&holding(&descs), 3);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].address, blocks[0].count), (0, 6));
+    }
+
+    #[test]
+    fn members_are_sorted_by_address() {
+        let descs = [register(10, 1), register(0, 1), register(5, 1)];
+        let blocks = plan(&holding(&descs), 10);
+        assert_eq!(blocks[0].members, [1, 2, 0]);
+    }
+
+    #[test]
+    fn overlapping_registers_keep_the_block_end() {
+        let descs = [register(0, 4), register(1, 1)];
+        let blocks = plan(&holding(&descs), 0);
+        assert_eq!((blocks[0].address, blocks[0].count), (0, 4));
+        assert_eq!(blocks[0].members, [0, 1]);
+    }
+
+    #[test]
+    fn blocks_stay_within_the_function_code_limits() {
+        let descs = [register(0, 100), register(100, 25), register(125, 1)];
+        let blocks = plan(&holding(&descs), 0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].count, MAX_READ_REGISTERS);
+        assert_eq!(blocks[1].address, 125);
+
+        let descs = [coils(0, 1000), coils(1000, 1000), coils(2000, 8)];
+        let registers = descs
+            .iter()
+            .map(|desc| (RegisterTable::Coils, desc))
+            .collect::<Vec<_>>();
+        let blocks = plan(&registers, 0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].count, MAX_READ_BITS);
+    }
+
+    #[test]
+    fn tables_never_share_a_block() {
+        let descs = [register(0, 1), register(1, 1)];
+        let registers = vec![
+            (RegisterTable::HoldingRegisters, &descs[0]),
+            (RegisterTable::InputRegisters, &descs[1]),
+        ];
+        let blocks = plan(&registers, 10);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].table, RegisterTable::InputRegisters);
+        assert_eq!(blocks[0].members, [1]);
+    }
+}
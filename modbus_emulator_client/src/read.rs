@@ -1,5 +1,7 @@
+use std::collections::VecDeque;
+
 use modbus_register_schema::{
-    types::{u16_flags::*, u32_flags::*, u64_flags::*},
+    types::{u16_flags::*, u32_flags::*, u64_flags::*, ValueFlags},
     *,
 };
 
@@ -7,140 +9,343 @@ use tokio_modbus::prelude::*;
 
 use tracing;
 
+use crate::{
+    output::{Raw, ReadRecord},
+    plan,
+};
+
+/// Read one register description from `table` and decode it.
 pub async fn read_register(
     ctx: &mut tokio_modbus::client::Context,
     desc: &RegisterDescription,
-    is_input_register: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    match &desc.value {
+    table: RegisterTable,
+) -> Result<ReadRecord, Box<dyn std::error::Error>> {
+    let raw = fetch_register(ctx, desc, table).await?;
+    Ok(decode_record(desc, table, raw))
+}
+
+/// Read the bits or registers of one register description from `table`.
+pub async fn fetch_register(
+    ctx: &mut tokio_modbus::client::Context,
+    desc: &RegisterDescription,
+    table: RegisterTable,
+) -> Result<Raw, Box<dyn std::error::Error>> {
+    let raw = match &desc.value {
         RegisterValueType::Coils(constraints) => {
-            let resp = ctx.read_coils(desc.address, constraints.max_bits).await??;
+            Raw::Bits(ctx.read_coils(desc.address, constraints.max_bits).await??)
+        }
+        RegisterValueType::Discrete(constraints) => Raw::Bits(
+            ctx.read_discrete_inputs(desc.address, constraints.max_bits)
+                .await??,
+        ),
+        _ => Raw::Registers(if table == RegisterTable::InputRegisters {
+            ctx.read_input_registers(desc.address, desc.count).await??
+        } else {
+            ctx.read_holding_registers(desc.address, desc.count)
+                .await??
+        }),
+    };
+
+    Ok(raw)
+}
+
+/// Read `registers` with the requests of [plan::plan], the results are in
+/// the order of `registers`. A block the device rejects, e.g. for spanning
+/// unmapped addresses, is read again as runs of adjacent descriptions, then
+/// one description at a time.
+pub async fn fetch_registers(
+    ctx: &mut tokio_modbus::client::Context,
+    registers: &[(RegisterTable, &RegisterDescription)],
+    max_gap: u16,
+) -> Vec<Result<Raw, Box<dyn std::error::Error>>> {
+    let mut results = registers.iter().map(|_| None).collect::<Vec<_>>();
+    let mut blocks = plan::plan(registers, max_gap)
+        .into_iter()
+        .map(|block| (block, max_gap))
+        .collect::<VecDeque<_>>();
+    while let Some((block, gap)) = blocks.pop_front() {
+        if block.members.len() > 1 {
+            match fetch_block(ctx, &block).await {
+                Ok(raw) => {
+                    tracing::debug!(
+                        "read {} [{}, {}) for {} registers",
+                        block.table,
+                        block.address,
+                        block.address as u32 + block.count as u32,
+                        block.members.len()
+                    );
+                    for &i in &block.members {
+                        let desc = registers[i].1;
+                        let start = (desc.address - block.address) as usize;
+                        let end = start + desc.span() as usize;
+                        results[i] = Some(Ok(match &raw {
+                            Raw::Bits(bits) => Raw::Bits(bits[start..end].to_vec()),
+                            Raw::Registers(words) => Raw::Registers(words[start..end].to_vec()),
+                        }));
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    tracing::debug!(
+                        "read {} [{}, {}) failed: {err}",
+                        block.table,
+                        block.address,
+                        block.address as u32 + block.count as u32
+                    );
+                    if gap > 0 {
+                        let members = block
+                            .members
+                            .iter()
+                            .map(|&i| registers[i])
+                            .collect::<Vec<_>>();
+                        for mut run in plan::plan(&members, 0) {
+                            run.members = run.members.iter().map(|&j| block.members[j]).collect();
+                            blocks.push_back((run, 0));
+                        }
+                        continue;
+                    }
+                }
+            }
+        }
+        for &i in &block.members {
+            let (table, desc) = registers[i];
+            results[i] = Some(fetch_register(ctx, desc, table).await);
+        }
+    }
+    // every index is the member of one block
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+async fn fetch_block(
+    ctx: &mut tokio_modbus::client::Context,
+    block: &plan::Block,
+) -> Result<Raw, Box<dyn std::error::Error>> {
+    let raw = match block.table {
+        RegisterTable::Coils => Raw::Bits(ctx.read_coils(block.address, block.count).await??),
+        RegisterTable::DiscreteInputs => Raw::Bits(
+            ctx.read_discrete_inputs(block.address, block.count)
+                .await??,
+        ),
+        RegisterTable::InputRegisters => Raw::Registers(
+            ctx.read_input_registers(block.address, block.count)
+                .await??,
+        ),
+        RegisterTable::HoldingRegisters => Raw::Registers(
+            ctx.read_holding_registers(block.address, block.count)
+                .await??,
+        ),
+    };
+    let read = match &raw {
+        Raw::Bits(bits) => bits.len(),
+        Raw::Registers(words) => words.len(),
+    };
+    // the members are sliced out of the response
+    if read < block.count as usize {
+        return Err(format!("short read, {read} of {}", block.count).into());
+    }
+
+    Ok(raw)
+}
+
+/// Decode and log what [fetch_register] read.
+pub fn decode_record(desc: &RegisterDescription, table: RegisterTable, raw: Raw) -> ReadRecord {
+    match raw {
+        Raw::Bits(bits) => bits_record(desc, table, bits),
+        Raw::Registers(resp) => decode_registers(desc, table, &resp),
+    }
+}
+
+fn bits_record(desc: &RegisterDescription, table: RegisterTable, bits: Vec<bool>) -> ReadRecord {
+    if let RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) =
+        &desc.value
+    {
+        tracing::info!(
+            "read(name: {}, addr: {}, count: {}) -> {:?} (default: {:?})",
+            desc.name,
+            desc.address,
+            desc.count,
+            bits,
+            constraints.val,
+        );
+    }
+    let mut record = ReadRecord::new(desc, table, Raw::Bits(bits.clone()));
+    record.value = serde_json::json!(bits);
+    record
+}
+
+/// Decode and log the registers of one register description.
+pub fn decode_registers(
+    desc: &RegisterDescription,
+    table: RegisterTable,
+    resp: &[u16],
+) -> ReadRecord {
+    let mut record = ReadRecord::new(desc, table, Raw::Registers(resp.to_vec()));
+    match &desc.value {
+        RegisterValueType::Coils(_constraints) | RegisterValueType::Discrete(_constraints) => {}
+        RegisterValueType::U8(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            // the value is the low byte of the register
+            let v = bytes[1];
             tracing::info!(
-                "read(name: {}, addr: {}, count: {}) -> {:?} (default: {:?})",
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                resp,
-                constraints.val,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
-        RegisterValueType::Discrete(constraints) => {
-            let resp = ctx
-                .read_discrete_inputs(desc.address, constraints.max_bits)
-                .await??;
+        RegisterValueType::U16(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u16::from_be_bytes([bytes[0], bytes[1]]);
             tracing::info!(
-                "read(name: {}, addr: {}, count: {}) -> {:?} (default: {:?})",
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                resp,
-                constraints.val,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
-        RegisterValueType::U8(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 1).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 1).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
+        RegisterValueType::U32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                bytes[0],
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
-        RegisterValueType::U16(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 1).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 1).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u16::from_be_bytes([bytes[0], bytes[1]])
-            } else {
-                u16::from_le_bytes([bytes[0], bytes[1]])
-            };
+        RegisterValueType::U64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
-        RegisterValueType::U32(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 2).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 2).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            } else {
-                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            };
+        RegisterValueType::I16(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = i16::from_be_bytes([bytes[0], bytes[1]]);
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
-        RegisterValueType::U64(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 4).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 4).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u64::from_be_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
-            } else {
-                u64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
-            };
+        RegisterValueType::I32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                resp
+            );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
+        }
+        RegisterValueType::I64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = i64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
+        }
+        RegisterValueType::F32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                resp
+            );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
+        }
+        RegisterValueType::F64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = f64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                resp
+            );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(constraints.format_number(v));
+            record.unit = constraints.unit.clone();
         }
         RegisterValueType::U16Flags(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 1).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 1).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u16::from_be_bytes([bytes[0], bytes[1]])
-            } else {
-                u16::from_le_bytes([bytes[0], bytes[1]])
-            };
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u16::from_be_bytes([bytes[0], bytes[1]]);
             let vf = U16ValueFlags::from_u16(v, constraints.flag_names.len() as u8);
             let mut flags = String::new();
             for (i, flag_name) in constraints.flag_names.iter().enumerate() {
@@ -154,25 +359,19 @@ pub async fn read_register(
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 flags,
                 vf.value,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(vf.value.to_string());
+            record.flags = set_flags(&constraints.flag_names, vf.flag_bits());
         }
         RegisterValueType::U32Flags(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 2).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 2).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            } else {
-                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            };
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
             let vf = U32ValueFlags::from_u32(v, constraints.flag_names.len() as u8);
             let mut flags = String::new();
             for (i, flag_name) in constraints.flag_names.iter().enumerate() {
@@ -186,29 +385,21 @@ pub async fn read_register(
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 flags,
                 vf.value,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(vf.value.to_string());
+            record.flags = set_flags(&constraints.flag_names, vf.flag_bits());
         }
         RegisterValueType::U64Flags(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 4).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 4).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u64::from_be_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
-            } else {
-                u64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
-            };
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
             let vf = U64ValueFlags::from_u64(v, constraints.flag_names.len() as u8);
             let mut flags = String::new();
             for (i, flag_name) in constraints.flag_names.iter().enumerate() {
@@ -222,66 +413,52 @@ pub async fn read_register(
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 flags,
                 vf.value,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(vf.value.to_string());
+            record.flags = set_flags(&constraints.flag_names, vf.flag_bits());
         }
         RegisterValueType::Bytes(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, desc.count * 2)
-                    .await??
-            } else {
-                ctx.read_holding_registers(desc.address, desc.count * 2)
-                    .await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            let bytes = deserialize_registers(resp, endianness);
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 bytes,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.value = serde_json::json!(bytes);
         }
         RegisterValueType::String(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, desc.count * 2)
-                    .await??
-            } else {
-                ctx.read_holding_registers(desc.address, desc.count * 2)
-                    .await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            let bytes = deserialize_registers(resp, endianness);
+            let text = String::from_utf8_lossy(&bytes).to_string();
             tracing::info!(
                 "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                String::from_utf8(bytes).unwrap(),
+                constraints.endianness.unwrap_or_default(),
+                text,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            // strings shorter than the registers are padded with nul
+            record.value = serde_json::Value::String(text.trim_end_matches('\0').to_string());
         }
 
         RegisterValueType::Enum(constraints) => {
-            let resp = if is_input_register {
-                ctx.read_input_registers(desc.address, 2).await??
-            } else {
-                ctx.read_holding_registers(desc.address, 2).await??
-            };
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = deserialize_registers(&resp, is_big_endian);
-            let v = if is_big_endian {
-                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            } else {
-                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-            };
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(resp, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
             let name = constraints
                 .kv
                 .iter()
@@ -291,13 +468,107 @@ pub async fn read_register(
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 v,
                 name,
                 resp
             );
+            record.endianness = Some(constraints.endianness.unwrap_or_default());
+            record.set_number(v.to_string());
+            record.label = name.cloned();
+        }
+    }
+
+    record
+}
+
+/// Names of the flags set in `flag_bits`.
+fn set_flags(flag_names: &[String], flag_bits: u64) -> Vec<String> {
+    flag_names
+        .iter()
+        .enumerate()
+        .filter(|(i, _name)| flag_bits & (1 << i) != 0)
+        .map(|(_i, name)| name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::description;
+
+    fn decoded(value: RegisterValueType, count: u16, resp: &[u16]) -> serde_json::Value {
+        let desc = description("signed", 0, count, value);
+        decode_registers(&desc, RegisterTable::HoldingRegisters, resp).value
+    }
+
+    fn signed<T: Default>(endianness: Endianness) -> NumericConstraints<T> {
+        NumericConstraints {
+            endianness: Some(endianness),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn signed_values_are_twos_complement() {
+        assert_eq!(
+            decoded(RegisterValueType::I16(Default::default()), 1, &[0xFFFE]),
+            serde_json::json!(-2)
+        );
+        assert_eq!(
+            decoded(
+                RegisterValueType::I32(signed(Endianness::Big)),
+                2,
+                &[0x8000, 0x0000]
+            ),
+            serde_json::json!(i32::MIN)
+        );
+        assert_eq!(
+            decoded(
+                RegisterValueType::I64(Default::default()),
+                4,
+                &[0xFFFE, 0xFFFF, 0xFFFF, 0xFFFF]
+            ),
+            serde_json::json!(-2)
+        );
+    }
+
+    #[test]
+    fn signed_values_follow_the_layout() {
+        for (endianness, registers) in [
+            (Endianness::Abcd, [0xFFFE, 0xEE90]),
+            (Endianness::Little, [0xEE90, 0xFFFE]),
+            (Endianness::Dcba, [0x90EE, 0xFEFF]),
+            (Endianness::Badc, [0xFEFF, 0x90EE]),
+        ] {
+            assert_eq!(
+                decoded(RegisterValueType::I32(signed(endianness)), 2, &registers),
+                serde_json::json!(-70000),
+                "{endianness:?}"
+            );
         }
     }
 
-    Ok(())
+    #[test]
+    fn scaled_signed_values_are_engineering_values() {
+        let constraints = NumericConstraints::<i16> {
+            scale: Some(0.1),
+            ..Default::default()
+        };
+        assert_eq!(
+            decoded(RegisterValueType::I16(constraints), 1, &[0xFF9C]),
+            serde_json::json!(-10)
+        );
+    }
+
+    #[test]
+    fn records_report_the_default_endianness_when_none_is_given() {
+        let desc = description("plain", 0, 2, RegisterValueType::U32(Default::default()));
+        let record = decode_registers(&desc, RegisterTable::HoldingRegisters, &[1, 0]);
+        assert_eq!(record.endianness, Some(Endianness::default()));
+
+        let desc = description("big", 0, 2, RegisterValueType::U32(signed(Endianness::Big)));
+        let record = decode_registers(&desc, RegisterTable::HoldingRegisters, &[0, 1]);
+        assert_eq!(record.endianness, Some(Endianness::Big));
+    }
 }
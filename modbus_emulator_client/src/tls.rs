@@ -0,0 +1,77 @@
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+
+use tokio::net::TcpStream;
+
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+/// Client side of modbus/tcp security: the server certificate must be issued
+/// by a certificate authority of `ca`, `identity` is the certificate chain
+/// and key presented to the server. All files are PEM.
+pub fn client_config(ca: &str, identity: Option<(&str, &str)>) -> io::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for ca_cert in load_certs(ca)? {
+        roots
+            .add(ca_cert)
+            .map_err(|err| invalid_data(format!("{ca}: {err}")))?;
+    }
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|err| invalid_data(format!("{cert}: {err}")))?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Connect to `socket_addr` and run the handshake, the server certificate
+/// must be valid for `server_name`, the ip address of `socket_addr` if
+/// unset.
+pub async fn connect(
+    config: Arc<ClientConfig>,
+    socket_addr: SocketAddr,
+    server_name: Option<&str>,
+) -> io::Result<TlsStream<TcpStream>> {
+    let server_name = match server_name {
+        Some(name) => ServerName::try_from(name.to_string())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        None => ServerName::from(socket_addr.ip()),
+    };
+    let stream = TcpStream::connect(socket_addr).await?;
+    TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await
+}
+
+fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).map_err(|err| in_file(path, err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|err| in_file(path, err))?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!("{path}: no certificate found")));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).map_err(|err| in_file(path, err))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| in_file(path, err))?
+        .ok_or(invalid_data(format!("{path}: no private key found")))
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn in_file(path: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{path}: {err}"))
+}
@@ -0,0 +1,134 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UdpSocket,
+    time::Sleep,
+};
+
+use tracing;
+
+/// Time to wait for a response before sending the request again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Requests sent again before a read gives up.
+const MAX_RETRIES: u32 = 3;
+
+/// Carries the mbap frames of the tcp client as datagrams, one frame per
+/// flush. Lost datagrams are sent again, responses to other transactions
+/// than the last request are dropped.
+#[derive(Debug)]
+pub struct UdpLine {
+    socket: UdpSocket,
+    /// mbap bytes written since the last flush
+    pending: Vec<u8>,
+    /// the last request sent, empty once its response arrived
+    request: Vec<u8>,
+    /// response bytes not yet read
+    received: Vec<u8>,
+    retry: Option<Pin<Box<Sleep>>>,
+    retries: u32,
+}
+
+impl UdpLine {
+    pub async fn connect(socket_addr: SocketAddr) -> io::Result<Self> {
+        let local_addr: SocketAddr = if socket_addr.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(local_addr).await?;
+        socket.connect(socket_addr).await?;
+        Ok(Self {
+            socket,
+            pending: vec![],
+            request: vec![],
+            received: vec![],
+            retry: None,
+            retries: 0,
+        })
+    }
+}
+
+impl AsyncRead for UdpLine {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.received.is_empty() {
+            let mut datagram = [0u8; 260];
+            let mut datagram = ReadBuf::new(&mut datagram);
+            match this.socket.poll_recv(cx, &mut datagram) {
+                Poll::Ready(result) => {
+                    result?;
+                    let datagram = datagram.filled();
+                    // a late response to a request sent again
+                    if this.request.len() < 2 || datagram.get(..2) != Some(&this.request[..2]) {
+                        tracing::warn!("dropped stray datagram {:02x?}", datagram);
+                        continue;
+                    }
+                    this.received.extend_from_slice(datagram);
+                    this.request.clear();
+                    this.retry = None;
+                }
+                Poll::Pending => {
+                    if this.request.is_empty() {
+                        return Poll::Pending;
+                    }
+                    let retry = this
+                        .retry
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(RETRY_INTERVAL)));
+                    ready!(retry.as_mut().poll(cx));
+                    if this.retries >= MAX_RETRIES {
+                        this.request.clear();
+                        this.retry = None;
+                        return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+                    }
+                    this.retries += 1;
+                    tracing::warn!("no response, sending the request again");
+                    this.socket.try_send(&this.request)?;
+                    this.retry = None;
+                }
+            }
+        }
+        let n = this.received.len().min(buf.remaining());
+        buf.put_slice(&this.received[..n]);
+        this.received.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UdpLine {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().pending.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.pending.is_empty() {
+            ready!(this.socket.poll_send(cx, &this.pending))?;
+            this.request = std::mem::take(&mut this.pending);
+            this.retries = 0;
+            this.retry = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
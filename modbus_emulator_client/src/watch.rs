@@ -0,0 +1,155 @@
+use std::{fs::OpenOptions, io::Write, time::Duration};
+
+use modbus_register_schema::*;
+
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+
+use tokio::time::MissedTickBehavior;
+
+use tracing;
+
+use crate::{
+    output::{csv_field, Printer},
+    read,
+};
+
+/// How a `watch` command polls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchOptions {
+    pub interval: Duration,
+    /// append the changes to this csv file
+    pub csv: Option<String>,
+    /// stop after this many polls, ctrl-c otherwise
+    pub count: Option<usize>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            csv: None,
+            count: None,
+        }
+    }
+}
+
+/// Parse `500ms`, `2s`, `1m` or a plain number of milliseconds.
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let (number, scale) = if let Some(number) = text.strip_suffix("ms") {
+        (number, 1)
+    } else if let Some(number) = text.strip_suffix('s') {
+        (number, 1_000)
+    } else if let Some(number) = text.strip_suffix('m') {
+        (number, 60_000)
+    } else {
+        (text, 1)
+    };
+    let millis = number
+        .parse::<u64>()
+        .map_err(|err| format!("invalid interval {text}: {err}"))?;
+    if millis == 0 {
+        return Err(format!("invalid interval {text}: must not be zero"));
+    }
+    Ok(Duration::from_millis(millis * scale))
+}
+
+/// Re-read `registers` every interval and report the values that changed
+/// since the previous poll, the first poll reports all of them. Runs until
+/// ctrl-c or the poll count of `options`.
+pub async fn watch(
+    ctx: &mut tokio_modbus::client::Context,
+    registers: &[(RegisterTable, &RegisterDescription)],
+    options: &WatchOptions,
+    max_gap: u16,
+    mut printer: Option<&mut Printer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv = match &options.csv {
+        Some(path) => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "timestamp,name,table,address,old,new")?;
+            }
+            Some(file)
+        }
+        None => None,
+    };
+
+    tracing::info!(
+        "watching {} register(s) every {:?}, ctrl-c to stop",
+        registers.len(),
+        options.interval
+    );
+    let mut last: Vec<Option<String>> = vec![None; registers.len()];
+    let mut ticker = tokio::time::interval(options.interval);
+    // a slow device stretches the interval instead of bursting reads
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let stop = tokio::signal::ctrl_c();
+    tokio::pin!(stop);
+    let mut polls = 0;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut stop => break,
+        }
+
+        let timestamp = now();
+        let mut changed = vec![];
+        let raws = read::fetch_registers(ctx, registers, max_gap).await;
+        for (i, ((table, desc), raw)) in registers.iter().zip(raws).enumerate() {
+            let raw = match raw {
+                Ok(raw) => raw,
+                Err(err) => {
+                    tracing::warn!("read {} failed: {err}", desc.name);
+                    continue;
+                }
+            };
+            // the per read log would drown the changes
+            let record = tracing::subscriber::with_default(
+                tracing::subscriber::NoSubscriber::default(),
+                || read::decode_record(desc, *table, raw),
+            );
+            let new = record.display();
+            if last[i].as_ref() == Some(&new) {
+                continue;
+            }
+            let old = last[i].replace(new.clone());
+            match &old {
+                Some(old) => tracing::info!("{}: {old} → {new}", desc.name),
+                None => tracing::info!("{}: {new}", desc.name),
+            }
+            if let Some(file) = &mut csv {
+                writeln!(
+                    file,
+                    "{timestamp},{},{table},{},{},{}",
+                    csv_field(&desc.name),
+                    desc.address,
+                    csv_field(&old.unwrap_or_default()),
+                    csv_field(&new)
+                )?;
+            }
+            changed.push(record);
+        }
+        if let Some(printer) = printer.as_mut() {
+            if !changed.is_empty() {
+                printer.print(&changed);
+            }
+        }
+
+        polls += 1;
+        if options.count.is_some_and(|count| polls >= count) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Local time in the format of the log lines.
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .to_offset(UtcOffset::from_hms(8, 0, 0).unwrap())
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"
+        ))
+        .unwrap_or_default()
+}
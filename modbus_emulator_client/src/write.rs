@@ -7,17 +7,20 @@ use tokio_modbus::prelude::*;
 
 use tracing;
 
+use crate::output::ReadRecord;
+use crate::read::decode_registers;
+
 pub async fn write_register(
     ctx: &mut tokio_modbus::client::Context,
     desc: &RegisterDescription,
-    params: Vec<&str>,
+    values: &[&str],
 ) -> Result<(), Box<dyn std::error::Error>> {
     match &desc.value {
         RegisterValueType::Coils(_constraints) => {
-            let values = params[3..]
+            let values = values
                 .iter()
-                .map(|s| s.parse::<u8>().unwrap() != 0)
-                .collect::<Vec<bool>>();
+                .map(|s| s.parse::<u8>().map(|v| v != 0))
+                .collect::<Result<Vec<bool>, _>>()?;
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}) -> {:?}",
                 desc.name,
@@ -25,357 +28,446 @@ pub async fn write_register(
                 desc.count,
                 values
             );
-            let _ = ctx.write_multiple_coils(desc.address, &values).await?;
+            ctx.write_multiple_coils(desc.address, &values).await??;
         }
         RegisterValueType::Discrete(_constraints) => {}
-        RegisterValueType::U8(constraints) => {
-            let v = params[3].parse::<u16>()?;
-            if v > u8::MAX.into() || !validate(v as u8, &constraints) {
-                return Err("v > u8::MAX.into() || validate(v as u8, &constraints)".into());
+        _ => {
+            let w = encode_registers(desc, values)?;
+            if w.len() == 1 {
+                ctx.write_single_register(desc.address, w[0]).await??;
+            } else {
+                ctx.write_multiple_registers(desc.address, &w).await??;
             }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse, validate and encode `values` into the registers of one register
+/// description.
+pub fn encode_registers(
+    desc: &RegisterDescription,
+    values: &[&str],
+) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    if values.is_empty() {
+        return Err("value missing".into());
+    }
+
+    match &desc.value {
+        RegisterValueType::Coils(_constraints) | RegisterValueType::Discrete(_constraints) => {
+            Err(format!("{} is not a register", desc.name).into())
+        }
+        RegisterValueType::U8(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let mut w = vec![0u16; 1];
+            serialize_registers(&[0, v], endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
-                [v]
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
             );
-            let _ = ctx.write_single_register(desc.address, v).await?;
+            Ok(w)
         }
         RegisterValueType::U16(constraints) => {
-            let v = params[3].parse::<u16>()?;
-            if !validate(v, &constraints) {
-                return Err("validate(v, &constraints)".into());
-            }
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let mut w = vec![0u16; 1];
+            serialize_registers(&v.to_be_bytes(), endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
-                [v]
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
             );
-            let _ = ctx.write_single_register(desc.address, v).await?;
+            Ok(w)
         }
         RegisterValueType::U32(constraints) => {
-            let v = params[3].parse::<u32>()?;
-            if !validate(v, &constraints) {
-                return Err("validate(v, &constraints)".into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = if is_big_endian {
-                &v.to_be_bytes()
-            } else {
-                &v.to_le_bytes()
-            };
-            let mut w = vec![
-                0u16;
-                if bytes.len() % 2 != 0 {
-                    (bytes.len() + 1) / 2
-                } else {
-                    bytes.len() / 2
-                }
-            ];
-            serialize_registers(bytes, is_big_endian, &mut w);
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
         RegisterValueType::U64(constraints) => {
-            let v = params[3].parse::<u64>()?;
-            if !validate(v, &constraints) {
-                return Err("validate(v, &constraints)".into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = if is_big_endian {
-                &v.to_be_bytes()
-            } else {
-                &v.to_le_bytes()
-            };
-            let mut w = vec![
-                0u16;
-                if bytes.len() % 2 != 0 {
-                    (bytes.len() + 1) / 2
-                } else {
-                    bytes.len() / 2
-                }
-            ];
-            serialize_registers(bytes, is_big_endian, &mut w);
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                v,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
+            );
+            Ok(w)
+        }
+        RegisterValueType::I16(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
+        }
+        RegisterValueType::I32(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
+            );
+            Ok(w)
+        }
+        RegisterValueType::I64(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
+            );
+            Ok(w)
+        }
+        RegisterValueType::F32(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
+            );
+            Ok(w)
+        }
+        RegisterValueType::F64(constraints) => {
+            let v = parse_value(constraints, values[0])?;
+            constraints.validate(&v)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                constraints.format_value(v),
+                w
+            );
+            Ok(w)
         }
         RegisterValueType::U16Flags(constraints) => {
-            let v = params[3].parse::<u16>()?;
+            let v = values[0].parse::<u16>()?;
             let vf = U16ValueFlags::from_u16(v, constraints.flag_names.len() as u8);
-            let cf = NumericConstraints {
-                val: Some(v),
-                default: constraints.default,
-                lt: constraints.lt,
-                lte: constraints.lte,
-                gt: constraints.gt,
-                gte: constraints.gte,
-                endianness: constraints.endianness.clone(),
-            };
-            if !validate(vf.value, &cf) {
-                return Err("validate(v, &constraints)".into());
-            }
+            constraints.validate(&vf)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let mut w = vec![0u16; 1];
+            serialize_registers(&v.to_be_bytes(), endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 v,
-                [v]
+                w
             );
-            let _ = ctx.write_single_register(desc.address, v).await?;
+            Ok(w)
         }
         RegisterValueType::U32Flags(constraints) => {
-            let v = params[3].parse::<u32>()?;
+            let v = values[0].parse::<u32>()?;
             let vf = U32ValueFlags::from_u32(v, constraints.flag_names.len() as u8);
-            let cf = NumericConstraints {
-                val: Some(v),
-                default: constraints.default,
-                lt: constraints.lt,
-                lte: constraints.lte,
-                gt: constraints.gt,
-                gte: constraints.gte,
-                endianness: constraints.endianness.clone(),
-            };
-            if !validate(vf.value, &cf) {
-                return Err("validate(v, &constraints)".into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = if is_big_endian {
-                &v.to_be_bytes()
-            } else {
-                &v.to_le_bytes()
-            };
-            let mut w = vec![
-                0u16;
-                if bytes.len() % 2 != 0 {
-                    (bytes.len() + 1) / 2
-                } else {
-                    bytes.len() / 2
-                }
-            ];
-            serialize_registers(bytes, is_big_endian, &mut w);
+            constraints.validate(&vf)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 v,
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
         RegisterValueType::U64Flags(constraints) => {
-            let v = params[3].parse::<u64>()?;
+            let v = values[0].parse::<u64>()?;
             let vf = U64ValueFlags::from_u64(v, constraints.flag_names.len() as u8);
-            let cf = NumericConstraints {
-                val: Some(v),
-                default: constraints.default,
-                lt: constraints.lt,
-                lte: constraints.lte,
-                gt: constraints.gt,
-                gte: constraints.gte,
-                endianness: constraints.endianness.clone(),
-            };
-            if !validate(vf.value, &cf) {
-                return Err("validate(v, &constraints)".into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = if is_big_endian {
-                &v.to_be_bytes()
-            } else {
-                &v.to_le_bytes()
-            };
-            let mut w = vec![
-                0u16;
-                if bytes.len() % 2 != 0 {
-                    (bytes.len() + 1) / 2
-                } else {
-                    bytes.len() / 2
-                }
-            ];
-            serialize_registers(bytes, is_big_endian, &mut w);
+            constraints.validate(&vf)?;
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 v,
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
         RegisterValueType::Bytes(constraints) => {
-            let values = params[3..]
+            let values = values
                 .iter()
-                .map(|s| s.parse::<u8>().unwrap())
-                .collect::<Vec<u8>>();
-            if values.len() > (desc.count * 2) as usize {
-                return Err(format!("len: {} > max_size: {}", values.len(), desc.count * 2).into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let mut w = vec![
-                0u16;
-                if values.len() % 2 != 0 {
-                    (values.len() + 1) / 2
-                } else {
-                    values.len() / 2
-                }
-            ];
-            serialize_registers(values.as_slice(), is_big_endian, &mut w);
+                .map(|s| s.parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()?;
+            constraints.validate(&values, desc.count)?;
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            // pad to the whole description so no stale tail is left behind
+            let mut w = vec![0u16; desc.count.into()];
+            serialize_registers(values.as_slice(), endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 values,
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
         RegisterValueType::String(constraints) => {
-            let values = params[3..].join("");
-            if values.as_bytes().len() > (desc.count * 2) as usize {
-                return Err(format!(
-                    "len: {} > max_size: {}",
-                    values.as_bytes().len(),
-                    desc.count * 2
-                )
-                .into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let mut w = vec![
-                0u16;
-                if values.len() % 2 != 0 {
-                    (values.len() + 1) / 2
-                } else {
-                    values.len() / 2
-                }
-            ];
-            serialize_registers(values.as_bytes(), is_big_endian, &mut w);
+            let values = values.join("");
+            constraints.validate(&values, desc.count)?;
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            // pad to the whole description so no stale tail is left behind
+            let mut w = vec![0u16; desc.count.into()];
+            serialize_registers(values.as_bytes(), endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 values,
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
         RegisterValueType::Enum(constraints) => {
-            let mut v = 0;
-            println!("{:?}", constraints.kv);
-            if let Some(i) = constraints.kv.get(params[3]) {
-                v = i.clone();
-            } else {
-                _ = v;
-                return Err(format!("{} not in {:?}", params[3], constraints.kv).into());
-            }
-            let is_big_endian = constraints.endianness == Some(Endianness::Big);
-            let bytes = if is_big_endian {
-                &v.to_be_bytes()
-            } else {
-                &v.to_le_bytes()
+            let Some(v) = constraints.kv.get(values[0]).copied() else {
+                return Err(format!("{} not in {:?}", values[0], constraints.kv).into());
             };
-            let mut w = vec![
-                0u16;
-                if bytes.len() % 2 != 0 {
-                    (bytes.len() + 1) / 2
-                } else {
-                    bytes.len() / 2
-                }
-            ];
-            serialize_registers(bytes, is_big_endian, &mut w);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = v.to_be_bytes();
+            let mut w = vec![0u16; bytes.len().div_ceil(2)];
+            serialize_registers(&bytes, endianness, &mut w);
             tracing::info!(
                 "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} = {} (raw: {:?})",
                 desc.name,
                 desc.address,
                 desc.count,
-                constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                constraints.endianness.unwrap_or_default(),
                 v,
-                params[3],
+                values[0],
                 w
             );
-            let _ = ctx.write_multiple_registers(desc.address, &w).await?;
+            Ok(w)
         }
     }
+}
+
+/// Write `values` into one holding register description and read another one
+/// back with a single read/write multiple registers request.
+pub async fn read_write_register(
+    ctx: &mut tokio_modbus::client::Context,
+    read_desc: &RegisterDescription,
+    write_desc: &RegisterDescription,
+    values: &[&str],
+) -> Result<ReadRecord, Box<dyn std::error::Error>> {
+    let w = encode_registers(write_desc, values)?;
+    let resp = ctx
+        .read_write_multiple_registers(read_desc.address, read_desc.count, write_desc.address, &w)
+        .await??;
+
+    Ok(decode_registers(
+        read_desc,
+        RegisterTable::HoldingRegisters,
+        &resp,
+    ))
+}
+
+/// Modify the first register of a holding register description with a mask
+/// write register request.
+pub async fn mask_write_register(
+    ctx: &mut tokio_modbus::client::Context,
+    desc: &RegisterDescription,
+    and_mask: u16,
+    or_mask: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!(
+        "mask_write(name: {}, addr: {}, count: {}) -> and_mask: {:#06x}, or_mask: {:#06x}",
+        desc.name,
+        desc.address,
+        desc.count,
+        and_mask,
+        or_mask
+    );
+    ctx.masked_write_register(desc.address, and_mask, or_mask)
+        .await??;
 
     Ok(())
 }
 
-fn validate<T: std::fmt::Display + std::fmt::Debug + PartialOrd>(
-    value: T,
+/// Parse a mask given as decimal or `0x` prefixed hex.
+pub fn parse_mask(text: &str) -> Result<u16, std::num::ParseIntError> {
+    match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    }
+}
+
+/// Parse a raw value, or an engineering value for descriptions with `scale`
+/// or `offset`.
+fn parse_value<T>(
     constraints: &NumericConstraints<T>,
-) -> bool {
-    if let Some(lt) = &constraints.lt {
-        if value >= *lt {
-            tracing::warn!(
-                "invalid value, v: {}, constraints: {:?}",
-                value,
-                constraints
-            );
-            return false;
-        }
+    text: &str,
+) -> Result<T, Box<dyn std::error::Error>>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display + RawValue,
+    T::Err: std::error::Error + 'static,
+{
+    if constraints.is_scaled() {
+        Ok(constraints.from_engineering(text.parse::<f64>()?)?)
+    } else {
+        Ok(text.parse::<T>()?)
     }
-    if let Some(lte) = &constraints.lte {
-        if value > *lte {
-            tracing::warn!(
-                "invalid value, v: {}, constraints: {:?}",
-                value,
-                constraints
-            );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::description;
 
-            return false;
+    fn signed<T: Default>(endianness: Option<Endianness>) -> NumericConstraints<T> {
+        NumericConstraints {
+            endianness,
+            ..Default::default()
         }
     }
-    if let Some(gt) = &constraints.gt {
-        if value <= *gt {
-            tracing::warn!(
-                "invalid value, v: {}, constraints: {:?}",
-                value,
-                constraints
+
+    #[test]
+    fn signed_values_are_twos_complement() {
+        let desc = description("offset", 0, 1, RegisterValueType::I16(signed(None)));
+        assert_eq!(encode_registers(&desc, &["-2"]).unwrap(), [0xFFFE]);
+        let desc = description(
+            "position",
+            0,
+            2,
+            RegisterValueType::I32(signed(Some(Endianness::Big))),
+        );
+        // -70000 is 0xFFFEEE90
+        assert_eq!(
+            encode_registers(&desc, &["-70000"]).unwrap(),
+            [0xFFFE, 0xEE90]
+        );
+        let desc = description("total", 0, 4, RegisterValueType::I64(signed(None)));
+        assert_eq!(
+            encode_registers(&desc, &["-2"]).unwrap(),
+            [0xFFFE, 0xFFFF, 0xFFFF, 0xFFFF]
+        );
+    }
+
+    #[test]
+    fn signed_values_follow_the_layout() {
+        for (endianness, registers) in [
+            (Endianness::Abcd, [0xFFFE, 0xEE90]),
+            (Endianness::Cdab, [0xEE90, 0xFFFE]),
+            (Endianness::Dcba, [0x90EE, 0xFEFF]),
+            (Endianness::Badc, [0xFEFF, 0x90EE]),
+        ] {
+            let desc = description(
+                "position",
+                0,
+                2,
+                RegisterValueType::I32(signed(Some(endianness))),
+            );
+            assert_eq!(
+                encode_registers(&desc, &["-70000"]).unwrap(),
+                registers,
+                "{endianness:?}"
             );
-            return false;
         }
     }
-    if let Some(gte) = &constraints.gte {
-        if value < *gte {
-            tracing::warn!(
-                "invalid value, v: {}, constraints: {:?}",
-                value,
-                constraints
-            );
 
-            return false;
-        }
+    #[test]
+    fn negative_bounds_are_checked_before_encoding() {
+        let constraints = NumericConstraints::<i16> {
+            gte: Some(-100),
+            ..Default::default()
+        };
+        let desc = description("offset", 0, 1, RegisterValueType::I16(constraints));
+        assert!(encode_registers(&desc, &["-101"]).is_err());
+        assert_eq!(encode_registers(&desc, &["-100"]).unwrap(), [0xFF9C]);
+        assert!(encode_registers(&desc, &["40000"]).is_err());
     }
-    return true;
 }
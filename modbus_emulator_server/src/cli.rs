@@ -1,17 +1,109 @@
+use std::time::Duration;
+
 use clap::Parser;
 
+use modbus_serial_line::{FlowControl, Parity, SerialParams};
+
+use crate::frame::Framing;
+
 #[derive(Debug, Parser)]
 #[command(name = "modbus emulator server")]
 pub struct Args {
-    /// windows serial - COMX, linux serial - /dev/X, tcp - host:port
+    /// windows serial - COMX, linux serial - /dev/X, tcp - host:port, udp - udp://host:port
     #[arg(long, default_value = "127.0.0.1:5052")]
     pub addr: String,
 
+    /// framing of requests and responses, rtu on a serial port and mbap on tcp by default
+    #[arg(long, value_enum)]
+    pub framing: Option<Framing>,
+
     /// serial port baud rate
     #[arg(long, default_value_t = 0)]
     pub baud_rate: u32,
 
+    /// serial data bits
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(5..=8))]
+    pub data_bits: u8,
+
+    /// serial parity
+    #[arg(long, value_enum, default_value_t = Parity::None)]
+    pub parity: Parity,
+
+    /// serial stop bits
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub stop_bits: u8,
+
+    /// serial flow control
+    #[arg(long, value_enum, default_value_t = FlowControl::None)]
+    pub flow_control: FlowControl,
+
+    /// raise RTS while sending, for rs-485 transceivers without automatic direction control
+    #[arg(long, default_value_t = false)]
+    pub rts_toggle: bool,
+
+    /// microseconds of silence kept between rtu frames, 3.5 characters by default; when set, a partial request followed by this much silence is dropped
+    #[arg(long)]
+    pub inter_frame_us: Option<u64>,
+
     /// register schema toml file
     #[arg(long, default_value = "schema.toml")]
     pub schema: String,
+
+    /// server config toml file mapping unit ids to register schema files, replaces --schema
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// zero-fill reads of unmapped addresses and ignore writes to them instead of answering IllegalDataAddress
+    #[arg(long, default_value_t = false)]
+    pub fill_holes: bool,
+
+    /// fault injection toml file, reloaded whenever it changes
+    #[arg(long)]
+    pub faults: Option<String>,
+
+    /// host:port of the http/json control endpoint reading and writing registers by name
+    #[arg(long)]
+    pub control: Option<String>,
+
+    /// state file (toml, or json by extension) the register values are restored from at startup and saved to, json when a u64 register is not bounded to i64::MAX
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// milliseconds between saves of the state file, skipped while the values are unchanged
+    #[arg(long, default_value_t = 1000)]
+    pub state_interval_ms: u64,
+
+    /// serve modbus/tcp security (port 802 style) with this PEM server certificate chain, clients must present a certificate
+    #[arg(long, requires_all = ["tls_key", "tls_ca"])]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+
+    /// PEM certificate authorities the client certificates must be issued by
+    #[arg(long, requires = "tls_cert")]
+    pub tls_ca: Option<String>,
+
+    /// role access toml file mapping the roles of client certificates to read-only or read-write access per table, every client may read and write if unset
+    #[arg(long, requires = "tls_cert")]
+    pub tls_roles: Option<String>,
+
+    /// validate the register schemas, print the problems found and exit
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+}
+
+impl Args {
+    pub fn serial_params(&self) -> SerialParams {
+        SerialParams {
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            flow_control: self.flow_control,
+            rts_toggle: self.rts_toggle,
+            inter_frame: self.inter_frame_us.map(Duration::from_micros),
+        }
+    }
 }
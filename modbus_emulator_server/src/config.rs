@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use config_file_derives::ConfigFile;
+use config_file_types;
+
+use modbus_register_schema::RegisterSchema;
+
+use serde::{Deserialize, Serialize};
+
+/// Server configuration emulating several devices behind one port.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ConfigFile)]
+#[config_file_ext("toml")]
+pub struct ServerConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units: Vec<UnitConfig>,
+
+    #[serde(skip)]
+    pub path: String,
+}
+
+/// One emulated device, `schema` is relative to the config file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UnitConfig {
+    pub unit_id: u8,
+    pub schema: String,
+}
+
+impl ServerConfig {
+    /// Check the unit ids, each must be unique and none the broadcast id 0.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut unit_ids = HashSet::new();
+        for unit in &self.units {
+            if unit.unit_id == 0 {
+                return Err("unit_id 0 is the broadcast id".to_string());
+            }
+            if !unit_ids.insert(unit.unit_id) {
+                return Err(format!("unit_id {} is configured twice", unit.unit_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the register schema of `unit`.
+    pub fn schema_path(&self, unit: &UnitConfig) -> String {
+        match Path::new(&self.path).parent() {
+            Some(dir) => dir.join(&unit.schema).to_string_lossy().to_string(),
+            None => unit.schema.clone(),
+        }
+    }
+
+    /// Load the register schema of every unit with its unit id and path.
+    pub fn load_schemas(&self) -> Result<Vec<(u8, String, RegisterSchema)>, String> {
+        self.units
+            .iter()
+            .map(|unit| {
+                let path = self.schema_path(unit);
+                match RegisterSchema::load(&path, false) {
+                    Some(schema) => Ok((unit.unit_id, path, schema)),
+                    None => Err(format!(
+                        "failed to load register schema {path} of unit_id {}",
+                        unit.unit_id
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    fn config(units: &[(u8, &str)]) -> ServerConfig {
+        ServerConfig {
+            units: units
+                .iter()
+                .map(|&(unit_id, schema)| UnitConfig {
+                    unit_id,
+                    schema: schema.to_string(),
+                })
+                .collect(),
+            path: String::new(),
+        }
+    }
+
+    #[test]
+    fn unit_ids_are_unique() {
+        assert_eq!(config(&[(1, "a.toml"), (2, "b.toml")]).validate(), Ok(()));
+        assert_eq!(
+            config(&[(1, "a.toml"), (1, "b.toml")]).validate(),
+            Err("unit_id 1 is configured twice".to_string())
+        );
+    }
+
+    #[test]
+    fn broadcast_unit_id_is_rejected() {
+        assert!(config(&[(0, "a.toml")]).validate().is_err());
+    }
+
+    #[test]
+    fn schemas_are_relative_to_the_config_file() {
+        let mut config = config(&[(1, "meter.toml")]);
+        config.path = "site/server.toml".to_string();
+        assert_eq!(config.schema_path(&config.units[0]), "site/meter.toml");
+    }
+
+    #[test]
+    fn schemas_load_per_unit() {
+        let dir = temp_dir("config-schemas");
+        std::fs::write(
+            dir.join("meter.toml"),
+            "[[holding_registers]]\nname = \"h_power\"\naddress = 0\ncount = 1\nvalue.U16.default = 7\n",
+        )
+        .unwrap();
+        let mut config = config(&[(3, "meter.toml")]);
+        config.path = dir.join("server.toml").to_string_lossy().to_string();
+        let schemas = config.load_schemas().unwrap();
+        assert_eq!(schemas.len(), 1);
+        assert_eq!(schemas[0].0, 3);
+        assert_eq!(schemas[0].2.holding_registers[0].name, "h_power");
+
+        config.units.push(UnitConfig {
+            unit_id: 4,
+            schema: "missing.toml".to_string(),
+        });
+        let err = config.load_schemas().unwrap_err();
+        assert!(err.contains("missing.toml"), "{err}");
+        assert!(err.contains("unit_id 4"), "{err}");
+    }
+}
@@ -0,0 +1,544 @@
+use std::fmt::Display;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+
+use modbus_register_schema::{types::ValueFlags, *};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use tokio::net::TcpListener;
+
+use tracing;
+
+use crate::service::{data::ModbusServiceData, units::ModbusServiceUnits};
+
+/// Serve the http/json control endpoint on `listener`.
+///
+/// * `GET /units` - unit ids of the emulated devices, `null` for one
+///   answering every unit id
+/// * `GET /registers` - every register with its current value
+/// * `GET /registers/{name}` - one register with its current value
+/// * `PUT /registers/{name}` - store `{"value": ...}` into a register like a
+///   modbus write, firing its rules
+/// * `POST /reset` - restore the values loaded from the schema
+///
+/// Every route takes an optional `?unit=<id>` query, required when several
+/// devices are emulated. `POST /reset` without it resets every device.
+pub async fn serve(listener: TcpListener, units: ModbusServiceUnits) -> std::io::Result<()> {
+    let router = Router::new()
+        .route("/units", get(list_units))
+        .route("/registers", get(list_registers))
+        .route("/registers/:name", get(read_register).put(write_register))
+        .route("/reset", post(reset))
+        .with_state(units);
+    axum::serve(listener, router).await
+}
+
+#[derive(Debug, Deserialize)]
+struct UnitQuery {
+    unit: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WriteBody {
+    value: Value,
+}
+
+/// A register description with its current value.
+///
+/// `value` is the engineering value for scaled numbers, the bits of coils and
+/// discrete inputs, the name of an enum and the value and flags of flag
+/// registers; `raw` is the number stored in the registers if it differs.
+#[derive(Debug, Serialize)]
+struct RegisterState {
+    name: String,
+    table: RegisterTable,
+    address: u16,
+    count: u16,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    value: Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    raw: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<String>,
+}
+
+#[derive(Debug)]
+enum ControlError {
+    UnitRequired,
+    UnknownUnit(u8),
+    UnknownRegister(String),
+    InvalidValue(String),
+}
+
+impl Display for ControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlError::UnitRequired => {
+                write!(
+                    f,
+                    "several devices are emulated, select one with ?unit=<id>"
+                )
+            }
+            ControlError::UnknownUnit(unit) => write!(f, "no device with unit id {unit}"),
+            ControlError::UnknownRegister(name) => write!(f, "no register named {name}"),
+            ControlError::InvalidValue(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl IntoResponse for ControlError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ControlError::UnitRequired => StatusCode::BAD_REQUEST,
+            ControlError::UnknownUnit(_) | ControlError::UnknownRegister(_) => {
+                StatusCode::NOT_FOUND
+            }
+            ControlError::InvalidValue(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// Register image of the device selected by `unit`, the only one if unset.
+fn select_unit(
+    units: &ModbusServiceUnits,
+    unit: Option<u8>,
+) -> Result<ModbusServiceData, ControlError> {
+    match unit {
+        Some(unit) => units
+            .get(unit)
+            .cloned()
+            .ok_or(ControlError::UnknownUnit(unit)),
+        None => {
+            let mut all = units.iter();
+            match (all.next(), all.next()) {
+                (Some((_unit, data)), None) => Ok(data.clone()),
+                _ => Err(ControlError::UnitRequired),
+            }
+        }
+    }
+}
+
+async fn list_units(State(units): State<ModbusServiceUnits>) -> Json<Vec<Option<u8>>> {
+    Json(units.iter().map(|(unit, _data)| unit).collect())
+}
+
+async fn list_registers(
+    State(units): State<ModbusServiceUnits>,
+    Query(query): Query<UnitQuery>,
+) -> Result<Json<Vec<RegisterState>>, ControlError> {
+    let data = select_unit(&units, query.unit)?;
+    Ok(Json(
+        data.descriptions()
+            .iter()
+            .map(|(table, desc)| register_state(*table, desc))
+            .collect(),
+    ))
+}
+
+async fn read_register(
+    State(units): State<ModbusServiceUnits>,
+    Path(name): Path<String>,
+    Query(query): Query<UnitQuery>,
+) -> Result<Json<RegisterState>, ControlError> {
+    let data = select_unit(&units, query.unit)?;
+    data.with_named(&name, |table, desc| Json(register_state(table, desc)))
+        .ok_or(ControlError::UnknownRegister(name))
+}
+
+async fn write_register(
+    State(units): State<ModbusServiceUnits>,
+    Path(name): Path<String>,
+    Query(query): Query<UnitQuery>,
+    Json(body): Json<WriteBody>,
+) -> Result<Json<RegisterState>, ControlError> {
+    let data = select_unit(&units, query.unit)?;
+    let mut updated = data
+        .with_named(&name, |_table, desc| desc.clone())
+        .ok_or(ControlError::UnknownRegister(name.clone()))?;
+    set_value(&mut updated, &body.value).map_err(ControlError::InvalidValue)?;
+    // values the registers can not hold are rejected before encoding them
+    updated
+        .validate_value()
+        .map_err(|err| ControlError::InvalidValue(err.to_string()))?;
+    let (table, desc) = data
+        .write_value(&updated)
+        .ok_or(ControlError::UnknownRegister(name.clone()))?
+        .map_err(|err| ControlError::InvalidValue(format!("rejected by the register - {err:?}")))?;
+    let state = register_state(table, &desc);
+
+    tracing::info!(
+        "control(unit: {:?}, name: {}) <- {} (raw: {})",
+        query.unit,
+        name,
+        state.value,
+        state.raw
+    );
+    Ok(Json(state))
+}
+
+async fn reset(
+    State(units): State<ModbusServiceUnits>,
+    Query(query): Query<UnitQuery>,
+) -> Result<StatusCode, ControlError> {
+    match query.unit {
+        Some(unit) => select_unit(&units, Some(unit))?.reset(),
+        None => units.iter().for_each(|(_unit, data)| data.reset()),
+    }
+    tracing::info!("control(unit: {:?}) reset to schema values", query.unit);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn register_state(table: RegisterTable, desc: &RegisterDescription) -> RegisterState {
+    let (value, raw, unit) = match &desc.value {
+        RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => (
+            json!((0..constraints.max_bits)
+                .map(|i| {
+                    // short values read as cleared bits instead of panicking
+                    constraints
+                        .val
+                        .get(i as usize / 8)
+                        .is_some_and(|byte| byte & (1 << (i % 8)) != 0)
+                })
+                .collect::<Vec<bool>>()),
+            Value::Null,
+            None,
+        ),
+        RegisterValueType::U8(constraints) => numeric_state(constraints),
+        RegisterValueType::U16(constraints) => numeric_state(constraints),
+        RegisterValueType::U32(constraints) => numeric_state(constraints),
+        RegisterValueType::U64(constraints) => numeric_state(constraints),
+        RegisterValueType::I16(constraints) => numeric_state(constraints),
+        RegisterValueType::I32(constraints) => numeric_state(constraints),
+        RegisterValueType::I64(constraints) => numeric_state(constraints),
+        RegisterValueType::F32(constraints) => numeric_state(constraints),
+        RegisterValueType::F64(constraints) => numeric_state(constraints),
+        RegisterValueType::U16Flags(constraints) => flags_state(
+            constraints.val.as_ref(),
+            &constraints.flag_names,
+            &desc.value,
+        ),
+        RegisterValueType::U32Flags(constraints) => flags_state(
+            constraints.val.as_ref(),
+            &constraints.flag_names,
+            &desc.value,
+        ),
+        RegisterValueType::U64Flags(constraints) => flags_state(
+            constraints.val.as_ref(),
+            &constraints.flag_names,
+            &desc.value,
+        ),
+        RegisterValueType::Bytes(constraints) => (
+            json!(constraints.val.as_ref().or(constraints.default.as_ref())),
+            Value::Null,
+            None,
+        ),
+        RegisterValueType::String(constraints) => (
+            json!(constraints.val.as_ref().or(constraints.default.as_ref())),
+            Value::Null,
+            None,
+        ),
+        RegisterValueType::Enum(constraints) => {
+            let raw = desc.value.numeric().map(|v| v as u32);
+            let name = raw.and_then(|raw| {
+                constraints
+                    .kv
+                    .iter()
+                    .find_map(|(name, index)| (*index == raw).then_some(name))
+            });
+            match name {
+                Some(name) => (json!(name), json!(raw), None),
+                None => (json!(raw), Value::Null, None),
+            }
+        }
+    };
+
+    RegisterState {
+        name: desc.name.clone(),
+        table,
+        address: desc.address,
+        count: desc.count,
+        type_: desc.value.name(),
+        value,
+        raw,
+        unit,
+    }
+}
+
+fn numeric_state<T>(constraints: &NumericConstraints<T>) -> (Value, Value, Option<String>)
+where
+    T: Copy + PartialOrd + Display + RawValue + Serialize,
+{
+    let Some(raw) = constraints.val.or(constraints.default) else {
+        return (Value::Null, Value::Null, None);
+    };
+    if constraints.is_scaled() {
+        // same rounding as format_value, without the float noise of 0.1 scales
+        let factor = 10f64.powi(constraints.precision.unwrap_or(9) as i32);
+        let engineering = (constraints.to_engineering(raw) * factor).round() / factor;
+        (json!(engineering), json!(raw), constraints.unit.clone())
+    } else {
+        (json!(raw), Value::Null, constraints.unit.clone())
+    }
+}
+
+fn flags_state<F>(
+    val: Option<&F>,
+    flag_names: &[String],
+    value: &RegisterValueType,
+) -> (Value, Value, Option<String>)
+where
+    F: ValueFlags,
+    F::Value: Serialize,
+{
+    let Some(raw) = value.numeric() else {
+        return (Value::Null, Value::Null, None);
+    };
+    let Some(vf) = val else {
+        // only a default, which is stored as the plain number
+        return (json!(raw as u64), Value::Null, None);
+    };
+    let flags = flag_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.clone(), json!(vf.flag_bits() & (1 << i) != 0)))
+        .collect::<serde_json::Map<String, Value>>();
+    (
+        json!({ "value": vf.value(), "flags": flags }),
+        json!(raw as u64),
+        None,
+    )
+}
+
+/// Take the json `value` as the value of a copy of the description, numbers
+/// of scaled registers as engineering values. The copy is written through
+/// `ModbusServiceData::write_value`, like a modbus write.
+fn set_value(desc: &mut RegisterDescription, value: &Value) -> Result<(), String> {
+    match &mut desc.value {
+        RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+            let bits = match value {
+                Value::Bool(bit) => vec![*bit],
+                _ => from_json::<Vec<bool>>(value)?,
+            };
+            if bits.len() > constraints.max_bits as usize {
+                return Err(format!(
+                    "{} bits given, the register has {}",
+                    bits.len(),
+                    constraints.max_bits
+                ));
+            }
+            constraints
+                .val
+                .resize((constraints.max_bits as usize).div_ceil(8), 0);
+            constraints.set_bits(0, &bits);
+        }
+        RegisterValueType::U8(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::U16(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::U32(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::U64(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::I16(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::I32(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::I64(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::F32(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::F64(constraints) => set_numeric(constraints, value)?,
+        RegisterValueType::U16Flags(_)
+        | RegisterValueType::U32Flags(_)
+        | RegisterValueType::U64Flags(_) => {
+            let raw = from_json::<u64>(value)?;
+            if !desc.value.set_numeric(raw as f64) {
+                return Err("the flag names do not fit into the register".to_string());
+            }
+        }
+        RegisterValueType::Bytes(constraints) => constraints.val = Some(from_json(value)?),
+        RegisterValueType::String(constraints) => constraints.val = Some(from_json(value)?),
+        RegisterValueType::Enum(constraints) => {
+            let raw = match value {
+                Value::String(name) => *constraints
+                    .kv
+                    .get(name)
+                    .ok_or(format!("unknown enum name {name}"))?,
+                _ => from_json::<u32>(value)?,
+            };
+            constraints.val = Some(raw);
+        }
+    }
+    Ok(())
+}
+
+fn set_numeric<T>(constraints: &mut NumericConstraints<T>, value: &Value) -> Result<(), String>
+where
+    T: Copy + PartialOrd + Display + RawValue + DeserializeOwned,
+{
+    let raw = if constraints.is_scaled() {
+        let engineering = value.as_f64().ok_or("expected a number")?;
+        constraints
+            .from_engineering(engineering)
+            .map_err(|err| err.to_string())?
+    } else {
+        from_json(value)?
+    };
+    constraints.val = Some(raw);
+    Ok(())
+}
+
+fn from_json<T: DeserializeOwned>(value: &Value) -> Result<T, String> {
+    serde_json::from_value(value.clone()).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tokio_modbus::prelude::{Request, Response as ModbusResponse};
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "h_setpoint"
+        address = 0
+        count = 1
+        value.U16.default = 50
+        value.U16.lte = 100
+
+        [[holding_registers]]
+        name = "h_temperature"
+        address = 1
+        count = 1
+        value.I16.default = 215
+        value.I16.scale = 0.1
+        value.I16.unit = "°C"
+
+        [[holding_registers]]
+        name = "h_mode"
+        address = 2
+        count = 2
+        value.Enum.default = "off"
+        value.Enum.kv = { off = 0, on = 1 }
+        "#;
+
+    fn data() -> ModbusServiceData {
+        ModbusServiceData::new(toml::from_str(SCHEMA).unwrap(), false)
+    }
+
+    fn status<T: IntoResponse>(result: Result<T, ControlError>) -> StatusCode {
+        result.into_response().status()
+    }
+
+    fn query(unit: Option<u8>) -> Query<UnitQuery> {
+        Query(UnitQuery { unit })
+    }
+
+    async fn write(
+        units: &ModbusServiceUnits,
+        name: &str,
+        value: Value,
+    ) -> Result<Json<RegisterState>, ControlError> {
+        write_register(
+            State(units.clone()),
+            Path(name.to_string()),
+            query(None),
+            Json(WriteBody { value }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn registers_read_as_engineering_values() {
+        let units = ModbusServiceUnits::single(data());
+        let Json(state) = read_register(
+            State(units.clone()),
+            Path("h_temperature".to_string()),
+            query(None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.value, json!(21.5));
+        assert_eq!(state.raw, json!(215));
+        assert_eq!(state.unit.as_deref(), Some("°C"));
+
+        let Json(states) = list_registers(State(units), query(None)).await.unwrap();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[2].value, json!("off"));
+    }
+
+    #[tokio::test]
+    async fn writes_reach_the_modbus_registers() {
+        let data = data();
+        let units = ModbusServiceUnits::single(data.clone());
+        let Json(state) = write(&units, "h_temperature", json!(-4.5)).await.unwrap();
+        assert_eq!(state.raw, json!(-45));
+        let Json(state) = write(&units, "h_mode", json!("on")).await.unwrap();
+        assert_eq!(state.raw, json!(1));
+        // enums are little endian by default, the low register first
+        assert_eq!(
+            data.dispatch(Request::ReadHoldingRegisters(1, 3)),
+            Ok(ModbusResponse::ReadHoldingRegisters(vec![0xFFD3, 1, 0]))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_values_are_unprocessable() {
+        let units = ModbusServiceUnits::single(data());
+        for (name, value) in [
+            ("h_setpoint", json!(101)),
+            ("h_setpoint", json!("fast")),
+            ("h_mode", json!("auto")),
+        ] {
+            assert_eq!(
+                status(write(&units, name, value.clone()).await),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "{name} {value}"
+            );
+        }
+        let Json(state) = read_register(State(units), Path("h_setpoint".to_string()), query(None))
+            .await
+            .unwrap();
+        assert_eq!(state.value, json!(50));
+    }
+
+    #[tokio::test]
+    async fn unknown_registers_and_units_are_not_found() {
+        let units = ModbusServiceUnits::single(data());
+        assert_eq!(
+            status(write(&units, "h_missing", json!(1)).await),
+            StatusCode::NOT_FOUND
+        );
+        let units = ModbusServiceUnits::new(BTreeMap::from([(1, data()), (2, data())]));
+        let read = |unit| {
+            read_register(
+                State(units.clone()),
+                Path("h_setpoint".to_string()),
+                query(unit),
+            )
+        };
+        assert_eq!(status(read(Some(3)).await), StatusCode::NOT_FOUND);
+        assert_eq!(status(read(None).await), StatusCode::BAD_REQUEST);
+        assert_eq!(status(read(Some(2)).await), StatusCode::OK);
+        assert_eq!(list_units(State(units)).await.0, [Some(1), Some(2)]);
+    }
+
+    #[tokio::test]
+    async fn reset_restores_the_schema_values() {
+        let units = ModbusServiceUnits::single(data());
+        let Json(state) = write(&units, "h_setpoint", json!(80)).await.unwrap();
+        assert_eq!(state.value, json!(80));
+        assert_eq!(
+            status(reset(State(units.clone()), query(None)).await),
+            StatusCode::NO_CONTENT
+        );
+        let Json(state) = read_register(State(units), Path("h_setpoint".to_string()), query(None))
+            .await
+            .unwrap();
+        assert_eq!(state.value, json!(50));
+    }
+}
@@ -0,0 +1,90 @@
+use std::{io, net::SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use tokio_modbus::{prelude::*, server::Service};
+
+use tracing;
+
+use super::pdu::{decode_request, encode_exception, encode_response, invalid_request_exception};
+use crate::service::tcp::ModbusEmulatorTcpService;
+
+/// Largest mbap frame, a 7 byte header and a pdu of up to 253 bytes.
+pub const MAX_FRAME_LEN: usize = 260;
+
+/// Serve mbap frames read from `stream` of `peer` until it is closed, one
+/// request at a time. A header that is not modbus or announces a length no
+/// pdu can have ends the connection, the frames behind it can not be found.
+pub async fn serve<T: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: T,
+    service: &ModbusEmulatorTcpService,
+    peer: SocketAddr,
+) -> io::Result<()> {
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    loop {
+        // transaction id, protocol id and length
+        match stream.read_exact(&mut frame[..6]).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let len = u16::from_be_bytes([frame[4], frame[5]]) as usize;
+        if frame[2..4] != [0, 0] || !(2..=MAX_FRAME_LEN - 6).contains(&len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid mbap header {:02x?}", &frame[..6]),
+            ));
+        }
+        stream.read_exact(&mut frame[6..6 + len]).await?;
+        if let Some(response) = respond(service, peer, &frame[..6 + len]).await {
+            stream.write_all(&response).await?;
+            stream.flush().await?;
+        }
+    }
+}
+
+/// The response to the mbap frame `datagram`, `None` to stay silent.
+pub async fn respond(
+    service: &ModbusEmulatorTcpService,
+    peer: SocketAddr,
+    datagram: &[u8],
+) -> Option<Vec<u8>> {
+    // transaction id, protocol id, length, unit id and function code
+    if datagram.len() < 8
+        || datagram[2..4] != [0, 0]
+        || u16::from_be_bytes([datagram[4], datagram[5]]) as usize != datagram.len() - 6
+    {
+        tracing::warn!(
+            "SERVER: {peer} sent an invalid mbap frame {:02x?}",
+            datagram
+        );
+        return None;
+    }
+    let unit = datagram[6];
+    let function = datagram[7];
+    let pdu = match decode_request(&datagram[7..]) {
+        Ok(request) => match service
+            .call(SlaveRequest {
+                slave: unit,
+                request,
+            })
+            .await
+        {
+            Ok(Some(response)) => encode_response(&response),
+            Ok(None) => return None,
+            Err(exception) => encode_exception(function, exception),
+        },
+        Err(err) => {
+            tracing::warn!(
+                "SERVER: invalid request pdu {:02x?} - {err}",
+                &datagram[7..]
+            );
+            encode_exception(function, invalid_request_exception(function))
+        }
+    };
+    let mut response = datagram[..4].to_vec();
+    response.extend((pdu.len() as u16 + 1).to_be_bytes());
+    response.push(unit);
+    response.extend(pdu);
+    Some(response)
+}
@@ -0,0 +1,50 @@
+use std::io;
+
+use clap::ValueEnum;
+
+use tokio::net::TcpListener;
+
+use tracing;
+
+use modbus_serial_line::AsciiLine;
+
+use crate::service::rtu::ModbusEmulatorRtuService;
+
+pub mod mbap;
+pub mod pdu;
+pub mod rtu;
+pub mod udp;
+
+/// How requests and responses are framed on the line.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Framing {
+    /// modbus tcp, the MBAP header
+    Mbap,
+    /// slave id, pdu and crc
+    Rtu,
+    /// `:`, hex of slave id, pdu and LRC, CRLF
+    Ascii,
+}
+
+/// Serve rtu or ascii frames on every connection accepted by `listener`,
+/// e.g. for serial gateways tunneling raw frames over tcp.
+pub async fn serve_tcp(
+    listener: TcpListener,
+    service: ModbusEmulatorRtuService,
+    framing: Framing,
+) -> io::Result<()> {
+    loop {
+        let (stream, socket_addr) = listener.accept().await?;
+        tracing::info!("SERVER: {framing:?} connection from {socket_addr}");
+        let service = service.clone();
+        tokio::spawn(async move {
+            let result = match framing {
+                Framing::Ascii => rtu::serve(AsciiLine::new(stream), service, None).await,
+                _ => rtu::serve(stream, service, None).await,
+            };
+            if let Err(err) = result {
+                tracing::error!("SERVER: {socket_addr}: {err}");
+            }
+        });
+    }
+}
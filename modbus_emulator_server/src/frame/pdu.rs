@@ -0,0 +1,178 @@
+use std::io;
+
+use tokio_modbus::bytes::Bytes;
+use tokio_modbus::prelude::*;
+
+/// Length of the request pdu starting with `pdu`, `Ok(None)` if more bytes
+/// are needed to tell, `Err` with the function code if it is unknown.
+pub fn request_pdu_len(pdu: &[u8]) -> Result<Option<usize>, u8> {
+    let Some(&function) = pdu.first() else {
+        return Ok(None);
+    };
+    match function {
+        0x01..=0x06 => Ok(Some(5)),
+        0x07 | 0x0B | 0x0C | 0x11 => Ok(Some(1)),
+        0x0F | 0x10 => Ok(pdu.get(5).map(|&byte_count| 6 + byte_count as usize)),
+        0x16 => Ok(Some(7)),
+        0x17 => Ok(pdu.get(9).map(|&byte_count| 10 + byte_count as usize)),
+        0x18 => Ok(Some(3)),
+        _ => Err(function),
+    }
+}
+
+/// Exception answering a request pdu that does not decode, `IllegalFunction`
+/// for an exception function code, `IllegalDataValue` for malformed data.
+/// Unknown function codes decode as custom requests.
+pub fn invalid_request_exception(function: u8) -> ExceptionCode {
+    if function >= 0x80 {
+        ExceptionCode::IllegalFunction
+    } else {
+        ExceptionCode::IllegalDataValue
+    }
+}
+
+pub fn decode_request(pdu: &[u8]) -> io::Result<Request<'static>> {
+    // tokio-modbus indexes past packed coils shorter than their quantity
+    if let [0x0F, _, _, quantity_hi, quantity_lo, byte_count, ..] = *pdu {
+        let quantity = u16::from_be_bytes([quantity_hi, quantity_lo]);
+        if byte_count as usize != (quantity as usize).div_ceil(8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "byte count does not match the coil quantity",
+            ));
+        }
+    }
+    Request::try_from(Bytes::copy_from_slice(pdu))
+}
+
+pub fn encode_response(response: &Response) -> Vec<u8> {
+    let mut pdu = vec![response.function_code().value()];
+    match response {
+        Response::ReadCoils(coils) | Response::ReadDiscreteInputs(coils) => {
+            let packed = pack_coils(coils);
+            pdu.push(packed.len() as u8);
+            pdu.extend(packed);
+        }
+        Response::ReadInputRegisters(registers)
+        | Response::ReadHoldingRegisters(registers)
+        | Response::ReadWriteMultipleRegisters(registers) => {
+            pdu.push((registers.len() * 2) as u8);
+            for register in registers {
+                pdu.extend(register.to_be_bytes());
+            }
+        }
+        Response::WriteSingleCoil(address, value) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(if *value { [0xFF, 0x00] } else { [0x00, 0x00] });
+        }
+        Response::WriteMultipleCoils(address, quantity)
+        | Response::WriteMultipleRegisters(address, quantity) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(quantity.to_be_bytes());
+        }
+        Response::WriteSingleRegister(address, value) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(value.to_be_bytes());
+        }
+        Response::MaskWriteRegister(address, and_mask, or_mask) => {
+            pdu.extend(address.to_be_bytes());
+            pdu.extend(and_mask.to_be_bytes());
+            pdu.extend(or_mask.to_be_bytes());
+        }
+        Response::ReportServerId(server_id, run_indication, additional_data) => {
+            pdu.push(2 + additional_data.len() as u8);
+            pdu.push(*server_id);
+            pdu.push(if *run_indication { 0xFF } else { 0x00 });
+            pdu.extend(additional_data);
+        }
+        Response::Custom(_function, data) => pdu.extend(data.iter()),
+    }
+    pdu
+}
+
+pub fn encode_exception(function: u8, exception: ExceptionCode) -> Vec<u8> {
+    vec![function | 0x80, exception.into()]
+}
+
+fn pack_coils(coils: &[bool]) -> Vec<u8> {
+    let mut packed = vec![0u8; coils.len().div_ceil(8)];
+    for (i, _) in coils.iter().enumerate().filter(|(_, coil)| **coil) {
+        packed[i / 8] |= 1 << (i % 8);
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_lengths_follow_the_function_code() {
+        assert_eq!(request_pdu_len(&[]), Ok(None));
+        assert_eq!(request_pdu_len(&[0x03]), Ok(Some(5)));
+        assert_eq!(request_pdu_len(&[0x11]), Ok(Some(1)));
+        assert_eq!(request_pdu_len(&[0x16]), Ok(Some(7)));
+        // byte count still missing
+        assert_eq!(request_pdu_len(&[0x10, 0x00, 0x00, 0x00, 0x02]), Ok(None));
+        assert_eq!(
+            request_pdu_len(&[0x10, 0x00, 0x00, 0x00, 0x02, 0x04]),
+            Ok(Some(10))
+        );
+        assert_eq!(
+            request_pdu_len(&[0x17, 0, 0, 0, 1, 0, 0, 0, 1, 0x02]),
+            Ok(Some(12))
+        );
+        assert_eq!(request_pdu_len(&[0x41]), Err(0x41));
+    }
+
+    #[test]
+    fn malformed_requests_are_illegal_data_values() {
+        assert_eq!(
+            invalid_request_exception(0x03),
+            ExceptionCode::IllegalDataValue
+        );
+        assert_eq!(
+            invalid_request_exception(0x83),
+            ExceptionCode::IllegalFunction
+        );
+    }
+
+    #[test]
+    fn coil_byte_count_must_match_the_quantity() {
+        // 10 coils need 2 bytes
+        assert!(decode_request(&[0x0F, 0x00, 0x00, 0x00, 0x0A, 0x01, 0xFF]).is_err());
+        assert_eq!(
+            decode_request(&[0x0F, 0x00, 0x00, 0x00, 0x0A, 0x02, 0xFF, 0x03]).unwrap(),
+            Request::WriteMultipleCoils(0, vec![true; 10].into())
+        );
+    }
+
+    #[test]
+    fn unknown_function_codes_decode_as_custom_requests() {
+        assert!(matches!(
+            decode_request(&[0x41, 0x01]).unwrap(),
+            Request::Custom(0x41, _)
+        ));
+    }
+
+    #[test]
+    fn responses_are_encoded_big_endian_with_packed_coils() {
+        assert_eq!(
+            encode_response(&Response::ReadHoldingRegisters(vec![0x1234, 0x0001])),
+            vec![0x03, 0x04, 0x12, 0x34, 0x00, 0x01]
+        );
+        let coils = vec![true, false, true, false, false, false, false, false, true];
+        assert_eq!(
+            encode_response(&Response::ReadCoils(coils)),
+            vec![0x01, 0x02, 0x05, 0x01]
+        );
+        assert_eq!(
+            encode_response(&Response::WriteSingleCoil(0x0010, true)),
+            vec![0x05, 0x00, 0x10, 0xFF, 0x00]
+        );
+        assert_eq!(
+            encode_exception(0x03, ExceptionCode::IllegalDataAddress),
+            vec![0x83, 0x02]
+        );
+    }
+}
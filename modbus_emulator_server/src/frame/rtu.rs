@@ -0,0 +1,171 @@
+use std::{io, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use tracing;
+
+use modbus_serial_line::crc16;
+
+use super::pdu::{decode_request, invalid_request_exception, request_pdu_len};
+use crate::service::rtu::ModbusEmulatorRtuService;
+
+/// Serve rtu frames read from `transport` until it is closed. With a
+/// `frame_timeout`, a partial frame followed by that much silence is dropped
+/// instead of being completed by the bytes of the next one.
+pub async fn serve<T: AsyncRead + AsyncWrite + Unpin>(
+    mut transport: T,
+    service: ModbusEmulatorRtuService,
+    frame_timeout: Option<Duration>,
+) -> io::Result<()> {
+    let mut buf: Vec<u8> = vec![];
+    let mut chunk = [0u8; 256];
+    loop {
+        let n = match frame_timeout {
+            Some(timeout) if !buf.is_empty() => {
+                match tokio::time::timeout(timeout, transport.read(&mut chunk)).await {
+                    Ok(n) => n?,
+                    Err(_) => {
+                        tracing::warn!("SERVER: incomplete frame {:02x?} dropped", buf);
+                        buf.clear();
+                        continue;
+                    }
+                }
+            }
+            _ => transport.read(&mut chunk).await?,
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        while let Some((slave, pdu)) = next_frame(&mut buf) {
+            let reply = match decode_request(&pdu) {
+                Ok(request) => service.call(slave, pdu[0], request).await,
+                Err(err) => {
+                    tracing::warn!("SERVER: invalid request pdu {:02x?} - {err}", pdu);
+                    service.reject(slave, pdu[0], invalid_request_exception(pdu[0]))
+                }
+            };
+            let Some(pdu) = reply.pdu else {
+                continue;
+            };
+            let mut frame = vec![slave];
+            frame.extend(pdu);
+            let mut crc = crc16(&frame);
+            if reply.corrupt_crc {
+                crc = !crc;
+            }
+            frame.extend(crc.to_le_bytes());
+            transport.write_all(&frame).await?;
+            // the flush ends the frame on a serial line
+            transport.flush().await?;
+        }
+    }
+}
+
+/// Longest rtu frame of the spec, slave id, 253 bytes of pdu and crc.
+const MAX_FRAME_LEN: usize = 256;
+
+/// Take the next complete frame with a valid crc off `buf`, skipping bytes
+/// until one lines up.
+fn next_frame(buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let found = (0..buf.len()).find_map(|start| {
+        let len = frame_len(&buf[start..], start == 0)?;
+        Some((start, len))
+    });
+    let Some((start, len)) = found else {
+        // noise that never lines up must not pile up
+        if buf.len() > MAX_FRAME_LEN {
+            buf.drain(..buf.len() - MAX_FRAME_LEN);
+        }
+        return None;
+    };
+    if start > 0 {
+        tracing::warn!("SERVER: crc mismatch, {start} bytes dropped to resync");
+    }
+    let frame = buf.drain(..start + len).skip(start).collect::<Vec<u8>>();
+    Some((frame[0], frame[1..len - 2].to_vec()))
+}
+
+/// Length of the frame with a valid crc at the start of `buf`, `None` if
+/// there is none or it is incomplete. The end of a frame with an unknown
+/// function code is where the crc matches, only looked for with `unknown`
+/// as random bytes match a crc too often when searched everywhere.
+fn frame_len(buf: &[u8], unknown: bool) -> Option<usize> {
+    // slave id, function code and crc at least
+    if buf.len() < 4 {
+        return None;
+    }
+    let crc_matches = |frame: &[u8]| {
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        crc16(body).to_le_bytes() == crc
+    };
+    match request_pdu_len(&buf[1..]) {
+        Ok(Some(len)) => {
+            let frame_len = 1 + len + 2;
+            (frame_len <= buf.len() && crc_matches(&buf[..frame_len])).then_some(frame_len)
+        }
+        Ok(None) => None,
+        Err(_function) if unknown => {
+            (4..=buf.len().min(MAX_FRAME_LEN)).find(|&len| crc_matches(&buf[..len]))
+        }
+        Err(_function) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `body` followed by its crc.
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut frame = body.to_vec();
+        frame.extend(crc16(body).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn complete_frames_are_taken_off_the_buffer() {
+        let mut buf = frame(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]);
+        buf.extend(&[0x01, 0x06]);
+        assert_eq!(
+            next_frame(&mut buf),
+            Some((0x01, vec![0x03, 0x00, 0x00, 0x00, 0x0A]))
+        );
+        // the start of the next frame stays
+        assert_eq!(buf, vec![0x01, 0x06]);
+        assert_eq!(next_frame(&mut buf), None);
+    }
+
+    #[test]
+    fn noise_before_a_frame_is_skipped() {
+        let mut buf = vec![0x00, 0xFF, 0x13];
+        buf.extend(frame(&[0x02, 0x06, 0x00, 0x01, 0x00, 0x03]));
+        assert_eq!(
+            next_frame(&mut buf),
+            Some((0x02, vec![0x06, 0x00, 0x01, 0x00, 0x03]))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn broken_crc_is_not_a_frame() {
+        let mut buf = frame(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]);
+        let last = buf.len() - 1;
+        buf[last] ^= 0xFF;
+        assert_eq!(next_frame(&mut buf), None);
+    }
+
+    #[test]
+    fn unknown_function_code_ends_where_the_crc_matches() {
+        let mut buf = frame(&[0x01, 0x41, 0x12, 0x34]);
+        assert_eq!(next_frame(&mut buf), Some((0x01, vec![0x41, 0x12, 0x34])));
+    }
+
+    #[test]
+    fn noise_is_capped() {
+        let mut buf = vec![0x00; 3 * MAX_FRAME_LEN];
+        assert_eq!(next_frame(&mut buf), None);
+        assert_eq!(buf.len(), MAX_FRAME_LEN);
+    }
+}
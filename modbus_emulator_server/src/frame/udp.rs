@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::net::UdpSocket;
+
+use tracing;
+
+use super::mbap::{respond, MAX_FRAME_LEN};
+use crate::service::tcp::ModbusEmulatorTcpService;
+
+/// How long the last transaction of a peer is kept to answer retransmits.
+const TRANSACTION_TTL: Duration = Duration::from_secs(30);
+
+/// The last transaction of one peer, the response is `None` while the
+/// request is still being served.
+struct Transaction {
+    request: Vec<u8>,
+    response: Option<Vec<u8>>,
+    at: Instant,
+}
+
+/// Serve one mbap frame per datagram received on `socket`. A datagram
+/// repeating the last request of its peer, e.g. retransmitted after a lost
+/// response, gets the same response again instead of being executed twice.
+pub async fn serve(socket: UdpSocket, service: ModbusEmulatorTcpService) -> io::Result<()> {
+    let socket = Arc::new(socket);
+    let service = Arc::new(service);
+    let transactions: Arc<Mutex<HashMap<SocketAddr, Transaction>>> = Default::default();
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    loop {
+        let (n, peer) = socket.recv_from(&mut buf).await?;
+        let datagram = buf[..n].to_vec();
+
+        {
+            let mut transactions = transactions.lock().unwrap();
+            transactions.retain(|_peer, transaction| transaction.at.elapsed() < TRANSACTION_TTL);
+            if let Some(transaction) = transactions.get(&peer) {
+                if transaction.request == datagram {
+                    tracing::warn!("SERVER: {peer} repeated a request, answered once");
+                    if let Some(response) = &transaction.response {
+                        let _ = socket.try_send_to(response, peer);
+                    }
+                    continue;
+                }
+            }
+            transactions.insert(
+                peer,
+                Transaction {
+                    request: datagram.clone(),
+                    response: None,
+                    at: Instant::now(),
+                },
+            );
+        }
+
+        let socket = socket.clone();
+        let service = service.clone();
+        let transactions = transactions.clone();
+        // a delayed response must not hold up the other peers
+        tokio::spawn(async move {
+            let response = respond(&service, peer, &datagram).await;
+            {
+                let mut transactions = transactions.lock().unwrap();
+                if let Some(transaction) = transactions.get_mut(&peer) {
+                    if transaction.request == datagram {
+                        match &response {
+                            Some(response) => transaction.response = Some(response.clone()),
+                            // unanswered like a lost datagram, a retransmit is served anew
+                            None => {
+                                transactions.remove(&peer);
+                            }
+                        }
+                    }
+                }
+            }
+            let Some(response) = response else {
+                return;
+            };
+            if let Err(err) = socket.send_to(&response, peer).await {
+                tracing::error!("SERVER: {peer}: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_modbus::prelude::*;
+
+    use super::*;
+    use crate::service::{
+        data::ModbusServiceData, fault::FaultInjector, units::ModbusServiceUnits,
+    };
+
+    async fn server(data: &ModbusServiceData) -> (UdpSocket, SocketAddr) {
+        let units = ModbusServiceUnits::single(data.clone());
+        let service = ModbusEmulatorTcpService::new(units, FaultInjector::default());
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(serve(socket, service));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        (client, addr)
+    }
+
+    async fn next_response(client: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let n = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .expect("a response")
+            .unwrap();
+        buf[..n].to_vec()
+    }
+
+    fn data() -> ModbusServiceData {
+        let schema = toml::from_str(
+            "[[holding_registers]]\nname = \"h_setpoint\"\naddress = 0\ncount = 1\nvalue.U16.lte = 1000\n",
+        )
+        .unwrap();
+        ModbusServiceData::new(schema, false)
+    }
+
+    fn setpoint(data: &ModbusServiceData) -> u16 {
+        match data.dispatch(Request::ReadHoldingRegisters(0, 1)) {
+            Ok(Response::ReadHoldingRegisters(values)) => values[0],
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn retransmits_replay_the_response_without_executing_again() {
+        let data = data();
+        let (client, addr) = server(&data).await;
+        let write = [0, 1, 0, 0, 0, 6, 1, 0x06, 0, 0, 0, 5];
+        client.send_to(&write, addr).await.unwrap();
+        let response = next_response(&client).await;
+        assert_eq!(response, write);
+        assert_eq!(setpoint(&data), 5);
+
+        // changed behind the client's back, a retransmit must not undo it
+        data.dispatch(Request::WriteSingleRegister(0, 9)).unwrap();
+        client.send_to(&write, addr).await.unwrap();
+        assert_eq!(next_response(&client).await, response);
+        assert_eq!(setpoint(&data), 9);
+
+        // the same pdu in a new transaction is executed
+        let mut again = write;
+        again[1] = 2;
+        client.send_to(&again, addr).await.unwrap();
+        assert_eq!(next_response(&client).await, again);
+        assert_eq!(setpoint(&data), 5);
+    }
+
+    #[tokio::test]
+    async fn malformed_datagrams_are_not_answered() {
+        let data = data();
+        let (client, addr) = server(&data).await;
+        let mut oversized = vec![0, 1, 0, 0];
+        oversized.extend((MAX_FRAME_LEN as u16 + 34).to_be_bytes());
+        oversized.extend([1, 0x10, 0, 0, 0, 1, 2, 0, 7]);
+        oversized.resize(MAX_FRAME_LEN + 40, 0);
+        for datagram in [
+            // truncated header
+            vec![0, 1, 0, 0, 0, 6, 1],
+            // length past the end of the datagram
+            vec![0, 2, 0, 0, 0, 8, 1, 0x06, 0, 0, 0, 7],
+            // not modbus
+            vec![0, 3, 0, 1, 0, 6, 1, 0x06, 0, 0, 0, 7],
+            // cut down to MAX_FRAME_LEN, its length no longer matches
+            oversized,
+        ] {
+            client.send_to(&datagram, addr).await.unwrap();
+        }
+        let read = [0, 4, 0, 0, 0, 6, 1, 0x03, 0, 0, 0, 1];
+        client.send_to(&read, addr).await.unwrap();
+        assert_eq!(
+            next_response(&client).await,
+            [0, 4, 0, 0, 0, 5, 1, 0x03, 2, 0, 0]
+        );
+        assert_eq!(setpoint(&data), 0);
+    }
+}
@@ -1,21 +1,25 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 
 use clap::Parser;
 
 use modbus_register_schema::*;
 
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, UdpSocket};
 
-use tokio_modbus::server::{rtu, tcp};
+use tokio_modbus::server::tcp;
 
 use time::{macros::format_description, UtcOffset};
 
-use tracing;
 use tracing_subscriber::{self, fmt::time::OffsetTime};
 
 pub mod cli;
+pub mod config;
+pub mod control;
+pub mod frame;
 pub mod op;
 pub mod service;
+pub mod tls;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -32,24 +36,221 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line args
     let args = cli::Args::parse();
     tracing::info!("{:?}", args);
-    let schema = RegisterSchema::load(&args.schema, false).unwrap();
 
-    if (args.addr.starts_with("COM") || args.addr.starts_with("/dev/")) && args.baud_rate > 0 {
-        // run rtu server
-        let serial_builder = tokio_serial::new(args.addr, args.baud_rate);
-        let serial_server = tokio_serial::SerialStream::open(&serial_builder).unwrap();
+    // every device with the path of its register schema
+    let mut loaded = vec![];
+    match &args.config {
+        Some(path) => {
+            let Some(config) = config::ServerConfig::load(path, false) else {
+                tracing::error!("failed to load server config {}", path);
+                std::process::exit(1);
+            };
+            if let Err(err) = config.validate() {
+                tracing::error!("{}: {}", path, err);
+                std::process::exit(1);
+            }
+            match config.load_schemas() {
+                Ok(schemas) => loaded.extend(
+                    schemas
+                        .into_iter()
+                        .map(|(unit, path, schema)| (Some(unit), path, schema)),
+                ),
+                Err(err) => {
+                    tracing::error!("{}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let Some(schema) = RegisterSchema::load(&args.schema, false) else {
+                tracing::error!("failed to load register schema {}", args.schema);
+                std::process::exit(1);
+            };
+            loaded.push((None, args.schema.clone(), schema));
+        }
+    }
+    if args.check {
+        let failed = loaded
+            .iter()
+            .filter(|(_unit, path, schema)| !check_schema(path, schema))
+            .count();
+        std::process::exit(if failed == 0 { 0 } else { 1 });
+    }
+    // the codecs rely on a valid layout, a broken schema is not served
+    let mut invalid = false;
+    for (_unit, path, schema) in &loaded {
+        for error in schema.validate().err().unwrap_or_default() {
+            tracing::error!("{path}: {error}");
+            invalid = true;
+        }
+    }
+    if invalid {
+        tracing::error!("invalid register schema, see --check");
+        std::process::exit(1);
+    }
+
+    // one register image per device, shared by every connection
+    let units = if args.config.is_some() {
+        let mut units = BTreeMap::new();
+        for (unit, _path, schema) in loaded {
+            let data = service::data::ModbusServiceData::new(schema, args.fill_holes);
+            units.insert(unit.unwrap(), data);
+        }
+        service::units::ModbusServiceUnits::new(units)
+    } else {
+        let (_unit, _path, schema) = loaded.pop().unwrap();
+        let data = service::data::ModbusServiceData::new(schema, args.fill_holes);
+        service::units::ModbusServiceUnits::single(data)
+    };
+    // restore the saved values before anything reads or changes them
+    if let Some(path) = &args.state {
+        for (unit, data) in units.iter() {
+            let path = service::state::state_path(path, unit);
+            if let Err(err) = service::state::check_path(data, &path) {
+                tracing::error!("state(path: {}) - {}", path, err);
+                std::process::exit(1);
+            }
+            service::state::restore(data, &path);
+            service::state::spawn_persistence(data.clone(), path, args.state_interval_ms);
+        }
+
+        // save once more on ctrl-c, changes since the last interval included
+        let units = units.clone();
+        let path = path.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            for (unit, data) in units.iter() {
+                let path = service::state::state_path(&path, unit);
+                if let Err(err) = service::state::save(data, &path) {
+                    tracing::error!("state(path: {}) not saved - {}", path, err);
+                }
+            }
+            std::process::exit(0);
+        });
+    }
+    for (_unit, data) in units.iter() {
+        service::simulation::spawn_simulations(data);
+    }
+
+    // faults injected in front of the register images, reloaded on change
+    let faults = match &args.faults {
+        Some(path) => {
+            let Some(config) = service::fault::FaultConfig::load(path, false) else {
+                tracing::error!("failed to load fault config {}", path);
+                std::process::exit(1);
+            };
+            if let Err(err) = config.validate() {
+                tracing::error!("{}: {}", path, err);
+                std::process::exit(1);
+            }
+            let faults = service::fault::FaultInjector::new(config);
+            faults.watch();
+            faults
+        }
+        None => service::fault::FaultInjector::default(),
+    };
+
+    if let Some(addr) = &args.control {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("control endpoint listening on http://{}", addr);
+        let units = units.clone();
+        tokio::spawn(async move {
+            if let Err(err) = control::serve(listener, units).await {
+                tracing::error!("control endpoint failed: {err}");
+            }
+        });
+    }
+
+    // roles are checked before any connection is accepted
+    let roles = match &args.tls_roles {
+        Some(path) => {
+            let Some(config) = service::role::RoleConfig::load(path, false) else {
+                tracing::error!("failed to load role config {}", path);
+                std::process::exit(1);
+            };
+            if let Err(err) = config.validate() {
+                tracing::error!("{}: {}", path, err);
+                std::process::exit(1);
+            }
+            Some(config)
+        }
+        None => None,
+    };
 
-        let rtu_master = rtu::Server::new(serial_server);
-        let service = service::rtu::ModbusEmulatorRtuService::new(schema.clone());
-        rtu_master.serve_forever(service).await?;
+    // udp is picked by the scheme, tcp is the default
+    let (udp, addr) = match args.addr.strip_prefix("udp://") {
+        Some(addr) => (true, addr),
+        None => (false, args.addr.trim_start_matches("tcp://")),
+    };
+    let serial = (addr.starts_with("COM") || addr.starts_with("/dev/")) && args.baud_rate > 0;
+    let framing = args.framing.unwrap_or(if serial {
+        frame::Framing::Rtu
+    } else {
+        frame::Framing::Mbap
+    });
+    if args.tls_cert.is_some() && (serial || udp || framing != frame::Framing::Mbap) {
+        tracing::error!("tls needs a tcp address and mbap framing");
+        std::process::exit(1);
+    }
+    if serial {
+        // run rtu or ascii server
+        if framing == frame::Framing::Mbap {
+            tracing::error!("mbap framing needs a tcp address");
+            std::process::exit(1);
+        }
+        let params = args.serial_params();
+        let serial_server = modbus_serial_line::SerialLine::open(addr, &params).unwrap();
+
+        let service = service::rtu::ModbusEmulatorRtuService::new(units, faults);
+        if framing == frame::Framing::Ascii {
+            let ascii_server = modbus_serial_line::AsciiLine::new(serial_server);
+            frame::rtu::serve(ascii_server, service, None).await?;
+        } else {
+            let frame_timeout = args.inter_frame_us.map(|_| params.inter_frame());
+            frame::rtu::serve(serial_server, service, frame_timeout).await?;
+        }
+    } else if udp {
+        // run udp server
+        if framing != frame::Framing::Mbap {
+            tracing::error!("udp carries mbap framing only");
+            std::process::exit(1);
+        }
+        let socket_addr: SocketAddr = addr.parse().unwrap();
+        let udp_socket = UdpSocket::bind(socket_addr).await?;
+        let service = service::tcp::ModbusEmulatorTcpService::new(units, faults);
+        frame::udp::serve(udp_socket, service).await?;
+    } else if framing != frame::Framing::Mbap {
+        // run rtu or ascii over tcp server
+        let socket_addr: SocketAddr = addr.parse().unwrap();
+        let tcp_listener = TcpListener::bind(socket_addr).await?;
+        let service = service::rtu::ModbusEmulatorRtuService::new(units, faults);
+        frame::serve_tcp(tcp_listener, service, framing).await?;
+    } else if let (Some(cert), Some(key), Some(ca)) = (&args.tls_cert, &args.tls_key, &args.tls_ca)
+    {
+        // run modbus/tcp security server
+        let config = match tls::server_config(cert, key, ca) {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!("{err}");
+                std::process::exit(1);
+            }
+        };
+        let acceptor = tokio_rustls::TlsAcceptor::from(config);
+        let socket_addr: SocketAddr = addr.parse().unwrap();
+        let tcp_listener = TcpListener::bind(socket_addr).await?;
+        let service = service::tcp::ModbusEmulatorTcpService::new(units, faults);
+        tls::serve(tcp_listener, acceptor, service, roles).await?;
     } else {
         // run tcp server
-        let socket_addr: SocketAddr = args.addr.parse().unwrap();
+        let socket_addr: SocketAddr = addr.parse().unwrap();
         let tcp_listener = TcpListener::bind(socket_addr).await?;
         let tcp_server = tcp::Server::new(tcp_listener);
         let service = |_socket_addr| {
             Ok(Some(service::tcp::ModbusEmulatorTcpService::new(
-                schema.clone(),
+                units.clone(),
+                faults.clone(),
             )))
         };
         let on_connected = |stream, socket_addr| async move {
@@ -63,3 +264,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Print the validation result of `schema`, `true` if it is valid.
+fn check_schema(path: &str, schema: &RegisterSchema) -> bool {
+    match schema.validate() {
+        Ok(()) => {
+            println!("{path}: ok");
+            true
+        }
+        Err(errors) => {
+            println!("{path}: {} problem(s)", errors.len());
+            for error in errors {
+                println!("  {error}");
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::path::PathBuf;
+
+    /// An empty directory of its own for the test `name`.
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "modbus_emulator_server-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use modbus_register_schema::*;
+
+use tokio_modbus::prelude::*;
+
+use tracing;
+
+use super::read::description_read_u16;
+
+/// Current values of the holding register descriptions that unlock `locked`
+/// descriptions, by name. Taken before a write request changes anything, so
+/// a request cannot unlock itself.
+#[derive(Debug, Default)]
+pub struct Unlocks(BTreeMap<String, u64>);
+
+impl Unlocks {
+    /// Take the values of `descs`, their registers read most significant
+    /// first as one unsigned number.
+    pub fn new<'a>(descs: impl Iterator<Item = &'a RegisterDescription>) -> Self {
+        let values = descs
+            .filter_map(|desc| {
+                let registers = description_read_u16(desc)?;
+                let key = registers
+                    .iter()
+                    .fold(0u64, |key, register| (key << 16) | *register as u64);
+                Some((desc.name.clone(), key))
+            })
+            .collect();
+        Self(values)
+    }
+
+    fn holds(&self, unlock: &str, key: u64) -> bool {
+        self.0.get(unlock) == Some(&key)
+    }
+}
+
+/// Refuse reads of a `wo` description.
+pub fn check_read(desc: &RegisterDescription) -> Result<(), ExceptionCode> {
+    if desc.access == Access::WriteOnly {
+        tracing::error!(
+            "SERVER: ExceptionCode::IllegalDataAddress({}) - {} is write only",
+            desc.address,
+            desc.name
+        );
+        return Err(ExceptionCode::IllegalDataAddress);
+    }
+    Ok(())
+}
+
+/// Refuse writes the access of `desc` does not allow. A `ro` description
+/// looks like no writable register at all, a `write-once` or `locked` one
+/// like a register the device will not take writes to right now.
+pub fn check_write(desc: &RegisterDescription, unlocks: &Unlocks) -> Result<(), ExceptionCode> {
+    match &desc.access {
+        Access::ReadWrite | Access::WriteOnly => Ok(()),
+        Access::ReadOnly => {
+            tracing::error!(
+                "SERVER: ExceptionCode::IllegalDataAddress({}) - {} is read only",
+                desc.address,
+                desc.name
+            );
+            Err(ExceptionCode::IllegalDataAddress)
+        }
+        Access::WriteOnce if desc.written => {
+            tracing::error!(
+                "SERVER: ExceptionCode::IllegalFunction({}) - {} was written already",
+                desc.address,
+                desc.name
+            );
+            Err(ExceptionCode::IllegalFunction)
+        }
+        Access::WriteOnce => Ok(()),
+        Access::Locked { unlock, key } => {
+            if unlocks.holds(unlock, *key) {
+                return Ok(());
+            }
+            tracing::error!(
+                "SERVER: ExceptionCode::IllegalFunction({}) - {} is locked by {}",
+                desc.address,
+                desc.name,
+                unlock
+            );
+            Err(ExceptionCode::IllegalFunction)
+        }
+    }
+}
+
+/// Note a successful write, closing a `write-once` description.
+pub fn mark_written(desc: &mut RegisterDescription) {
+    if desc.access == Access::WriteOnce {
+        desc.written = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::op::tests::registers;
+    use crate::op::write::{register_mask_write_u16, register_write_u16};
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "firmware"
+        address = 0
+        count = 1
+        value.U16.default = 7
+        access = "ro"
+
+        [[holding_registers]]
+        name = "password"
+        address = 1
+        count = 1
+        value.U16.default = 0
+        access = "wo"
+
+        [[holding_registers]]
+        name = "serial"
+        address = 2
+        count = 1
+        value.U16.default = 0
+        access = "write-once"
+
+        [[holding_registers]]
+        name = "unlock"
+        address = 3
+        count = 1
+        value.U16.default = 0
+
+        [[holding_registers]]
+        name = "setpoint"
+        address = 4
+        count = 1
+        value.U16.default = 0
+        access = { locked = { unlock = "unlock", key = 1234 } }
+        "#;
+
+    fn unlocks(registers: &BTreeMap<u16, RegisterDescription>) -> Unlocks {
+        Unlocks::new(registers.values())
+    }
+
+    #[test]
+    fn read_only_registers_are_not_writable() {
+        let registers = registers(SCHEMA);
+        assert_eq!(check_read(&registers[&0]), Ok(()));
+        assert_eq!(
+            check_write(&registers[&0], &unlocks(&registers)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+
+    #[test]
+    fn write_only_registers_are_not_readable() {
+        let registers = registers(SCHEMA);
+        assert_eq!(
+            check_read(&registers[&1]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(check_write(&registers[&1], &unlocks(&registers)), Ok(()));
+    }
+
+    #[test]
+    fn write_once_registers_take_one_write() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = unlocks(&registers);
+        assert_eq!(
+            register_write_u16(&mut registers, 2, &[42], &unlocks, false),
+            Ok(vec![2])
+        );
+        assert!(registers[&2].written);
+        assert_eq!(
+            register_write_u16(&mut registers, 2, &[43], &unlocks, false),
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[test]
+    fn locked_registers_open_with_the_key() {
+        let mut registers = registers(SCHEMA);
+        assert_eq!(
+            check_write(&registers[&4], &unlocks(&registers)),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        // a request cannot unlock itself, the key must be in place before
+        let locked = unlocks(&registers);
+        assert_eq!(
+            register_write_u16(&mut registers, 3, &[1234, 5], &locked, false),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        register_write_u16(&mut registers, 3, &[1234], &locked, false).unwrap();
+        assert_eq!(check_write(&registers[&4], &unlocks(&registers)), Ok(()));
+    }
+
+    #[test]
+    fn mask_writes_to_write_only_registers_need_no_read_access() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = unlocks(&registers);
+        assert_eq!(
+            register_mask_write_u16(&mut registers, 1, 0x0000, 0x00FF, &unlocks, false),
+            Ok(vec![1])
+        );
+        assert_eq!(description_read_u16(&registers[&1]), Some(vec![0x00FF]));
+    }
+}
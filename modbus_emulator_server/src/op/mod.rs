@@ -1,2 +1,21 @@
+pub mod access;
+pub mod range;
 pub mod read;
 pub mod write;
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+
+    use modbus_register_schema::*;
+
+    /// Descriptions of every table of the toml `schema`, keyed by address.
+    pub fn registers(schema: &str) -> BTreeMap<u16, RegisterDescription> {
+        let schema: RegisterSchema = toml::from_str(schema).unwrap();
+        RegisterTable::ALL
+            .into_iter()
+            .flat_map(|table| schema.registers(table).clone())
+            .map(|desc| (desc.address, desc))
+            .collect()
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use modbus_register_schema::*;
+
+use tokio_modbus::prelude::*;
+
+use tracing;
+
+/// Most coils or discrete inputs one read request may ask for.
+pub const MAX_READ_BITS: u16 = 2000;
+/// Most coils one write multiple coils request may carry.
+pub const MAX_WRITE_BITS: u16 = 1968;
+/// Most registers one read request may ask for.
+pub const MAX_READ_REGISTERS: u16 = 125;
+/// Most registers one write multiple registers request may carry.
+pub const MAX_WRITE_REGISTERS: u16 = 123;
+/// Most registers the write part of a read/write multiple registers request
+/// may carry.
+pub const MAX_READ_WRITE_REGISTERS: u16 = 121;
+
+/// Reject a quantity of zero or above `max` with `IllegalDataValue`, the
+/// response would not fit into a pdu.
+pub fn check_quantity(cnt: u16, max: u16) -> Result<(), ExceptionCode> {
+    if cnt == 0 || cnt > max {
+        tracing::error!(
+            "SERVER: ExceptionCode::IllegalDataValue - quantity {cnt} not in 1..={max}"
+        );
+        return Err(ExceptionCode::IllegalDataValue);
+    }
+    Ok(())
+}
+
+/// Start addresses of the descriptions overlapping `[addr, addr + cnt)`, in
+/// address order.
+///
+/// Addresses not covered by any description are holes: they are rejected with
+/// `IllegalDataAddress` unless `fill_holes` is set, in which case the caller
+/// zero-fills them on read and discards them on write.
+pub fn overlapping_descriptions(
+    registers: &BTreeMap<u16, RegisterDescription>,
+    addr: u16,
+    cnt: u16,
+    fill_holes: bool,
+) -> Result<Vec<u16>, ExceptionCode> {
+    let end = addr as u32 + cnt as u32;
+    if end > u16::MAX as u32 + 1 {
+        tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({addr}, {cnt})");
+        return Err(ExceptionCode::IllegalDataAddress);
+    }
+
+    // a description starting before addr may still reach into the range
+    let first = registers
+        .range(..=addr)
+        .next_back()
+        .map(|(start, _)| *start)
+        .unwrap_or(addr);
+
+    let mut starts = Vec::new();
+    let mut covered = addr as u32;
+    for (start, desc) in registers.range(first..) {
+        let start = *start as u32;
+        if start >= end {
+            break;
+        }
+        let desc_end = start + desc.span() as u32;
+        if desc_end <= covered {
+            continue;
+        }
+        if start > covered && !fill_holes {
+            break;
+        }
+        starts.push(start as u16);
+        covered = desc_end.min(end);
+    }
+
+    if covered < end && !fill_holes {
+        tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({})", covered);
+        return Err(ExceptionCode::IllegalDataAddress);
+    }
+
+    Ok(starts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::tests::registers;
+
+    /// Registers 10..=11 and 12 with a hole at 13, then 14..=17.
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "u32"
+        address = 10
+        count = 2
+        value.U32.default = 1
+
+        [[holding_registers]]
+        name = "u16"
+        address = 12
+        count = 1
+        value.U16.default = 2
+
+        [[holding_registers]]
+        name = "u64"
+        address = 14
+        count = 4
+        value.U64.default = 3
+        "#;
+
+    #[test]
+    fn quantities_of_zero_or_above_the_limit_are_illegal_data_values() {
+        assert_eq!(check_quantity(0, 125), Err(ExceptionCode::IllegalDataValue));
+        assert_eq!(check_quantity(1, 125), Ok(()));
+        assert_eq!(check_quantity(125, 125), Ok(()));
+        assert_eq!(
+            check_quantity(126, 125),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn requests_may_start_and_end_within_descriptions() {
+        let registers = registers(SCHEMA);
+        assert_eq!(
+            overlapping_descriptions(&registers, 11, 2, false),
+            Ok(vec![10, 12])
+        );
+        assert_eq!(
+            overlapping_descriptions(&registers, 15, 2, false),
+            Ok(vec![14])
+        );
+    }
+
+    #[test]
+    fn holes_are_illegal_data_addresses_unless_filled() {
+        let registers = registers(SCHEMA);
+        assert_eq!(
+            overlapping_descriptions(&registers, 12, 3, false),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            overlapping_descriptions(&registers, 12, 3, true),
+            Ok(vec![12, 14])
+        );
+        assert_eq!(
+            overlapping_descriptions(&registers, 18, 1, false),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            overlapping_descriptions(&registers, 18, 1, true),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn requests_past_the_last_address_are_illegal_data_addresses() {
+        assert_eq!(
+            overlapping_descriptions(&registers(SCHEMA), u16::MAX, 2, true),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use modbus_register_schema::*;
 
@@ -6,286 +6,449 @@ use tokio_modbus::prelude::*;
 
 use tracing;
 
+use super::{
+    access::check_read,
+    range::{check_quantity, overlapping_descriptions, MAX_READ_BITS, MAX_READ_REGISTERS},
+};
+
 pub fn register_read_bool(
-    registers: &HashMap<u16, RegisterDescription>,
+    registers: &BTreeMap<u16, RegisterDescription>,
     addr: u16,
     cnt: u16,
+    fill_holes: bool,
 ) -> Result<Vec<bool>, ExceptionCode> {
-    let mut response: Vec<bool> = Vec::new();
-    if let Some(desc) = registers.get(&addr) {
-        match &desc.value {
-            RegisterValueType::Coils(constraints) => {
-                response = constraints.get_bits(0, cnt as usize);
-                tracing::info!(
-                    "read(name: {}, addr: {}, count: {}) -> {:?} (raw: {:?})",
-                    desc.name,
-                    desc.address,
-                    desc.count,
-                    response,
-                    constraints.val,
-                );
+    check_quantity(cnt, MAX_READ_BITS)?;
+    let mut response: Vec<bool> = vec![false; cnt.into()];
+    for start in overlapping_descriptions(registers, addr, cnt, fill_holes)? {
+        let desc = &registers[&start];
+        if let Err(err) = check_read(desc) {
+            if !fill_holes {
+                return Err(err);
             }
-            RegisterValueType::Discrete(constraints) => {
-                response = constraints.get_bits(0, cnt as usize);
-                tracing::info!(
-                    "read(name: {}, addr: {}, count: {}) -> {:?} (raw: {:?})",
-                    desc.name,
-                    desc.address,
-                    desc.count,
-                    response,
-                    constraints.val,
-                );
-            }
-            RegisterValueType::U8(_constraints) => {}
-            RegisterValueType::U16(_constraints) => {}
-            RegisterValueType::U32(_constraints) => {}
-            RegisterValueType::U64(_constraints) => {}
-            RegisterValueType::U16Flags(_constraints) => {}
-            RegisterValueType::U32Flags(_constraints) => {}
-            RegisterValueType::U64Flags(_constraints) => {}
-            RegisterValueType::Bytes(_constraints) => {}
-            RegisterValueType::String(_constraints) => {}
-            RegisterValueType::Enum(_constraints) => {}
+            continue;
         }
+        let Some(bits) = description_read_bool(desc) else {
+            if !fill_holes {
+                tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({})", start);
+                return Err(ExceptionCode::IllegalDataAddress);
+            }
+            continue;
+        };
+        copy_overlap(&bits, start, &mut response, addr);
     }
     Ok(response)
 }
 
+/// Current bits of a coils or discrete inputs description, `None` for
+/// register types.
+pub fn description_read_bool(desc: &RegisterDescription) -> Option<Vec<bool>> {
+    let (RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints)) =
+        &desc.value
+    else {
+        return None;
+    };
+
+    let response = constraints.get_bits(0, constraints.max_bits as usize);
+    tracing::info!(
+        "read(name: {}, addr: {}, count: {}) -> {:?} (raw: {:?})",
+        desc.name,
+        desc.address,
+        desc.count,
+        response,
+        constraints.val,
+    );
+    Some(response)
+}
+
 pub fn register_read_u16(
-    registers: &HashMap<u16, RegisterDescription>,
+    registers: &BTreeMap<u16, RegisterDescription>,
     addr: u16,
     cnt: u16,
+    fill_holes: bool,
 ) -> Result<Vec<u16>, ExceptionCode> {
+    check_quantity(cnt, MAX_READ_REGISTERS)?;
     let mut response: Vec<u16> = vec![0; cnt.into()];
-    if let Some(desc) = registers.get(&addr) {
-        match &desc.value {
-            RegisterValueType::Coils(_constraints) => {}
-            RegisterValueType::Discrete(_constraints) => {}
-            RegisterValueType::U8(constraints) => {
-                if let Some(v) = constraints.val.or(constraints.default) {
-                    response[0] = v as u16;
-                    tracing::info!(
-                        "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                        desc.name,
-                        desc.address,
-                        desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                        v,
-                        response
-                    );
-                }
+    for start in overlapping_descriptions(registers, addr, cnt, fill_holes)? {
+        let desc = &registers[&start];
+        if let Err(err) = check_read(desc) {
+            if !fill_holes {
+                return Err(err);
             }
-            RegisterValueType::U16(constraints) => {
-                if let Some(v) = constraints.val.or(constraints.default) {
-                    response[0] = v;
-                    tracing::info!(
-                        "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                        desc.name,
-                        desc.address,
-                        desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                        v,
-                        response
-                    );
-                }
+            continue;
+        }
+        let Some(values) = description_read_u16(desc) else {
+            if !fill_holes {
+                tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({})", start);
+                return Err(ExceptionCode::IllegalDataAddress);
             }
-            RegisterValueType::U32(constraints) => {
-                let val = constraints.val.or(constraints.default).unwrap_or(0);
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = if is_big_endian {
-                    &val.to_be_bytes()
-                } else {
-                    &val.to_le_bytes()
-                };
-                serialize_registers(bytes, is_big_endian, &mut response);
+            continue;
+        };
+        copy_overlap(&values, start, &mut response, addr);
+    }
+    Ok(response)
+}
+
+/// Copy the part of a description's values, starting at table address
+/// `start`, that falls into a response starting at table address `addr`.
+fn copy_overlap<T: Copy>(values: &[T], start: u16, response: &mut [T], addr: u16) {
+    let (start, addr) = (start as usize, addr as usize);
+    for (i, value) in values.iter().enumerate() {
+        if start + i >= addr && start + i - addr < response.len() {
+            response[start + i - addr] = *value;
+        }
+    }
+}
+
+/// Current registers of a register description, `None` for coils and
+/// discrete inputs.
+pub fn description_read_u16(desc: &RegisterDescription) -> Option<Vec<u16>> {
+    let mut response: Vec<u16> = vec![0; desc.count.into()];
+    match &desc.value {
+        RegisterValueType::Coils(_constraints) | RegisterValueType::Discrete(_constraints) => {
+            return None
+        }
+        RegisterValueType::U8(constraints) => {
+            if let Some(v) = constraints.val.or(constraints.default) {
+                let endianness = constraints.endianness.unwrap_or_default();
+                serialize_registers(&[0, v], endianness, &mut response);
                 tracing::info!(
                     "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                     desc.name,
                     desc.address,
                     desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    val,
+                    constraints.endianness.unwrap_or_default(),
+                    v,
                     response
                 );
             }
-            RegisterValueType::U64(constraints) => {
-                let val = constraints.val.or(constraints.default).unwrap_or(0);
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = if is_big_endian {
-                    &val.to_be_bytes()
-                } else {
-                    &val.to_le_bytes()
-                };
-                serialize_registers(bytes, is_big_endian, &mut response);
+        }
+        RegisterValueType::U16(constraints) => {
+            if let Some(v) = constraints.val.or(constraints.default) {
+                let endianness = constraints.endianness.unwrap_or_default();
+                serialize_registers(&v.to_be_bytes(), endianness, &mut response);
                 tracing::info!(
                     "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                     desc.name,
                     desc.address,
                     desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    val,
+                    constraints.endianness.unwrap_or_default(),
+                    v,
                     response
                 );
             }
-            RegisterValueType::U16Flags(constraints) => {
-                let vo: Option<u16> = if let Some(vf) = constraints.val.clone() {
-                    Some(vf.to_u16())
-                } else if let Some(v) = constraints.default {
-                    Some(v)
-                } else {
-                    None
-                };
+        }
+        RegisterValueType::U32(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::U64(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::I16(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::I32(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::I64(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::F32(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0.0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::F64(constraints) => {
+            let val = constraints.val.or(constraints.default).unwrap_or(0.0);
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = val.to_be_bytes();
+            serialize_registers(&bytes, endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::U16Flags(constraints) => {
+            let vo: Option<u16> = constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u16())
+                .or(constraints.default);
 
-                if let Some(v) = vo {
-                    response[0] = v;
-                    tracing::info!(
-                        "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                        desc.name,
-                        desc.address,
-                        desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                        v,
-                        response
-                    );
-                }
-            }
-            RegisterValueType::U32Flags(constraints) => {
-                let vo: Option<u32> = if let Some(vf) = constraints.val.clone() {
-                    Some(vf.to_u32())
-                } else if let Some(v) = constraints.default {
-                    Some(v)
-                } else {
-                    None
-                };
-                if let Some(v) = vo {
-                    let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                    let bytes = if is_big_endian {
-                        &v.to_be_bytes()
-                    } else {
-                        &v.to_le_bytes()
-                    };
-                    serialize_registers(bytes, is_big_endian, &mut response);
-                    tracing::info!(
-                        "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                        desc.name,
-                        desc.address,
-                        desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                        v,
-                        response
-                    );
-                }
-            }
-            RegisterValueType::U64Flags(constraints) => {
-                let vo: Option<u64> = if let Some(vf) = constraints.val.clone() {
-                    Some(vf.to_u64())
-                } else if let Some(v) = constraints.default {
-                    Some(v)
-                } else {
-                    None
-                };
-                if let Some(v) = vo {
-                    let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                    let bytes = if is_big_endian {
-                        &v.to_be_bytes()
-                    } else {
-                        &v.to_le_bytes()
-                    };
-                    serialize_registers(bytes, is_big_endian, &mut response);
-                    tracing::info!(
-                        "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                        desc.name,
-                        desc.address,
-                        desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                        v,
-                        response
-                    );
-                }
+            if let Some(v) = vo {
+                let endianness = constraints.endianness.unwrap_or_default();
+                serialize_registers(&v.to_be_bytes(), endianness, &mut response);
+                tracing::info!(
+                    "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                    desc.name,
+                    desc.address,
+                    desc.count,
+                    constraints.endianness.unwrap_or_default(),
+                    v,
+                    response
+                );
             }
-            RegisterValueType::Bytes(constraints) => {
-                let val = constraints
-                    .val
-                    .clone()
-                    .or(constraints.default.clone())
-                    .unwrap_or_else(Vec::new);
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                serialize_registers(val.as_slice(), is_big_endian, &mut response);
+        }
+        RegisterValueType::U32Flags(constraints) => {
+            let vo: Option<u32> = constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u32())
+                .or(constraints.default);
+            if let Some(v) = vo {
+                let endianness = constraints.endianness.unwrap_or_default();
+                let bytes = v.to_be_bytes();
+                serialize_registers(&bytes, endianness, &mut response);
                 tracing::info!(
-                    "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
+                    "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                     desc.name,
                     desc.address,
                     desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    val,
+                    constraints.endianness.unwrap_or_default(),
+                    v,
                     response
                 );
             }
-            RegisterValueType::String(constraints) => {
-                let val = constraints
-                    .val
-                    .clone()
-                    .or(constraints.default.clone())
-                    .unwrap_or_else(String::new);
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                serialize_registers(val.as_bytes(), is_big_endian, &mut response);
+        }
+        RegisterValueType::U64Flags(constraints) => {
+            let vo: Option<u64> = constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u64())
+                .or(constraints.default);
+            if let Some(v) = vo {
+                let endianness = constraints.endianness.unwrap_or_default();
+                let bytes = v.to_be_bytes();
+                serialize_registers(&bytes, endianness, &mut response);
                 tracing::info!(
-                    "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
+                    "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
                     desc.name,
                     desc.address,
                     desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    val,
+                    constraints.endianness.unwrap_or_default(),
+                    v,
                     response
                 );
             }
-            RegisterValueType::Enum(constraints) => {
-                let mut set = false;
-                let mut v = 0;
-                if let Some(i) = constraints.val {
-                    set = true;
-                    v = i;
-                }
-                if !set {
-                    if let Some(name) = &constraints.default {
-                        if let Some(i) = constraints.kv.get(name) {
-                            set = true;
-                            v = i.clone();
-                        }
+        }
+        RegisterValueType::Bytes(constraints) => {
+            let val = constraints
+                .val
+                .clone()
+                .or(constraints.default.clone())
+                .unwrap_or_else(Vec::new);
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            serialize_registers(val.as_slice(), endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::String(constraints) => {
+            let val = constraints
+                .val
+                .clone()
+                .or(constraints.default.clone())
+                .unwrap_or_else(String::new);
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            serialize_registers(val.as_bytes(), endianness, &mut response);
+            tracing::info!(
+                "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                val,
+                response
+            );
+        }
+        RegisterValueType::Enum(constraints) => {
+            let mut set = false;
+            let mut v = 0;
+            if let Some(i) = constraints.val {
+                set = true;
+                v = i;
+            }
+            if !set {
+                if let Some(name) = &constraints.default {
+                    if let Some(i) = constraints.kv.get(name) {
+                        set = true;
+                        v = *i;
                     }
                 }
-                if !set {
-                    tracing::warn!("unset, schema: {:?}", desc)
-                } else {
-                    let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                    let bytes = if is_big_endian {
-                        &v.to_be_bytes()
-                    } else {
-                        &v.to_le_bytes()
-                    };
-                    serialize_registers(bytes, is_big_endian, &mut response);
-                    let name =
-                        constraints
-                            .kv
-                            .iter()
-                            .find_map(|(name, index)| if &v == index { Some(name) } else { None });
-                    tracing::info!(
+            }
+            if !set {
+                tracing::warn!("unset, schema: {:?}", desc)
+            } else {
+                let endianness = constraints.endianness.unwrap_or_default();
+                let bytes = v.to_be_bytes();
+                serialize_registers(&bytes, endianness, &mut response);
+                let name =
+                    constraints
+                        .kv
+                        .iter()
+                        .find_map(|(name, index)| if &v == index { Some(name) } else { None });
+                tracing::info!(
                         "read(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} = {:?} (raw: {:?})",
                         desc.name,
                         desc.address,
                         desc.count,
-                        constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
+                        constraints.endianness.unwrap_or_default(),
                         v,
                         name,
                         response
                     );
-                }
             }
         }
-    } else {
-        tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({})", addr);
-        return Err(ExceptionCode::IllegalDataAddress);
     }
 
-    Ok(response)
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::tests::registers;
+
+    #[test]
+    fn reads_span_descriptions_and_fill_holes_with_zero() {
+        let registers = registers(
+            r#"
+            [[holding_registers]]
+            name = "u32"
+            address = 10
+            count = 2
+            value.U32.default = 0x00010002
+            value.U32.endianness = "Big"
+
+            [[holding_registers]]
+            name = "u16"
+            address = 13
+            count = 1
+            value.U16.default = 3
+            "#,
+        );
+        assert_eq!(
+            register_read_u16(&registers, 11, 3, true),
+            Ok(vec![0x0002, 0, 3])
+        );
+        assert_eq!(
+            register_read_u16(&registers, 11, 3, false),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+
+    #[test]
+    fn oversized_reads_are_illegal_data_values() {
+        let registers = registers("");
+        assert_eq!(
+            register_read_u16(&registers, 0, 126, true),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            register_read_bool(&registers, 0, 2001, true),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            register_read_bool(&registers, 0, 0, true),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn bit_reads_start_within_a_description() {
+        let registers = registers(
+            r#"
+            [[coils]]
+            name = "coils"
+            address = 100
+            count = 1
+            value.Coils.max_bits = 8
+            value.Coils.val = [0x66]
+            "#,
+        );
+        assert_eq!(
+            register_read_bool(&registers, 101, 3, false),
+            Ok(vec![true, true, false])
+        );
+    }
 }
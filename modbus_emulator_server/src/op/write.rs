@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::ops::Range;
 
 use modbus_register_schema::{
     types::{u16_flags::U16ValueFlags, u32_flags::U32ValueFlags, u64_flags::U64ValueFlags},
@@ -9,239 +10,713 @@ use tokio_modbus::prelude::*;
 
 use tracing;
 
-/// Write a holding register. Used by both the write single register
-/// and write multiple registers requests.
+use super::{
+    access::{check_write, mark_written, Unlocks},
+    range::{check_quantity, overlapping_descriptions, MAX_WRITE_BITS, MAX_WRITE_REGISTERS},
+    read::description_read_u16,
+};
 
+/// Write coils. Returns the start addresses of the descriptions written.
 pub fn register_write_bool(
-    registers: &mut HashMap<u16, RegisterDescription>,
+    registers: &mut BTreeMap<u16, RegisterDescription>,
     addr: u16,
     values: &[bool],
-) -> Result<(), ExceptionCode> {
-    if let Some(desc) = registers.get_mut(&addr) {
-        match &mut desc.value {
-            RegisterValueType::Coils(constraints) => {
-                constraints.set_bits(0, values);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}) -> {:?} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    values,
-                    constraints.val
-                );
+    unlocks: &Unlocks,
+    fill_holes: bool,
+) -> Result<Vec<u16>, ExceptionCode> {
+    check_quantity(values.len().try_into().unwrap_or(u16::MAX), MAX_WRITE_BITS)?;
+    let starts = overlapping_descriptions(registers, addr, values.len() as u16, fill_holes)?;
+    for start in &starts {
+        let desc = &registers[start];
+        if !matches!(desc.value, RegisterValueType::Coils(_)) {
+            if fill_holes {
+                continue;
             }
-            RegisterValueType::Discrete(_constraints) => {}
-            RegisterValueType::U8(_constraints) => {}
-            RegisterValueType::U16(_constraints) => {}
-            RegisterValueType::U32(_constraints) => {}
-            RegisterValueType::U64(_constraints) => {}
-            RegisterValueType::U16Flags(_constraints) => {}
-            RegisterValueType::U32Flags(_constraints) => {}
-            RegisterValueType::U64Flags(_constraints) => {}
-            RegisterValueType::Bytes(_constraints) => {}
-            RegisterValueType::String(_constraints) => {}
-            RegisterValueType::Enum(_constraints) => {}
+            tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({start})");
+            return Err(ExceptionCode::IllegalDataAddress);
         }
+        check_write(desc, unlocks)?;
     }
 
-    Ok(())
+    let mut written = Vec::new();
+    for start in starts {
+        let desc = registers.get_mut(&start).unwrap();
+        if let RegisterValueType::Coils(constraints) = &mut desc.value {
+            for index in overlap(start, constraints.max_bits, addr, values.len()) {
+                constraints.set_bit(index as u16, values[start as usize + index - addr as usize]);
+            }
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}) -> {:?} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.get_bits(0, constraints.max_bits as usize),
+                constraints.val
+            );
+            mark_written(desc);
+            written.push(start);
+        }
+    }
+
+    Ok(written)
 }
 
+/// Write holding registers. Used by both the write single register
+/// and write multiple registers requests. Returns the start addresses of the
+/// descriptions written.
+///
+/// Descriptions only partially covered by the request keep their other
+/// registers. Nothing is stored unless every touched description accepts
+/// its new registers.
 pub fn register_write_u16(
-    registers: &mut HashMap<u16, RegisterDescription>,
+    registers: &mut BTreeMap<u16, RegisterDescription>,
     addr: u16,
     values: &[u16],
-) -> Result<(), ExceptionCode> {
-    if let Some(desc) = registers.get_mut(&addr) {
-        match &mut desc.value {
-            RegisterValueType::Coils(_constraints) => {}
-            RegisterValueType::Discrete(_constraints) => {}
-            RegisterValueType::U8(constraints) => {
-                constraints.val = Some(values[0] as u8);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    values[0],
-                    values
-                );
-            }
-            RegisterValueType::U16(constraints) => {
-                constraints.val = Some(values[0]);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    values[0],
-                    values
-                );
+    unlocks: &Unlocks,
+    fill_holes: bool,
+) -> Result<Vec<u16>, ExceptionCode> {
+    check_quantity(
+        values.len().try_into().unwrap_or(u16::MAX),
+        MAX_WRITE_REGISTERS,
+    )?;
+    let mut updated = Vec::new();
+    for start in overlapping_descriptions(registers, addr, values.len() as u16, fill_holes)? {
+        let desc = &registers[&start];
+        if matches!(
+            desc.value,
+            RegisterValueType::Coils(_) | RegisterValueType::Discrete(_)
+        ) {
+            if fill_holes {
+                continue;
             }
-            RegisterValueType::U32(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let v = if is_big_endian {
-                    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                } else {
-                    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                };
-                constraints.val = Some(v);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    v,
-                    values
-                );
-            }
-            RegisterValueType::U64(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let v = if is_big_endian {
-                    u64::from_be_bytes([
-                        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6],
-                        bytes[7],
-                    ])
-                } else {
-                    u64::from_le_bytes([
-                        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6],
-                        bytes[7],
-                    ])
-                };
-                constraints.val = Some(v);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    v,
-                    values
-                );
-            }
-            RegisterValueType::U16Flags(constraints) => {
-                let vf = U16ValueFlags::from_u16(values[0], constraints.flag_names.len() as u8);
-                constraints.val = Some(vf);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    values[0],
-                    values
-                );
-            }
-            RegisterValueType::U32Flags(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let v = if is_big_endian {
-                    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                } else {
-                    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                };
-                let vf = U32ValueFlags::from_u32(v, constraints.flag_names.len() as u8);
-                constraints.val = Some(vf);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    v,
-                    values
-                );
-            }
-            RegisterValueType::U64Flags(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let v = if is_big_endian {
-                    u64::from_be_bytes([
-                        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6],
-                        bytes[7],
-                    ])
-                } else {
-                    u64::from_le_bytes([
-                        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6],
-                        bytes[7],
-                    ])
-                };
-                let vf = U64ValueFlags::from_u64(v, constraints.flag_names.len() as u8);
-                constraints.val = Some(vf);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    v,
-                    values
+            tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({start})");
+            return Err(ExceptionCode::IllegalDataAddress);
+        }
+        check_write(desc, unlocks)?;
+
+        // registers outside of the request keep their current value
+        let written = overlap(start, desc.count, addr, values.len());
+        let mut desc_values = if written.len() < desc.count as usize {
+            description_read_u16(desc).unwrap_or_default()
+        } else {
+            vec![0; desc.count.into()]
+        };
+        for index in written {
+            desc_values[index] = values[start as usize + index - addr as usize];
+        }
+
+        let mut desc = desc.clone();
+        description_write_u16(&mut desc, &desc_values)?;
+        mark_written(&mut desc);
+        updated.push(desc);
+    }
+
+    let written = updated.iter().map(|desc| desc.address).collect();
+    for desc in updated {
+        registers.insert(desc.address, desc);
+    }
+
+    Ok(written)
+}
+
+/// Mask write a holding register:
+/// `(current AND and_mask) OR (or_mask AND (NOT and_mask))`.
+///
+/// The current value is read internally, so only the write access of the
+/// description is checked.
+pub fn register_mask_write_u16(
+    registers: &mut BTreeMap<u16, RegisterDescription>,
+    addr: u16,
+    and_mask: u16,
+    or_mask: u16,
+    unlocks: &Unlocks,
+    fill_holes: bool,
+) -> Result<Vec<u16>, ExceptionCode> {
+    let mut current = 0;
+    // a hole reads as zero with fill_holes, the write then discards it
+    if let Some(start) = overlapping_descriptions(registers, addr, 1, fill_holes)?.first() {
+        let Some(values) = description_read_u16(&registers[start]) else {
+            tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({start})");
+            return Err(ExceptionCode::IllegalDataAddress);
+        };
+        current = values[(addr - start) as usize];
+    }
+    let value = (current & and_mask) | (or_mask & !and_mask);
+    tracing::info!(
+        "mask_write(addr: {}, and_mask: {:#06x}, or_mask: {:#06x}) -> {:#06x} (current: {:#06x})",
+        addr,
+        and_mask,
+        or_mask,
+        value,
+        current
+    );
+    register_write_u16(registers, addr, &[value], unlocks, fill_holes)
+}
+
+/// Indexes into a description starting at `start` and spanning `span`
+/// addresses that are covered by a request of `len` addresses at `addr`.
+fn overlap(start: u16, span: u16, addr: u16, len: usize) -> Range<usize> {
+    let first = (addr as usize).saturating_sub(start as usize);
+    let last = (addr as usize + len)
+        .saturating_sub(start as usize)
+        .min(span as usize);
+    first..last.max(first)
+}
+
+/// Store the full register image of one description, checking the decoded
+/// value against the constraints of the description.
+pub fn description_write_u16(
+    desc: &mut RegisterDescription,
+    values: &[u16],
+) -> Result<(), ExceptionCode> {
+    match &mut desc.value {
+        RegisterValueType::Coils(_constraints) | RegisterValueType::Discrete(_constraints) => {
+            tracing::error!(
+                "SERVER: ExceptionCode::IllegalDataAddress({})",
+                desc.address
+            );
+            return Err(ExceptionCode::IllegalDataAddress);
+        }
+        RegisterValueType::U8(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(&values[..1], endianness);
+            let v = u16::from_be_bytes([bytes[0], bytes[1]]);
+            let Ok(v) = u8::try_from(v) else {
+                tracing::error!(
+                    "SERVER: ExceptionCode::IllegalDataValue({}) - {} > u8::MAX",
+                    desc.address,
+                    v
                 );
-            }
-            RegisterValueType::Bytes(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes: Vec<u8> = deserialize_registers(values, is_big_endian);
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    bytes,
-                    values
+                return Err(ExceptionCode::IllegalDataValue);
+            };
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U16(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u16::from_be_bytes([bytes[0], bytes[1]]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::I16(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = i16::from_be_bytes([bytes[0], bytes[1]]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::I32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::I64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = i64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::F32(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::F64(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = f64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            constraints.val = Some(v);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U16Flags(constraints) => {
+            if let Err(err) = constraints.validate_flag_names() {
+                tracing::error!(
+                    "SERVER: ExceptionCode::IllegalDataValue({}) - {err}",
+                    desc.address
                 );
-                constraints.val = Some(bytes);
+                return Err(ExceptionCode::IllegalDataValue);
             }
-            RegisterValueType::String(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let text = String::from_utf8(bytes).unwrap();
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    text,
-                    values
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u16::from_be_bytes([bytes[0], bytes[1]]);
+            let vf = U16ValueFlags::from_u16(v, constraints.flag_names.len() as u8);
+            constraints.val = Some(vf);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U32Flags(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if let Err(err) = constraints.validate_flag_names() {
+                tracing::error!(
+                    "SERVER: ExceptionCode::IllegalDataValue({}) - {err}",
+                    desc.address
                 );
-                constraints.val = Some(text);
+                return Err(ExceptionCode::IllegalDataValue);
             }
-            RegisterValueType::Enum(constraints) => {
-                let is_big_endian = constraints.endianness == Some(Endianness::Big);
-                let bytes = deserialize_registers(values, is_big_endian);
-                let v = if is_big_endian {
-                    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                } else {
-                    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                };
-                constraints.val = Some(v);
-                let name =
-                    constraints
-                        .kv
-                        .iter()
-                        .find_map(|(name, index)| if &v == index { Some(name) } else { None });
-                tracing::info!(
-                    "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} = {:?} (raw: {:?})",
-                    desc.name,
-                    addr,
-                    desc.count,
-                    constraints.endianness.as_ref().unwrap_or(&Endianness::Big),
-                    v,
-                    name,
-                    values
+            let vf = U32ValueFlags::from_u32(v, constraints.flag_names.len() as u8);
+            constraints.val = Some(vf);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::U64Flags(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]);
+            if let Err(err) = constraints.validate_flag_names() {
+                tracing::error!(
+                    "SERVER: ExceptionCode::IllegalDataValue({}) - {err}",
+                    desc.address
                 );
+                return Err(ExceptionCode::IllegalDataValue);
             }
+            let vf = U64ValueFlags::from_u64(v, constraints.flag_names.len() as u8);
+            constraints.val = Some(vf);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                values
+            );
+        }
+        RegisterValueType::Bytes(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            let bytes: Vec<u8> = deserialize_registers(values, endianness);
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {:?} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                bytes,
+                values
+            );
+            constraints.val = Some(bytes);
+        }
+        RegisterValueType::String(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default().byte_layout();
+            let bytes = deserialize_registers(values, endianness);
+            let Ok(text) = String::from_utf8(bytes) else {
+                tracing::error!("SERVER: ExceptionCode::IllegalDataValue({})", desc.address);
+                return Err(ExceptionCode::IllegalDataValue);
+            };
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                text,
+                values
+            );
+            constraints.val = Some(text);
         }
-    } else {
-        tracing::error!("SERVER: ExceptionCode::IllegalDataAddress({addr})");
-        return Err(ExceptionCode::IllegalDataAddress);
+        RegisterValueType::Enum(constraints) => {
+            let endianness = constraints.endianness.unwrap_or_default();
+            let bytes = deserialize_registers(values, endianness);
+            let v = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            constraints.val = Some(v);
+            let name = constraints
+                .kv
+                .iter()
+                .find_map(|(name, index)| if &v == index { Some(name) } else { None });
+            tracing::info!(
+                "write(name: {}, addr: {}, count: {}, endianness: {:?}) -> {} = {:?} (raw: {:?})",
+                desc.name,
+                desc.address,
+                desc.count,
+                constraints.endianness.unwrap_or_default(),
+                v,
+                name,
+                values
+            );
+        }
+    }
+
+    // reject values the device would not accept, like a real one does
+    if let Err(err) = desc.validate_value() {
+        tracing::error!(
+            "SERVER: ExceptionCode::IllegalDataValue({}) - {err}",
+            desc.address
+        );
+        return Err(ExceptionCode::IllegalDataValue);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::tests::registers;
+
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "setpoint"
+        address = 0
+        count = 1
+        value.U16.default = 50
+        value.U16.lte = 100
+
+        [[holding_registers]]
+        name = "counter"
+        address = 1
+        count = 2
+        value.U32.default = 0x00010002
+        value.U32.endianness = "Big"
+
+        [[holding_registers]]
+        name = "mode"
+        address = 3
+        count = 2
+        value.Enum.default = "off"
+        value.Enum.kv = { off = 0, on = 1 }
+        value.Enum.endianness = "Big"
+        "#;
+
+    fn read(registers: &BTreeMap<u16, RegisterDescription>, addr: u16) -> Vec<u16> {
+        description_read_u16(&registers[&addr]).unwrap()
+    }
+
+    #[test]
+    fn values_out_of_bounds_are_illegal_data_values() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[101], &unlocks, false),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(read(&registers, 0), vec![50]);
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[100], &unlocks, false),
+            Ok(vec![0])
+        );
+        assert_eq!(read(&registers, 0), vec![100]);
+    }
+
+    #[test]
+    fn enum_values_outside_of_kv_are_illegal_data_values() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 3, &[0, 2], &unlocks, false),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            register_write_u16(&mut registers, 3, &[0, 1], &unlocks, false),
+            Ok(vec![3])
+        );
+    }
+
+    #[test]
+    fn one_rejected_description_stores_nothing() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[1, 0, 7, 0, 9], &unlocks, false),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(read(&registers, 0), vec![50]);
+        assert_eq!(read(&registers, 1), vec![0x0001, 0x0002]);
+    }
+
+    #[test]
+    fn partial_writes_keep_the_other_registers() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 2, &[0x0009], &unlocks, false),
+            Ok(vec![1])
+        );
+        assert_eq!(read(&registers, 1), vec![0x0001, 0x0009]);
+    }
+
+    #[test]
+    fn mask_writes_combine_with_the_current_value() {
+        let mut registers = registers(SCHEMA);
+        let unlocks = Unlocks::default();
+        // (50 & 0xF0) | (0x05 & !0xF0) = 0x35
+        assert_eq!(
+            register_mask_write_u16(&mut registers, 0, 0x00F0, 0x0005, &unlocks, false),
+            Ok(vec![0])
+        );
+        assert_eq!(read(&registers, 0), vec![0x35]);
+    }
+
+    const FLOATS: &str = r#"
+        [[holding_registers]]
+        name = "temperature"
+        address = 0
+        count = 2
+        value.F32.default = 20.5
+        value.F32.gte = -40.0
+        value.F32.lte = 125.0
+        value.F32.endianness = "Big"
+
+        [[holding_registers]]
+        name = "energy"
+        address = 2
+        count = 4
+        value.F64.default = -1.5
+        value.F64.endianness = "Big"
+
+        [[holding_registers]]
+        name = "ratio"
+        address = 6
+        count = 2
+        value.F32.default = 0.0
+        "#;
+
+    #[test]
+    fn floats_are_ieee_754_in_their_layout() {
+        let mut registers = registers(FLOATS);
+        let unlocks = Unlocks::default();
+        assert_eq!(read(&registers, 0), vec![0x41A4, 0x0000]);
+        assert_eq!(read(&registers, 2), vec![0xBFF8, 0, 0, 0]);
+        // 1.0 is 0x3F800000, little endian puts the low register first
+        assert_eq!(
+            register_write_u16(&mut registers, 6, &[0x0000, 0x3F80], &unlocks, false),
+            Ok(vec![6])
+        );
+        assert_eq!(registers[&6].value.numeric(), Some(1.0));
+        assert_eq!(
+            register_write_u16(&mut registers, 2, &[0x4059, 0, 0, 0], &unlocks, false),
+            Ok(vec![2])
+        );
+        assert_eq!(registers[&2].value.numeric(), Some(100.0));
+    }
+
+    #[test]
+    fn nan_and_infinities_out_of_bounds_are_illegal_data_values() {
+        let mut registers = registers(FLOATS);
+        let unlocks = Unlocks::default();
+        for value in [[0x7FC0, 0x0000], [0x7F80, 0x0000], [0xFF80, 0x0000]] {
+            assert_eq!(
+                register_write_u16(&mut registers, 0, &value, &unlocks, false),
+                Err(ExceptionCode::IllegalDataValue),
+                "{value:04x?}"
+            );
+        }
+        assert_eq!(read(&registers, 0), vec![0x41A4, 0x0000]);
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[0x42FA, 0x0000], &unlocks, false),
+            Ok(vec![0])
+        );
+        assert_eq!(registers[&0].value.numeric(), Some(125.0));
+    }
+
+    #[test]
+    fn unbounded_floats_take_nan() {
+        let mut registers = registers(FLOATS);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 6, &[0x0000, 0x7FC0], &unlocks, false),
+            Ok(vec![6])
+        );
+        assert!(registers[&6].value.numeric().unwrap().is_nan());
+        assert_eq!(read(&registers, 6), vec![0x0000, 0x7FC0]);
+    }
+
+    const SIGNED: &str = r#"
+        [[holding_registers]]
+        name = "offset"
+        address = 0
+        count = 1
+        value.I16.default = -2
+        value.I16.gte = -100
+        value.I16.lte = 100
+
+        [[holding_registers]]
+        name = "position"
+        address = 1
+        count = 2
+        value.I32.default = -70000
+        value.I32.endianness = "Big"
+
+        [[holding_registers]]
+        name = "position_dcba"
+        address = 3
+        count = 2
+        value.I32.default = -70000
+        value.I32.endianness = "DCBA"
+
+        [[holding_registers]]
+        name = "total"
+        address = 5
+        count = 4
+        value.I64.default = -1
+        value.I64.lt = 0
+        "#;
+
+    #[test]
+    fn signed_values_are_twos_complement_in_their_layout() {
+        let registers = registers(SIGNED);
+        assert_eq!(read(&registers, 0), vec![0xFFFE]);
+        // -70000 is 0xFFFEEE90
+        assert_eq!(read(&registers, 1), vec![0xFFFE, 0xEE90]);
+        assert_eq!(read(&registers, 3), vec![0x90EE, 0xFEFF]);
+        assert_eq!(read(&registers, 5), vec![0xFFFF; 4]);
+    }
+
+    #[test]
+    fn signed_values_round_trip() {
+        let mut registers = registers(SIGNED);
+        let unlocks = Unlocks::default();
+        assert_eq!(
+            register_write_u16(&mut registers, 1, &[0x8000, 0x0000], &unlocks, false),
+            Ok(vec![1])
+        );
+        assert_eq!(registers[&1].value.numeric(), Some(i32::MIN as f64));
+        // -2 with the low register first
+        assert_eq!(
+            register_write_u16(
+                &mut registers,
+                5,
+                &[0xFFFE, 0xFFFF, 0xFFFF, 0xFFFF],
+                &unlocks,
+                false
+            ),
+            Ok(vec![5])
+        );
+        assert_eq!(registers[&5].value.numeric(), Some(-2.0));
+        assert_eq!(read(&registers, 5), vec![0xFFFE, 0xFFFF, 0xFFFF, 0xFFFF]);
+    }
+
+    #[test]
+    fn negative_bounds_compare_signed_values() {
+        let mut registers = registers(SIGNED);
+        let unlocks = Unlocks::default();
+        // -101 is below gte, though 0xFF9B is above lte unsigned
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[0xFF9B], &unlocks, false),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+        assert_eq!(
+            register_write_u16(&mut registers, 0, &[0xFF9C], &unlocks, false),
+            Ok(vec![0])
+        );
+        assert_eq!(registers[&0].value.numeric(), Some(-100.0));
+        assert_eq!(
+            register_write_u16(&mut registers, 5, &[0, 0, 0, 0], &unlocks, false),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+}
@@ -1,105 +1,723 @@
-use std::collections::HashMap;
-use std::future;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use modbus_register_schema::*;
 
+use tokio::time::MissedTickBehavior;
+
 use tokio_modbus::prelude::{ExceptionCode, Response, *};
 
 use tracing;
 
 use crate::op::{
-    read::{register_read_bool, register_read_u16},
-    write::{register_write_bool, register_write_u16},
+    access::Unlocks,
+    range::{check_quantity, MAX_READ_REGISTERS, MAX_READ_WRITE_REGISTERS},
+    read::{description_read_bool, description_read_u16, register_read_bool, register_read_u16},
+    write::{
+        description_write_u16, register_mask_write_u16, register_write_bool, register_write_u16,
+    },
 };
 
+/// The four modbus tables of one emulated device, each keyed by the start
+/// address of its descriptions.
+#[derive(Clone, Debug, Default)]
+pub struct RegisterTables {
+    pub coils: BTreeMap<u16, RegisterDescription>,
+    pub discrete_inputs: BTreeMap<u16, RegisterDescription>,
+    pub input_registers: BTreeMap<u16, RegisterDescription>,
+    pub holding_registers: BTreeMap<u16, RegisterDescription>,
+}
+
+impl RegisterTables {
+    pub fn table(&self, table: RegisterTable) -> &BTreeMap<u16, RegisterDescription> {
+        match table {
+            RegisterTable::Coils => &self.coils,
+            RegisterTable::DiscreteInputs => &self.discrete_inputs,
+            RegisterTable::InputRegisters => &self.input_registers,
+            RegisterTable::HoldingRegisters => &self.holding_registers,
+        }
+    }
+
+    /// The description called `name`, looked up in table order.
+    pub fn named(&self, name: &str) -> Option<&RegisterDescription> {
+        RegisterTable::ALL
+            .iter()
+            .find_map(|&table| self.table(table).values().find(|desc| desc.name == name))
+    }
+
+    pub fn table_mut(&mut self, table: RegisterTable) -> &mut BTreeMap<u16, RegisterDescription> {
+        match table {
+            RegisterTable::Coils => &mut self.coils,
+            RegisterTable::DiscreteInputs => &mut self.discrete_inputs,
+            RegisterTable::InputRegisters => &mut self.input_registers,
+            RegisterTable::HoldingRegisters => &mut self.holding_registers,
+        }
+    }
+
+    /// Current values of the holding registers named as `unlock` by a
+    /// `locked` description.
+    pub fn unlocks(&self) -> Unlocks {
+        let names = RegisterTable::ALL
+            .iter()
+            .flat_map(|&table| self.table(table).values())
+            .filter_map(|desc| match &desc.access {
+                Access::Locked { unlock, .. } => Some(unlock.as_str()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        Unlocks::new(
+            self.holding_registers
+                .values()
+                .filter(|desc| names.contains(desc.name.as_str())),
+        )
+    }
+}
+
+/// Server-wide register image.
+///
+/// Cloning is cheap and every clone refers to the same tables, so all tcp
+/// connections and the rtu service observe each other's writes. All tables
+/// sit behind one lock, which makes every request atomic as a whole.
+///
+/// Writes fire the `rules` of the written descriptions while the lock is
+/// still held, delayed and ramping actions follow from their own tasks.
+#[derive(Clone, Debug)]
 pub struct ModbusServiceData {
-    coils: Arc<Mutex<HashMap<u16, RegisterDescription>>>,
-    discrete_inputs: Arc<Mutex<HashMap<u16, RegisterDescription>>>,
-    input_registers: Arc<Mutex<HashMap<u16, RegisterDescription>>>,
-    holding_registers: Arc<Mutex<HashMap<u16, RegisterDescription>>>,
+    tables: Arc<Mutex<RegisterTables>>,
+    defaults: Arc<RegisterTables>,
+    fill_holes: bool,
+    /// latest rule action per target name, pending actions of an older one
+    /// are dropped
+    transitions: Arc<Mutex<HashMap<String, u64>>>,
 }
 
 impl ModbusServiceData {
-    pub fn new(schema: RegisterSchema) -> Self {
-        let mut coils = HashMap::new();
+    /// `fill_holes` zero-fills reads of unmapped addresses and discards
+    /// writes to them instead of answering `IllegalDataAddress`.
+    pub fn new(schema: RegisterSchema, fill_holes: bool) -> Self {
+        let mut tables = RegisterTables::default();
         for desc in schema.coils {
-            coils.insert(desc.address, desc);
+            tables.coils.insert(desc.address, desc);
         }
-
-        let mut discrete_inputs = HashMap::new();
         for desc in schema.discrete_inputs {
-            discrete_inputs.insert(desc.address, desc);
+            tables.discrete_inputs.insert(desc.address, desc);
         }
-
-        let mut input_registers = HashMap::new();
         for desc in schema.input_registers {
-            input_registers.insert(desc.address, desc);
+            tables.input_registers.insert(desc.address, desc);
         }
-
-        let mut holding_registers = HashMap::new();
         for desc in schema.holding_registers {
-            holding_registers.insert(desc.address, desc);
+            tables.holding_registers.insert(desc.address, desc);
         }
 
         Self {
-            coils: Arc::new(Mutex::new(coils)),
-            discrete_inputs: Arc::new(Mutex::new(discrete_inputs)),
-            input_registers: Arc::new(Mutex::new(input_registers)),
-            holding_registers: Arc::new(Mutex::new(holding_registers)),
+            defaults: Arc::new(tables.clone()),
+            tables: Arc::new(Mutex::new(tables)),
+            fill_holes,
+            transitions: Default::default(),
         }
     }
 
-    pub fn dispatch(
+    /// Run `f` on the description starting at `address` of `table`, `None`
+    /// if there is none.
+    pub fn with_description<R>(
+        &self,
+        table: RegisterTable,
+        address: u16,
+        f: impl FnOnce(&mut RegisterDescription) -> R,
+    ) -> Option<R> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.table_mut(table).get_mut(&address).map(f)
+    }
+
+    /// Run `f` on the description called `name` and its table, `None` if
+    /// there is none.
+    pub fn with_named<R>(
         &self,
-        request: Request<'static>,
-    ) -> future::Ready<Result<Response, ExceptionCode>> {
-        let result = match request {
+        name: &str,
+        f: impl FnOnce(RegisterTable, &mut RegisterDescription) -> R,
+    ) -> Option<R> {
+        let mut tables = self.tables.lock().unwrap();
+        let table = RegisterTable::ALL
+            .into_iter()
+            .find(|&table| tables.table(table).values().any(|desc| desc.name == name))?;
+        tables
+            .table_mut(table)
+            .values_mut()
+            .find(|desc| desc.name == name)
+            .map(|desc| f(table, desc))
+    }
+
+    /// Table, address and span of the description called `name`.
+    pub fn locate(&self, name: &str) -> Option<(RegisterTable, u16, u16)> {
+        self.with_named(name, |table, desc| (table, desc.address, desc.span()))
+    }
+
+    /// Snapshot of every description with its table.
+    pub fn descriptions(&self) -> Vec<(RegisterTable, RegisterDescription)> {
+        let tables = self.tables.lock().unwrap();
+        RegisterTable::ALL
+            .iter()
+            .flat_map(|&table| {
+                tables
+                    .table(table)
+                    .values()
+                    .map(move |desc| (table, desc.clone()))
+            })
+            .collect()
+    }
+
+    /// Restore every description to its value as loaded from the schema.
+    pub fn reset(&self) {
+        let mut tables = self.tables.lock().unwrap();
+        *tables = (*self.defaults).clone();
+        // pending rule actions would undo the reset
+        for generation in self.transitions.lock().unwrap().values_mut() {
+            *generation += 1;
+        }
+    }
+
+    /// Current register image in the shape of the schema, `val` holding the
+    /// current values.
+    pub fn snapshot(&self) -> RegisterSchema {
+        let tables = self.tables.lock().unwrap();
+        let mut schema = RegisterSchema::default();
+        for table in RegisterTable::ALL {
+            *schema.registers_mut(table) = tables.table(table).values().cloned().collect();
+        }
+        schema
+    }
+
+    /// Take over the values of `state`, matched by table and name. Values of
+    /// another type or violating the constraints of the schema are skipped,
+    /// their names returned.
+    pub fn restore(&self, state: &RegisterSchema) -> Vec<String> {
+        let mut tables = self.tables.lock().unwrap();
+        let mut skipped = vec![];
+        for table in RegisterTable::ALL {
+            for saved in state.registers(table) {
+                let Some(desc) = tables
+                    .table_mut(table)
+                    .values_mut()
+                    .find(|desc| desc.name == saved.name)
+                else {
+                    skipped.push(saved.name.clone());
+                    continue;
+                };
+                let mut restored = desc.clone();
+                restored.written = saved.written;
+                if restored.value.copy_val(&saved.value) && restored.validate_value().is_ok() {
+                    *desc = restored;
+                } else {
+                    skipped.push(saved.name.clone());
+                }
+            }
+        }
+        skipped
+    }
+
+    /// Store the value of `updated` into the description of the same name
+    /// the way a modbus write does: encoded into its registers, decoded and
+    /// checked by the codec of the description, then its rules fired.
+    ///
+    /// The access of the description is not checked and write-once
+    /// registers stay unwritten, the caller stands in for the device and
+    /// not for a client. `None` if there is no description of that name.
+    pub fn write_value(
+        &self,
+        updated: &RegisterDescription,
+    ) -> Option<Result<(RegisterTable, RegisterDescription), ExceptionCode>> {
+        let mut tables = self.tables.lock().unwrap();
+        let table = RegisterTable::ALL.into_iter().find(|&table| {
+            tables
+                .table(table)
+                .values()
+                .any(|desc| desc.name == updated.name)
+        })?;
+        let mut desc = tables
+            .table(table)
+            .values()
+            .find(|desc| desc.name == updated.name)
+            .cloned()?;
+
+        match &mut desc.value {
+            RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+                let Some(bits) = description_read_bool(updated) else {
+                    return Some(Err(ExceptionCode::IllegalDataValue));
+                };
+                constraints
+                    .val
+                    .resize((constraints.max_bits as usize).div_ceil(8), 0);
+                constraints.set_bits(0, &bits[..bits.len().min(constraints.max_bits as usize)]);
+            }
+            _ => {
+                let Some(values) = description_read_u16(updated) else {
+                    return Some(Err(ExceptionCode::IllegalDataValue));
+                };
+                if let Err(err) = description_write_u16(&mut desc, &values) {
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        tables.table_mut(table).insert(desc.address, desc.clone());
+        self.fire_rules(&mut tables, table, &[desc.address]);
+        let desc = tables.table(table)[&desc.address].clone();
+        Some(Ok((table, desc)))
+    }
+
+    /// Every description with a `simulation` section.
+    pub fn simulations(&self) -> Vec<(RegisterTable, RegisterDescription)> {
+        let tables = self.tables.lock().unwrap();
+        RegisterTable::ALL
+            .iter()
+            .flat_map(|&table| {
+                tables
+                    .table(table)
+                    .values()
+                    .filter(|desc| desc.simulation.is_some())
+                    .map(move |desc| (table, desc.clone()))
+            })
+            .collect()
+    }
+
+    pub fn dispatch(&self, request: Request<'static>) -> Result<Response, ExceptionCode> {
+        let fill_holes = self.fill_holes;
+        let mut tables = self.tables.lock().unwrap();
+        match request {
             // read/write coils
             Request::ReadCoils(addr, quantity) => {
-                register_read_bool(&self.coils.lock().unwrap(), addr, quantity)
+                register_read_bool(&tables.coils, addr, quantity, fill_holes)
                     .map(Response::ReadCoils)
             }
-            Request::WriteSingleCoil(addr, value) => register_write_bool(
-                &mut self.coils.lock().unwrap(),
-                addr,
-                std::slice::from_ref(&value),
-            )
-            .map(|_| Response::WriteSingleCoil(addr, value)),
+            Request::WriteSingleCoil(addr, value) => {
+                let unlocks = tables.unlocks();
+                register_write_bool(
+                    &mut tables.coils,
+                    addr,
+                    std::slice::from_ref(&value),
+                    &unlocks,
+                    fill_holes,
+                )
+                .map(|written| {
+                    self.fire_rules(&mut tables, RegisterTable::Coils, &written);
+                    Response::WriteSingleCoil(addr, value)
+                })
+            }
             Request::WriteMultipleCoils(addr, values) => {
-                register_write_bool(&mut self.coils.lock().unwrap(), addr, &values)
-                    .map(|_| Response::WriteMultipleCoils(addr, values.len() as u16))
+                let unlocks = tables.unlocks();
+                register_write_bool(&mut tables.coils, addr, &values, &unlocks, fill_holes).map(
+                    |written| {
+                        self.fire_rules(&mut tables, RegisterTable::Coils, &written);
+                        Response::WriteMultipleCoils(addr, values.len() as u16)
+                    },
+                )
             }
             // read discrete inputs
             Request::ReadDiscreteInputs(addr, cnt) => {
-                register_read_bool(&self.discrete_inputs.lock().unwrap(), addr, cnt)
+                register_read_bool(&tables.discrete_inputs, addr, cnt, fill_holes)
                     .map(Response::ReadDiscreteInputs)
             }
             // read input registers
             Request::ReadInputRegisters(addr, cnt) => {
-                register_read_u16(&self.input_registers.lock().unwrap(), addr, cnt)
+                register_read_u16(&tables.input_registers, addr, cnt, fill_holes)
                     .map(Response::ReadInputRegisters)
             }
             // read/write holding registers
             Request::ReadHoldingRegisters(addr, cnt) => {
-                register_read_u16(&self.holding_registers.lock().unwrap(), addr, cnt)
+                register_read_u16(&tables.holding_registers, addr, cnt, fill_holes)
                     .map(Response::ReadHoldingRegisters)
             }
-            Request::WriteSingleRegister(addr, value) => register_write_u16(
-                &mut self.holding_registers.lock().unwrap(),
-                addr,
-                std::slice::from_ref(&value),
-            )
-            .map(|_| Response::WriteSingleRegister(addr, value)),
+            Request::WriteSingleRegister(addr, value) => {
+                let unlocks = tables.unlocks();
+                register_write_u16(
+                    &mut tables.holding_registers,
+                    addr,
+                    std::slice::from_ref(&value),
+                    &unlocks,
+                    fill_holes,
+                )
+                .map(|written| {
+                    self.fire_rules(&mut tables, RegisterTable::HoldingRegisters, &written);
+                    Response::WriteSingleRegister(addr, value)
+                })
+            }
             Request::WriteMultipleRegisters(addr, values) => {
-                register_write_u16(&mut self.holding_registers.lock().unwrap(), addr, &values)
-                    .map(|_| Response::WriteMultipleRegisters(addr, values.len() as u16))
+                let unlocks = tables.unlocks();
+                register_write_u16(
+                    &mut tables.holding_registers,
+                    addr,
+                    &values,
+                    &unlocks,
+                    fill_holes,
+                )
+                .map(|written| {
+                    self.fire_rules(&mut tables, RegisterTable::HoldingRegisters, &written);
+                    Response::WriteMultipleRegisters(addr, values.len() as u16)
+                })
+            }
+            Request::MaskWriteRegister(addr, and_mask, or_mask) => {
+                let unlocks = tables.unlocks();
+                register_mask_write_u16(
+                    &mut tables.holding_registers,
+                    addr,
+                    and_mask,
+                    or_mask,
+                    &unlocks,
+                    fill_holes,
+                )
+                .map(|written| {
+                    self.fire_rules(&mut tables, RegisterTable::HoldingRegisters, &written);
+                    Response::MaskWriteRegister(addr, and_mask, or_mask)
+                })
+            }
+            Request::ReadWriteMultipleRegisters(read_addr, read_cnt, write_addr, values) => {
+                // both quantities are checked before anything is written
+                check_quantity(read_cnt, MAX_READ_REGISTERS)?;
+                check_quantity(values.len() as u16, MAX_READ_WRITE_REGISTERS)?;
+                // the write is performed before the read
+                let unlocks = tables.unlocks();
+                register_write_u16(
+                    &mut tables.holding_registers,
+                    write_addr,
+                    &values,
+                    &unlocks,
+                    fill_holes,
+                )
+                .and_then(|written| {
+                    self.fire_rules(&mut tables, RegisterTable::HoldingRegisters, &written);
+                    register_read_u16(&tables.holding_registers, read_addr, read_cnt, fill_holes)
+                })
+                .map(Response::ReadWriteMultipleRegisters)
             }
             _ => {
                 tracing::error!("SERVER: Exception::IllegalFunction - Unimplemented function code in request: {:?}", request);
                 Err(ExceptionCode::IllegalFunction)
             }
+        }
+    }
+
+    /// Fire the rules of the descriptions of `table` starting at `written`,
+    /// just written by a request. Actions store their values regardless of
+    /// the access of the target and fire no further rules.
+    fn fire_rules(&self, tables: &mut RegisterTables, table: RegisterTable, written: &[u16]) {
+        for start in written {
+            let desc = &tables.table(table)[start];
+            if desc.rules.is_empty() {
+                continue;
+            }
+            let desc = desc.clone();
+            let value = desc.value.numeric();
+            for rule in &desc.rules {
+                if let Some(when) = &rule.when {
+                    if value.is_none() || when.resolve(&desc.value) != value {
+                        continue;
+                    }
+                }
+                tracing::info!(
+                    "rule(name: {}, when: {:?}) fired by {:?}",
+                    desc.name,
+                    rule.when,
+                    value
+                );
+                for action in &rule.actions {
+                    self.run_action(tables, value, action);
+                }
+            }
+        }
+    }
+
+    fn run_action(&self, tables: &mut RegisterTables, written: Option<f64>, action: &Action) {
+        let target = action.target().to_string();
+        let generation = self.supersede(&target);
+        let (value, delay_ms) = match action {
+            Action::Set {
+                value, delay_ms, ..
+            } => {
+                let value = tables
+                    .named(&target)
+                    .and_then(|desc| value.resolve(&desc.value));
+                (value, *delay_ms)
+            }
+            Action::Copy { from, delay_ms, .. } => {
+                let value = match from {
+                    Some(from) => tables.named(from).and_then(|desc| desc.value.numeric()),
+                    None => written,
+                };
+                (value, *delay_ms)
+            }
+            Action::Ramp {
+                step, interval_ms, ..
+            } => {
+                let Some(goal) = written else {
+                    return;
+                };
+                let interval = Duration::from_millis(*interval_ms);
+                tokio::spawn(self.clone().ramp(target, goal, *step, interval, generation));
+                return;
+            }
+        };
+        let Some(value) = value else {
+            tracing::warn!(
+                "rule(target: {}) skipped, no value for {:?}",
+                target,
+                action
+            );
+            return;
         };
-        future::ready(result)
+
+        match delay_ms {
+            Some(delay_ms) => {
+                let data = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                    let mut tables = data.tables.lock().unwrap();
+                    if data.is_current(&target, generation) {
+                        store(&mut tables, &target, value);
+                    }
+                });
+            }
+            None => {
+                store(tables, &target, value);
+            }
+        }
+    }
+
+    /// Step the description `target` toward `goal` until it is reached, it
+    /// stops moving or a newer action takes over.
+    async fn ramp(self, target: String, goal: f64, step: f64, interval: Duration, generation: u64) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let mut tables = self.tables.lock().unwrap();
+            if !self.is_current(&target, generation) {
+                return;
+            }
+            let Some(current) = tables.named(&target).and_then(|desc| desc.value.numeric()) else {
+                return;
+            };
+            let next = if (goal - current).abs() <= step {
+                goal
+            } else {
+                current + step.copysign(goal - current)
+            };
+            if !store(&mut tables, &target, next) || next == goal {
+                return;
+            }
+            // integer types round a step smaller than one away
+            if tables.named(&target).and_then(|desc| desc.value.numeric()) == Some(current) {
+                return;
+            }
+        }
+    }
+
+    /// Start a new action on `target`, returning its generation.
+    fn supersede(&self, target: &str) -> u64 {
+        let mut transitions = self.transitions.lock().unwrap();
+        let generation = transitions.entry(target.to_string()).or_default();
+        *generation += 1;
+        *generation
+    }
+
+    fn is_current(&self, target: &str, generation: u64) -> bool {
+        self.transitions.lock().unwrap().get(target) == Some(&generation)
+    }
+}
+
+/// Store the raw `value` into the description called `target`, `false` if
+/// there is none or its constraints reject the value.
+fn store(tables: &mut RegisterTables, target: &str, value: f64) -> bool {
+    let Some(table) = RegisterTable::ALL
+        .into_iter()
+        .find(|&table| tables.table(table).values().any(|desc| desc.name == target))
+    else {
+        return false;
+    };
+    let desc = tables
+        .table_mut(table)
+        .values_mut()
+        .find(|desc| desc.name == target)
+        .unwrap();
+    let mut updated = desc.clone();
+    if !updated.value.set_numeric(value) || updated.validate_value().is_err() {
+        tracing::warn!(
+            "rule(target: {}, table: {}) rejected {}",
+            target,
+            table,
+            value
+        );
+        return false;
+    }
+    tracing::info!("rule(target: {}, table: {}) -> {}", target, table, value);
+    *desc = updated;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "h_command"
+        address = 0
+        count = 1
+        value.U16.default = 0
+
+        [[holding_registers.rules]]
+        when = 1
+        actions = [
+            { Set = { target = "h_status", value = 10 } },
+            { Set = { target = "h_status", value = 20, delay_ms = 50 } },
+        ]
+
+        [[holding_registers.rules]]
+        when = 2
+        actions = [
+            { Copy = { target = "h_echo" } },
+            { Copy = { target = "h_status", from = "h_source" } },
+        ]
+
+        [[holding_registers.rules]]
+        when = 3
+        actions = [{ Set = { target = "h_command", value = 4 } }]
+
+        [[holding_registers.rules]]
+        when = 4
+        actions = [{ Set = { target = "h_status", value = 30 } }]
+
+        [[holding_registers]]
+        name = "h_status"
+        address = 1
+        count = 1
+        value.U16.default = 0
+
+        [[holding_registers.rules]]
+        actions = [{ Set = { target = "h_echo", value = 99 } }]
+
+        [[holding_registers]]
+        name = "h_echo"
+        address = 2
+        count = 1
+        value.U16.default = 0
+
+        [[holding_registers]]
+        name = "h_source"
+        address = 3
+        count = 1
+        value.U16.default = 33
+
+        [[holding_registers]]
+        name = "h_setpoint"
+        address = 4
+        count = 1
+        value.U16.default = 0
+
+        [[holding_registers.rules]]
+        actions = [{ Ramp = { target = "h_speed", step = 10, interval_ms = 5 } }]
+
+        [[holding_registers]]
+        name = "h_speed"
+        address = 5
+        count = 1
+        value.U16.default = 0
+    "#;
+
+    fn data() -> ModbusServiceData {
+        ModbusServiceData::new(toml::from_str(SCHEMA).unwrap(), false)
+    }
+
+    fn write(data: &ModbusServiceData, address: u16, value: u16) {
+        data.dispatch(Request::WriteSingleRegister(address, value))
+            .unwrap();
+    }
+
+    fn value(data: &ModbusServiceData, name: &str) -> Option<f64> {
+        data.with_named(name, |_table, desc| desc.value.numeric())
+            .flatten()
+    }
+
+    /// Wait for the value of `name` to pass `done`, panicking after a second.
+    async fn settles(data: &ModbusServiceData, name: &str, done: impl Fn(f64) -> bool) {
+        let wait = async {
+            while !value(data, name).is_some_and(&done) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), wait)
+            .await
+            .unwrap_or_else(|_| panic!("{name} stuck at {:?}", value(data, name)));
+    }
+
+    #[tokio::test]
+    async fn set_actions_store_now_and_after_their_delay() {
+        let data = data();
+        write(&data, 0, 1);
+        assert_eq!(value(&data, "h_status"), Some(10.0));
+        settles(&data, "h_status", |status| status == 20.0).await;
+    }
+
+    #[tokio::test]
+    async fn copy_actions_store_the_written_or_the_named_value() {
+        let data = data();
+        write(&data, 0, 2);
+        assert_eq!(value(&data, "h_echo"), Some(2.0));
+        assert_eq!(value(&data, "h_status"), Some(33.0));
+    }
+
+    #[tokio::test]
+    async fn rules_only_fire_on_their_value() {
+        let data = data();
+        write(&data, 0, 7);
+        assert_eq!(value(&data, "h_status"), Some(0.0));
+        assert_eq!(value(&data, "h_echo"), Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn actions_fire_no_further_rules() {
+        let data = data();
+        // h_command set to 4 by its own rule, the rule of 4 stays silent
+        write(&data, 0, 3);
+        assert_eq!(value(&data, "h_command"), Some(4.0));
+        assert_eq!(value(&data, "h_status"), Some(0.0));
+        // h_status written by an action, its rule stays silent
+        write(&data, 0, 4);
+        assert_eq!(value(&data, "h_status"), Some(30.0));
+        assert_eq!(value(&data, "h_echo"), Some(0.0));
+        // written by a request, it fires
+        write(&data, 1, 5);
+        assert_eq!(value(&data, "h_echo"), Some(99.0));
+    }
+
+    #[tokio::test]
+    async fn newer_actions_drop_pending_delayed_ones() {
+        let data = data();
+        write(&data, 0, 1);
+        write(&data, 0, 4);
+        assert_eq!(value(&data, "h_status"), Some(30.0));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(value(&data, "h_status"), Some(30.0));
+    }
+
+    #[tokio::test]
+    async fn ramps_step_to_the_written_value() {
+        let data = data();
+        write(&data, 4, 45);
+        // one step at most per interval
+        assert!(value(&data, "h_speed").unwrap() <= 10.0);
+        settles(&data, "h_speed", |speed| speed == 45.0).await;
+    }
+
+    #[tokio::test]
+    async fn newer_writes_take_over_a_pending_ramp() {
+        let data = data();
+        write(&data, 4, 1000);
+        settles(&data, "h_speed", |speed| speed >= 50.0).await;
+        write(&data, 4, 0);
+        settles(&data, "h_speed", |speed| speed == 0.0).await;
+        // the first ramp is gone, nothing moves the speed up again
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(value(&data, "h_speed"), Some(0.0));
     }
 }
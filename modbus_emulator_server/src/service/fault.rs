@@ -0,0 +1,427 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use config_file_derives::ConfigFile;
+use config_file_types;
+
+use modbus_register_schema::*;
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use tokio_modbus::prelude::*;
+
+use tracing;
+
+use super::data::ModbusServiceData;
+
+/// Faults injected in front of the register images.
+///
+/// Rules are only applied while `enabled` is set, the file is reloaded when
+/// it changes so faults can be switched on and off at runtime.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ConfigFile)]
+#[config_file_ext("toml")]
+pub struct FaultConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faults: Vec<FaultRule>,
+
+    #[serde(skip)]
+    pub path: String,
+}
+
+/// A fault and the requests it hits, every filter left out matches all of
+/// them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FaultRule {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unit_ids: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub function_codes: Vec<u8>,
+    /// first and last address of the requests hit, in any table
+    pub addresses: Option<[u16; 2]>,
+    /// register description whose addresses the requests hit
+    pub register: Option<String>,
+    /// chance to fire per matching request, always if unset
+    pub probability: Option<f64>,
+    pub action: FaultAction,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FaultAction {
+    /// answer with an exception instead of executing the request
+    Exception(FaultException),
+    /// respond after a random delay, adds up over matching rules
+    Delay { min_ms: u64, max_ms: u64 },
+    /// execute the request without responding
+    Drop,
+    /// execute the request and respond with a broken crc, rtu only
+    CorruptCrc,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum FaultException {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    #[serde(alias = "SlaveDeviceFailure")]
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    MemoryParityError,
+    GatewayPathUnavailable,
+    GatewayTargetDevice,
+}
+
+impl From<FaultException> for ExceptionCode {
+    fn from(exception: FaultException) -> Self {
+        match exception {
+            FaultException::IllegalFunction => ExceptionCode::IllegalFunction,
+            FaultException::IllegalDataAddress => ExceptionCode::IllegalDataAddress,
+            FaultException::IllegalDataValue => ExceptionCode::IllegalDataValue,
+            FaultException::ServerDeviceFailure => ExceptionCode::ServerDeviceFailure,
+            FaultException::Acknowledge => ExceptionCode::Acknowledge,
+            FaultException::ServerDeviceBusy => ExceptionCode::ServerDeviceBusy,
+            FaultException::MemoryParityError => ExceptionCode::MemoryParityError,
+            FaultException::GatewayPathUnavailable => ExceptionCode::GatewayPathUnavailable,
+            FaultException::GatewayTargetDevice => ExceptionCode::GatewayTargetDevice,
+        }
+    }
+}
+
+impl FaultConfig {
+    /// Check every rule, probabilities must be within `[0, 1]` and delay
+    /// ranges not inverted.
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.faults {
+            if let Some(probability) = rule.probability {
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(format!(
+                        "fault {}: probability {} not within [0, 1]",
+                        rule.name, probability
+                    ));
+                }
+            }
+            if let FaultAction::Delay { min_ms, max_ms } = rule.action {
+                if min_ms > max_ms {
+                    return Err(format!(
+                        "fault {}: delay min_ms {} > max_ms {}",
+                        rule.name, min_ms, max_ms
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happens to a request once the delays of the firing rules passed.
+enum Outcome {
+    Execute,
+    Exception(FaultException),
+    Drop,
+    CorruptCrc,
+}
+
+/// Outcome of a request passed through the faults, `result` is `None` for a
+/// dropped response.
+pub struct FaultReply {
+    pub result: Option<Result<Response, ExceptionCode>>,
+    pub corrupt_crc: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector {
+    config: Arc<RwLock<FaultConfig>>,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    /// Reload the config file whenever it is modified.
+    pub fn watch(&self) {
+        let config = self.config.clone();
+        let path = config.read().unwrap().path.clone();
+        tokio::spawn(async move {
+            let modified = || {
+                std::fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+            };
+            let mut last: Option<SystemTime> = modified();
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let current = modified();
+                if current == last {
+                    continue;
+                }
+                last = current;
+                if let Some(reloaded) = FaultConfig::load(&path, false) {
+                    if let Err(err) = reloaded.validate() {
+                        tracing::error!("faults not reloaded(path: {}) - {}", path, err);
+                        continue;
+                    }
+                    tracing::info!(
+                        "faults reloaded(path: {}, enabled: {}, rules: {})",
+                        path,
+                        reloaded.enabled,
+                        reloaded.faults.len()
+                    );
+                    *config.write().unwrap() = reloaded;
+                }
+            }
+        });
+    }
+
+    /// Dispatch `request` to the register image `data` of `unit`, applying
+    /// every enabled fault rule it matches.
+    pub async fn dispatch(
+        &self,
+        unit: u8,
+        data: &ModbusServiceData,
+        request: Request<'static>,
+    ) -> FaultReply {
+        let (delay, outcome) = self.plan(unit, data, &request);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        match outcome {
+            Outcome::Execute => FaultReply {
+                result: Some(data.dispatch(request)),
+                corrupt_crc: false,
+            },
+            Outcome::Exception(exception) => FaultReply {
+                result: Some(Err(exception.into())),
+                corrupt_crc: false,
+            },
+            Outcome::Drop => {
+                // executed like a request whose response got lost on the way
+                let _ = data.dispatch(request);
+                FaultReply {
+                    result: None,
+                    corrupt_crc: false,
+                }
+            }
+            Outcome::CorruptCrc => FaultReply {
+                result: Some(data.dispatch(request)),
+                corrupt_crc: true,
+            },
+        }
+    }
+
+    /// Total delay and the outcome of the first other action of the rules
+    /// firing for `request`.
+    fn plan(
+        &self,
+        unit: u8,
+        data: &ModbusServiceData,
+        request: &Request<'static>,
+    ) -> (Duration, Outcome) {
+        let config = self.config.read().unwrap();
+        let mut delay = Duration::ZERO;
+        let mut outcome = Outcome::Execute;
+        if !config.enabled {
+            return (delay, outcome);
+        }
+
+        let function = request.function_code().value();
+        let ranges = request_ranges(request);
+        let mut rng = rand::thread_rng();
+        for rule in &config.faults {
+            if !rule.matches(unit, function, &ranges, data) {
+                continue;
+            }
+            if rule
+                .probability
+                .is_some_and(|probability| !rng.gen_bool(probability))
+            {
+                continue;
+            }
+
+            tracing::warn!(
+                "FAULT(name: {}, unit: {}, function: {:#04x}) -> {:?}",
+                rule.name,
+                unit,
+                function,
+                rule.action
+            );
+            match &rule.action {
+                FaultAction::Delay { min_ms, max_ms } => {
+                    let ms = rng.gen_range(*min_ms..=*max_ms);
+                    delay += Duration::from_millis(ms);
+                }
+                // the first other action wins
+                _ if !matches!(outcome, Outcome::Execute) => {}
+                FaultAction::Exception(exception) => outcome = Outcome::Exception(*exception),
+                FaultAction::Drop => outcome = Outcome::Drop,
+                FaultAction::CorruptCrc => outcome = Outcome::CorruptCrc,
+            }
+        }
+        (delay, outcome)
+    }
+}
+
+impl FaultRule {
+    fn matches(
+        &self,
+        unit: u8,
+        function: u8,
+        ranges: &[(RegisterTable, u16, u16)],
+        data: &ModbusServiceData,
+    ) -> bool {
+        if !self.unit_ids.is_empty() && !self.unit_ids.contains(&unit) {
+            return false;
+        }
+        if !self.function_codes.is_empty() && !self.function_codes.contains(&function) {
+            return false;
+        }
+        if let Some([first, last]) = self.addresses {
+            let span = (last as u32 + 1).saturating_sub(first as u32);
+            if !ranges
+                .iter()
+                .any(|&(_table, addr, cnt)| overlaps(first, span, addr, cnt as u32))
+            {
+                return false;
+            }
+        }
+        if let Some(name) = &self.register {
+            let Some((table, start, span)) = data.locate(name) else {
+                return false;
+            };
+            if !ranges.iter().any(|&(request_table, addr, cnt)| {
+                request_table == table && overlaps(start, span as u32, addr, cnt as u32)
+            }) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Table and address ranges a request touches.
+fn request_ranges(request: &Request<'static>) -> Vec<(RegisterTable, u16, u16)> {
+    match request {
+        Request::ReadCoils(addr, cnt) => vec![(RegisterTable::Coils, *addr, *cnt)],
+        Request::WriteSingleCoil(addr, _) => vec![(RegisterTable::Coils, *addr, 1)],
+        Request::WriteMultipleCoils(addr, values) => {
+            vec![(RegisterTable::Coils, *addr, values.len() as u16)]
+        }
+        Request::ReadDiscreteInputs(addr, cnt) => {
+            vec![(RegisterTable::DiscreteInputs, *addr, *cnt)]
+        }
+        Request::ReadInputRegisters(addr, cnt) => {
+            vec![(RegisterTable::InputRegisters, *addr, *cnt)]
+        }
+        Request::ReadHoldingRegisters(addr, cnt) => {
+            vec![(RegisterTable::HoldingRegisters, *addr, *cnt)]
+        }
+        Request::WriteSingleRegister(addr, _) | Request::MaskWriteRegister(addr, _, _) => {
+            vec![(RegisterTable::HoldingRegisters, *addr, 1)]
+        }
+        Request::WriteMultipleRegisters(addr, values) => {
+            vec![(RegisterTable::HoldingRegisters, *addr, values.len() as u16)]
+        }
+        Request::ReadWriteMultipleRegisters(read_addr, read_cnt, write_addr, values) => vec![
+            (RegisterTable::HoldingRegisters, *read_addr, *read_cnt),
+            (
+                RegisterTable::HoldingRegisters,
+                *write_addr,
+                values.len() as u16,
+            ),
+        ],
+        _ => vec![],
+    }
+}
+
+fn overlaps(start: u16, span: u32, addr: u16, cnt: u32) -> bool {
+    (start as u32) < addr as u32 + cnt && (addr as u32) < start as u32 + span
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn data() -> ModbusServiceData {
+        let schema = toml::from_str(
+            "[[holding_registers]]\nname = \"h_setpoint\"\naddress = 0\ncount = 1\nvalue.U16.default = 1\n",
+        )
+        .unwrap();
+        ModbusServiceData::new(schema, false)
+    }
+
+    fn injector(action: &str) -> FaultInjector {
+        let config: FaultConfig = toml::from_str(&format!(
+            "enabled = true\n[[faults]]\nname = \"test\"\nregister = \"h_setpoint\"\naction = {action}\n"
+        ))
+        .unwrap();
+        config.validate().unwrap();
+        FaultInjector::new(config)
+    }
+
+    fn setpoint(data: &ModbusServiceData) -> Result<Response, ExceptionCode> {
+        data.dispatch(Request::ReadHoldingRegisters(0, 1))
+    }
+
+    #[tokio::test]
+    async fn dropped_requests_are_executed_without_a_response() {
+        let data = data();
+        let reply = injector("\"Drop\"")
+            .dispatch(1, &data, Request::WriteSingleRegister(0, 7))
+            .await;
+        assert!(reply.result.is_none());
+        assert_eq!(setpoint(&data), Ok(Response::ReadHoldingRegisters(vec![7])));
+    }
+
+    #[tokio::test]
+    async fn exceptions_replace_the_request() {
+        let data = data();
+        let reply = injector("{ Exception = \"ServerDeviceBusy\" }")
+            .dispatch(
+                1,
+                &data,
+                Request::WriteMultipleRegisters(0, Cow::Owned(vec![7])),
+            )
+            .await;
+        assert_eq!(reply.result, Some(Err(ExceptionCode::ServerDeviceBusy)));
+        assert_eq!(setpoint(&data), Ok(Response::ReadHoldingRegisters(vec![1])));
+    }
+
+    #[tokio::test]
+    async fn corrupt_crc_executes_and_flags_the_reply() {
+        let data = data();
+        let reply = injector("\"CorruptCrc\"")
+            .dispatch(1, &data, Request::WriteSingleRegister(0, 7))
+            .await;
+        assert!(reply.corrupt_crc);
+        assert_eq!(reply.result, Some(Ok(Response::WriteSingleRegister(0, 7))));
+    }
+
+    #[tokio::test]
+    async fn disabled_faults_and_other_registers_are_left_alone() {
+        let data = data();
+        let mut config: FaultConfig = toml::from_str("[[faults]]\naction = \"Drop\"\n").unwrap();
+        let reply = FaultInjector::new(config.clone())
+            .dispatch(1, &data, Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert!(reply.result.is_some());
+
+        config.enabled = true;
+        config.faults[0].register = Some("h_other".to_string());
+        let reply = FaultInjector::new(config)
+            .dispatch(1, &data, Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert!(reply.result.is_some());
+    }
+}
@@ -1,3 +1,8 @@
 pub mod data;
+pub mod fault;
+pub mod role;
 pub mod rtu;
+pub mod simulation;
+pub mod state;
 pub mod tcp;
+pub mod units;
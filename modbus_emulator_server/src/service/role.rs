@@ -0,0 +1,189 @@
+use config_file_derives::ConfigFile;
+use config_file_types;
+
+use modbus_register_schema::*;
+
+use serde::{Deserialize, Serialize};
+
+use tokio_modbus::prelude::*;
+
+/// Access of the roles carried by client certificates, see `--tls-roles`.
+///
+/// A client whose certificate has no role, or one not listed here, may
+/// access no table at all.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ConfigFile)]
+#[config_file_ext("toml")]
+pub struct RoleConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<RoleAccess>,
+
+    #[serde(skip)]
+    pub path: String,
+}
+
+/// Access of one role to each table, none to the tables left out.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RoleAccess {
+    pub role: String,
+    #[serde(default)]
+    pub coils: TableAccess,
+    #[serde(default)]
+    pub discrete_inputs: TableAccess,
+    #[serde(default)]
+    pub input_registers: TableAccess,
+    #[serde(default)]
+    pub holding_registers: TableAccess,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TableAccess {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "ro")]
+    ReadOnly,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+impl RoleConfig {
+    /// Check that no role is listed twice.
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, access) in self.roles.iter().enumerate() {
+            if self.roles[..i]
+                .iter()
+                .any(|other| other.role == access.role)
+            {
+                return Err(format!("role {} is configured twice", access.role));
+            }
+        }
+        Ok(())
+    }
+
+    /// Access of `role`, none to every table for an unknown or missing role.
+    pub fn access(&self, role: Option<&str>) -> RoleAccess {
+        role.and_then(|role| self.roles.iter().find(|access| access.role == role))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl RoleAccess {
+    /// Full access for a client authenticated without role checks.
+    pub fn read_write(role: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            coils: TableAccess::ReadWrite,
+            discrete_inputs: TableAccess::ReadWrite,
+            input_registers: TableAccess::ReadWrite,
+            holding_registers: TableAccess::ReadWrite,
+        }
+    }
+
+    pub fn table(&self, table: RegisterTable) -> TableAccess {
+        match table {
+            RegisterTable::Coils => self.coils,
+            RegisterTable::DiscreteInputs => self.discrete_inputs,
+            RegisterTable::InputRegisters => self.input_registers,
+            RegisterTable::HoldingRegisters => self.holding_registers,
+        }
+    }
+
+    /// Whether the role may send `request`. Mask writes and read/write
+    /// requests count as writes, requests on no table are left to the
+    /// device to answer.
+    pub fn permits(&self, request: &Request<'static>) -> bool {
+        let Some((table, write)) = request_access(request) else {
+            return true;
+        };
+        match self.table(table) {
+            TableAccess::None => false,
+            TableAccess::ReadOnly => !write,
+            TableAccess::ReadWrite => true,
+        }
+    }
+}
+
+/// Table a request touches and whether it writes to it.
+fn request_access(request: &Request<'static>) -> Option<(RegisterTable, bool)> {
+    match request {
+        Request::ReadCoils(..) => Some((RegisterTable::Coils, false)),
+        Request::WriteSingleCoil(..) | Request::WriteMultipleCoils(..) => {
+            Some((RegisterTable::Coils, true))
+        }
+        Request::ReadDiscreteInputs(..) => Some((RegisterTable::DiscreteInputs, false)),
+        Request::ReadInputRegisters(..) => Some((RegisterTable::InputRegisters, false)),
+        Request::ReadHoldingRegisters(..) => Some((RegisterTable::HoldingRegisters, false)),
+        Request::WriteSingleRegister(..)
+        | Request::WriteMultipleRegisters(..)
+        | Request::MaskWriteRegister(..)
+        | Request::ReadWriteMultipleRegisters(..) => Some((RegisterTable::HoldingRegisters, true)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn config() -> RoleConfig {
+        toml::from_str(
+            r#"
+            [[roles]]
+            role = "operator"
+            input_registers = "ro"
+            holding_registers = "rw"
+
+            [[roles]]
+            role = "viewer"
+            holding_registers = "ro"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn read_only_role_reads_but_does_not_write() {
+        let viewer = config().access(Some("viewer"));
+        assert!(viewer.permits(&Request::ReadHoldingRegisters(0, 1)));
+        assert!(!viewer.permits(&Request::WriteSingleRegister(0, 1)));
+        assert!(!viewer.permits(&Request::MaskWriteRegister(0, 0xff, 0)));
+        assert!(!viewer.permits(&Request::ReadWriteMultipleRegisters(
+            0,
+            1,
+            0,
+            Cow::Owned(vec![1])
+        )));
+    }
+
+    #[test]
+    fn read_write_role_writes() {
+        let operator = config().access(Some("operator"));
+        assert!(operator.permits(&Request::WriteMultipleRegisters(0, Cow::Owned(vec![1, 2]))));
+        assert!(operator.permits(&Request::ReadInputRegisters(0, 1)));
+    }
+
+    #[test]
+    fn tables_left_out_are_denied() {
+        let operator = config().access(Some("operator"));
+        assert!(!operator.permits(&Request::ReadCoils(0, 1)));
+        assert!(!operator.permits(&Request::ReadDiscreteInputs(0, 1)));
+    }
+
+    #[test]
+    fn unknown_and_missing_roles_have_no_access() {
+        for role in [Some("admin"), None] {
+            let access = config().access(role);
+            assert!(!access.permits(&Request::ReadHoldingRegisters(0, 1)));
+        }
+    }
+
+    #[test]
+    fn duplicate_roles_are_rejected() {
+        let mut config = config();
+        config.roles.push(RoleAccess::read_write("viewer"));
+        assert!(config.validate().is_err());
+    }
+}
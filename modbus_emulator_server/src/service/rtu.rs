@@ -1,30 +1,73 @@
-use std::future;
+use tokio_modbus::prelude::*;
 
-use modbus_register_schema::*;
+use tracing;
 
-use tokio_modbus::prelude::*;
+use super::fault::FaultInjector;
+use super::units::ModbusServiceUnits;
+use crate::frame::pdu::{encode_exception, encode_response};
 
-use super::data::ModbusServiceData;
+/// Broadcast slave id, executed by every device without a response.
+const BROADCAST: u8 = 0;
 
-pub struct ModbusEmulatorRtuService {
-    pub data: ModbusServiceData,
+/// Response pdu to send back for one rtu request, `None` to stay silent.
+pub struct RtuReply {
+    pub pdu: Option<Vec<u8>>,
+    pub corrupt_crc: bool,
 }
 
-impl ModbusEmulatorRtuService {
-    pub fn new(schema: RegisterSchema) -> Self {
+impl RtuReply {
+    fn silent() -> Self {
         Self {
-            data: ModbusServiceData::new(schema),
+            pdu: None,
+            corrupt_crc: false,
         }
     }
 }
 
-impl tokio_modbus::server::Service for ModbusEmulatorRtuService {
-    type Request = SlaveRequest<'static>;
-    type Response = Response;
-    type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+#[derive(Clone)]
+pub struct ModbusEmulatorRtuService {
+    pub units: ModbusServiceUnits,
+    pub faults: FaultInjector,
+}
+
+impl ModbusEmulatorRtuService {
+    pub fn new(units: ModbusServiceUnits, faults: FaultInjector) -> Self {
+        Self { units, faults }
+    }
+
+    /// Answer a request that does not decode with `exception`, unless it was
+    /// broadcast or is for a device missing on the bus.
+    pub fn reject(&self, slave: u8, function: u8, exception: ExceptionCode) -> RtuReply {
+        if slave == BROADCAST || self.units.get(slave).is_none() {
+            return RtuReply::silent();
+        }
+        RtuReply {
+            pdu: Some(encode_exception(function, exception)),
+            corrupt_crc: false,
+        }
+    }
+
+    pub async fn call(&self, slave: u8, function: u8, request: Request<'static>) -> RtuReply {
+        if slave == BROADCAST {
+            for (_unit, data) in self.units.iter() {
+                let _ = data.dispatch(request.clone());
+            }
+            return RtuReply::silent();
+        }
 
-    fn call(&self, req: Self::Request) -> Self::Future {
-        self.data.dispatch(req.request)
+        // devices missing on the bus stay silent
+        let Some(data) = self.units.get(slave) else {
+            tracing::warn!("SERVER: no device with slave id {}", slave);
+            return RtuReply::silent();
+        };
+        let reply = self.faults.dispatch(slave, data, request).await;
+        let pdu = reply.result.map(|result| match result {
+            Ok(response) => encode_response(&response),
+            Err(exception) => encode_exception(function, exception),
+        });
+        RtuReply {
+            pdu,
+            corrupt_crc: reply.corrupt_crc,
+        }
     }
 }
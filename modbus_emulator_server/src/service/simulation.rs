@@ -0,0 +1,295 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use modbus_register_schema::*;
+
+use rand::Rng;
+
+use tokio::time::{Instant, MissedTickBehavior};
+
+use tracing;
+
+use super::data::ModbusServiceData;
+
+/// Start one timer task per description with a `simulation` section, each
+/// storing its generated values into the shared register image. The schema
+/// is validated beforehand, simulations of bytes, strings or with invalid
+/// generators do not get here.
+pub fn spawn_simulations(data: &ModbusServiceData) {
+    for (table, desc) in data.simulations() {
+        let Some(simulation) = desc.simulation else {
+            continue;
+        };
+        tracing::info!(
+            "simulation(name: {}, table: {}, addr: {}, interval_ms: {}) -> {:?}",
+            desc.name,
+            table,
+            desc.address,
+            simulation.interval_ms,
+            simulation.generator
+        );
+        tokio::spawn(run_simulation(
+            data.clone(),
+            table,
+            desc.address,
+            desc.name,
+            simulation,
+        ));
+    }
+}
+
+async fn run_simulation(
+    data: ModbusServiceData,
+    table: RegisterTable,
+    address: u16,
+    name: String,
+    simulation: Simulation,
+) {
+    let mut state = GeneratorState::new(&simulation.generator);
+    let mut interval = tokio::time::interval(Duration::from_millis(simulation.interval_ms));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let started = Instant::now();
+
+    loop {
+        interval.tick().await;
+        let Some(value) = state.next(&simulation.generator, started.elapsed()) else {
+            continue;
+        };
+        let stored = data.with_description(table, address, |desc| store(desc, value));
+        tracing::debug!(
+            "simulate(name: {}, table: {}, addr: {}) -> {} (stored: {:?})",
+            name,
+            table,
+            address,
+            value,
+            stored
+        );
+    }
+}
+
+/// Store the generated `value` into `desc`, `false` if it does not fit the
+/// type or its constraints reject it, like a client write would be.
+fn store(desc: &mut RegisterDescription, value: f64) -> bool {
+    let mut updated = desc.clone();
+    if !updated.value.set_numeric(value) || updated.validate_value().is_err() {
+        return false;
+    }
+    *desc = updated;
+    true
+}
+
+/// Per register progress of the stateful generators.
+struct GeneratorState {
+    tick: u64,
+    current: f64,
+}
+
+impl GeneratorState {
+    fn new(generator: &Generator) -> Self {
+        let current = match generator {
+            Generator::RandomWalk { start, .. } => *start,
+            Generator::Counter { min, .. } => *min,
+            _ => 0.0,
+        };
+        Self { tick: 0, current }
+    }
+
+    /// Value for the tick at `elapsed` since the simulation started, `None` if
+    /// the generator has nothing to produce.
+    fn next(&mut self, generator: &Generator, elapsed: Duration) -> Option<f64> {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let tick = self.tick;
+        self.tick += 1;
+
+        match generator {
+            Generator::Sine {
+                amplitude,
+                offset,
+                period_ms,
+            } => Some(offset + amplitude * (2.0 * PI * phase(elapsed_ms, *period_ms)).sin()),
+            Generator::Ramp {
+                from,
+                to,
+                period_ms,
+            } => Some(from + (to - from) * phase(elapsed_ms, *period_ms)),
+            Generator::RandomWalk { step, min, max, .. } => {
+                if tick > 0 {
+                    let step = step.abs();
+                    self.current += rand::thread_rng().gen_range(-step..=step);
+                    self.current = self.current.clamp(*min, *max);
+                }
+                Some(self.current)
+            }
+            Generator::Square {
+                low,
+                high,
+                period_ms,
+                duty,
+            } => {
+                if phase(elapsed_ms, *period_ms) < duty.unwrap_or(0.5) {
+                    Some(*high)
+                } else {
+                    Some(*low)
+                }
+            }
+            Generator::Steps { values } => {
+                if values.is_empty() {
+                    return None;
+                }
+                Some(values[(tick % values.len() as u64) as usize])
+            }
+            Generator::Counter { min, max, step } => {
+                if tick > 0 {
+                    self.current += step;
+                    if self.current > *max {
+                        self.current = *min;
+                    } else if self.current < *min {
+                        self.current = *max;
+                    }
+                }
+                Some(self.current)
+            }
+        }
+    }
+}
+
+/// Position within the current period as a fraction in `[0, 1)`.
+fn phase(elapsed_ms: f64, period_ms: u64) -> f64 {
+    if period_ms == 0 {
+        return 0.0;
+    }
+    (elapsed_ms % period_ms as f64) / period_ms as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(generator: &Generator, ticks: &[u64]) -> Vec<f64> {
+        let mut state = GeneratorState::new(generator);
+        ticks
+            .iter()
+            .map(|&ms| state.next(generator, Duration::from_millis(ms)).unwrap())
+            .collect()
+    }
+
+    fn description(value: &str) -> RegisterDescription {
+        let schema: RegisterSchema = toml::from_str(&format!(
+            r#"
+            [[holding_registers]]
+            name = "h_sim"
+            address = 0
+            count = 1
+            {value}
+            "#
+        ))
+        .unwrap();
+        schema.holding_registers[0].clone()
+    }
+
+    #[test]
+    fn sine_follows_its_period() {
+        let generator = Generator::Sine {
+            amplitude: 10.0,
+            offset: 100.0,
+            period_ms: 1000,
+        };
+        let values = values(&generator, &[0, 250, 500, 750, 1000]);
+        let expected = [100.0, 110.0, 100.0, 90.0, 100.0];
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+        }
+    }
+
+    #[test]
+    fn ramp_starts_over_every_period() {
+        let generator = Generator::Ramp {
+            from: 0.0,
+            to: 100.0,
+            period_ms: 1000,
+        };
+        assert_eq!(
+            values(&generator, &[0, 500, 999, 1000, 1250]),
+            [0.0, 50.0, 99.9, 0.0, 25.0]
+        );
+    }
+
+    #[test]
+    fn random_walk_stays_within_its_range() {
+        let generator = Generator::RandomWalk {
+            start: 5.0,
+            step: 4.0,
+            min: 0.0,
+            max: 10.0,
+        };
+        let values = values(&generator, &[0; 200]);
+        assert_eq!(values[0], 5.0);
+        for pair in values.windows(2) {
+            assert!((0.0..=10.0).contains(&pair[1]));
+            assert!((pair[1] - pair[0]).abs() <= 4.0);
+        }
+    }
+
+    #[test]
+    fn square_is_high_for_its_duty() {
+        let generator = Generator::Square {
+            low: 0.0,
+            high: 1.0,
+            period_ms: 1000,
+            duty: Some(0.25),
+        };
+        assert_eq!(
+            values(&generator, &[0, 249, 250, 999, 1000]),
+            [1.0, 1.0, 0.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn steps_repeat_their_sequence() {
+        let generator = Generator::Steps {
+            values: vec![1.0, 2.0, 3.0],
+        };
+        assert_eq!(values(&generator, &[0; 5]), [1.0, 2.0, 3.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn counter_wraps_around() {
+        let generator = Generator::Counter {
+            min: 0.0,
+            max: 2.0,
+            step: 1.0,
+        };
+        assert_eq!(values(&generator, &[0; 5]), [0.0, 1.0, 2.0, 0.0, 1.0]);
+        let generator = Generator::Counter {
+            min: 0.0,
+            max: 2.0,
+            step: -1.0,
+        };
+        assert_eq!(values(&generator, &[0; 4]), [0.0, 2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn values_outside_of_the_constraints_are_not_stored() {
+        let mut desc =
+            description("value.U16.default = 10\nvalue.U16.gte = 5\nvalue.U16.lte = 100");
+        assert!(store(&mut desc, 100.0));
+        assert!(!store(&mut desc, 101.0));
+        // saturated to 0 by the type, below gte
+        assert!(!store(&mut desc, -1.0));
+        assert_eq!(desc.value.numeric(), Some(100.0));
+
+        let mut desc =
+            description("value.U16.default = 10\nvalue.U16.scale = 0.1\nvalue.U16.eng_lte = 5.0");
+        assert!(!store(&mut desc, 60.0));
+        assert_eq!(desc.value.numeric(), Some(10.0));
+    }
+
+    #[test]
+    fn values_outside_of_an_enum_are_not_stored() {
+        let mut desc =
+            description("value.Enum.default = \"off\"\nvalue.Enum.kv = { off = 0, on = 1 }");
+        assert!(store(&mut desc, 1.0));
+        assert!(!store(&mut desc, 2.0));
+        assert_eq!(desc.value.numeric(), Some(1.0));
+    }
+}
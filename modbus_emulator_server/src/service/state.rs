@@ -0,0 +1,267 @@
+use std::path::Path;
+use std::time::Duration;
+
+use modbus_register_schema::*;
+
+use tokio::time::MissedTickBehavior;
+
+use tracing;
+
+use super::data::ModbusServiceData;
+
+/// State file of `unit`, `path` itself for the device answering every unit
+/// id, `<stem>.unit<id>.<ext>` next to it otherwise.
+pub fn state_path(path: &str, unit: Option<u8>) -> String {
+    let Some(unit) = unit else {
+        return path.to_string();
+    };
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.unit{unit}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.unit{unit}"),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+fn is_json(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Check that `path` can hold every value of the register image.
+///
+/// TOML integers are signed 64 bit, a `U64` register bounded above
+/// `i64::MAX` could be written a value that is never saved again, such
+/// schemas need a `.json` state path.
+pub fn check_path(data: &ModbusServiceData, path: &str) -> Result<(), String> {
+    if is_json(path) {
+        return Ok(());
+    }
+    let unbounded = data
+        .descriptions()
+        .into_iter()
+        .find(|(_table, desc)| match &desc.value {
+            RegisterValueType::U64(constraints) => {
+                let max = i64::MAX as u64;
+                !(constraints.lte.is_some_and(|lte| lte <= max)
+                    || constraints.lt.is_some_and(|lt| lt <= max + 1))
+            }
+            _ => false,
+        });
+    match unbounded {
+        Some((_table, desc)) => Err(format!(
+            "toml can not hold values of {} above {}, bound it with lte or use a .json state path",
+            desc.name,
+            i64::MAX
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Take over the values saved in `path` if it exists.
+pub fn restore(data: &ModbusServiceData, path: &str) {
+    if !Path::new(path).exists() {
+        tracing::info!("state(path: {}) not found, starting from the schema", path);
+        return;
+    }
+    let state = if is_json(path) {
+        std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                serde_json::from_str::<RegisterSchema>(&text).map_err(|err| err.to_string())
+            })
+    } else {
+        RegisterSchema::load(path, false).ok_or("invalid toml".to_string())
+    };
+    match state {
+        Ok(state) => {
+            let skipped = data.restore(&state);
+            for name in &skipped {
+                tracing::warn!("state(path: {}) value of {} skipped", path, name);
+            }
+            tracing::info!("state(path: {}) restored", path);
+        }
+        Err(err) => tracing::error!("state(path: {}) not restored - {}", path, err),
+    }
+}
+
+/// Write the current register image to `path`, through a temporary file so
+/// an interrupted write never leaves a truncated state behind.
+pub fn save(data: &ModbusServiceData, path: &str) -> std::io::Result<()> {
+    let text = serialize(&data.snapshot(), path)?;
+    write_atomic(path, &text)
+}
+
+fn serialize(state: &RegisterSchema, path: &str) -> std::io::Result<String> {
+    let text = if is_json(path) {
+        serde_json::to_string_pretty(state).map_err(std::io::Error::other)?
+    } else {
+        toml::to_string_pretty(state).map_err(std::io::Error::other)?
+    };
+    Ok(text)
+}
+
+fn write_atomic(path: &str, text: &str) -> std::io::Result<()> {
+    let temp = format!("{path}.tmp");
+    std::fs::write(&temp, text)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Save the register image to `path` every `interval_ms` milliseconds when
+/// it changed since the last save.
+pub fn spawn_persistence(data: ModbusServiceData, path: String, interval_ms: u64) {
+    tokio::spawn(async move {
+        let mut saved = String::new();
+        let mut interval = tokio::time::interval(Duration::from_millis(interval_ms.max(1)));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let text = match serialize(&data.snapshot(), &path) {
+                Ok(text) => text,
+                Err(err) => {
+                    tracing::error!("state(path: {}) not saved - {}", path, err);
+                    continue;
+                }
+            };
+            if text == saved {
+                continue;
+            }
+            match write_atomic(&path, &text) {
+                Ok(()) => {
+                    tracing::debug!("state(path: {}) saved", path);
+                    saved = text;
+                }
+                Err(err) => tracing::error!("state(path: {}) not saved - {}", path, err),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use tokio_modbus::prelude::{Request, Response};
+
+    use super::*;
+    use crate::tests::temp_dir;
+
+    const SCHEMA: &str = r#"
+        [[holding_registers]]
+        name = "h_setpoint"
+        address = 0
+        count = 1
+        value.U16.default = 50
+
+        [[holding_registers]]
+        name = "h_total"
+        address = 1
+        count = 4
+        value.U64.default = 0
+        value.U64.lte = 1000000
+
+        [[holding_registers]]
+        name = "h_label"
+        address = 5
+        count = 2
+        value.String.default = "ab"
+        "#;
+
+    fn data(schema: &str) -> ModbusServiceData {
+        ModbusServiceData::new(toml::from_str(schema).unwrap(), false)
+    }
+
+    fn holding(data: &ModbusServiceData) -> Vec<u16> {
+        match data.dispatch(Request::ReadHoldingRegisters(0, 7)) {
+            Ok(Response::ReadHoldingRegisters(registers)) => registers,
+            other => panic!("{other:?}"),
+        }
+    }
+
+    fn written(data: &ModbusServiceData) {
+        // 123456 is 0x0001E240, the low register first
+        let registers = vec![80, 0xE240, 0x0001, 0, 0, 0x7978, 0x7A7A];
+        data.dispatch(Request::WriteMultipleRegisters(0, Cow::Owned(registers)))
+            .unwrap();
+    }
+
+    #[test]
+    fn unit_state_files_sit_next_to_the_path() {
+        assert_eq!(state_path("state.toml", None), "state.toml");
+        assert_eq!(
+            state_path("run/state.toml", Some(2)),
+            "run/state.unit2.toml"
+        );
+        assert_eq!(state_path("run/state", Some(3)), "run/state.unit3");
+    }
+
+    #[test]
+    fn toml_paths_need_u64_values_bounded_to_i64() {
+        let bounded = data(SCHEMA);
+        assert_eq!(check_path(&bounded, "state.toml"), Ok(()));
+        let unbounded = data(
+            "[[holding_registers]]\nname = \"h_big\"\naddress = 0\ncount = 4\nvalue.U64.default = 0\n",
+        );
+        let err = check_path(&unbounded, "state.toml").unwrap_err();
+        assert!(err.contains("h_big"), "{err}");
+        assert_eq!(check_path(&unbounded, "state.json"), Ok(()));
+        assert_eq!(check_path(&unbounded, "state.JSON"), Ok(()));
+    }
+
+    #[test]
+    fn saved_values_are_restored() {
+        let dir = temp_dir("state-round-trip");
+        for name in ["state.toml", "state.json"] {
+            let path = dir.join(name).to_string_lossy().to_string();
+            let saved = data(SCHEMA);
+            written(&saved);
+            save(&saved, &path).unwrap();
+            assert!(!Path::new(&format!("{path}.tmp")).exists());
+
+            let restored = data(SCHEMA);
+            restore(&restored, &path);
+            assert_eq!(holding(&restored), holding(&saved), "{name}");
+        }
+    }
+
+    #[test]
+    fn missing_state_files_keep_the_schema_values() {
+        let dir = temp_dir("state-missing");
+        let restored = data(SCHEMA);
+        restore(&restored, &dir.join("state.toml").to_string_lossy());
+        assert_eq!(holding(&restored), holding(&data(SCHEMA)));
+    }
+
+    #[test]
+    fn stale_values_are_skipped() {
+        let saved = data(SCHEMA);
+        written(&saved);
+        // h_total is now bounded below its saved value, h_label is gone
+        let changed = data(
+            r#"
+            [[holding_registers]]
+            name = "h_setpoint"
+            address = 0
+            count = 1
+            value.U16.default = 50
+
+            [[holding_registers]]
+            name = "h_total"
+            address = 1
+            count = 4
+            value.U64.default = 0
+            value.U64.lte = 1000
+            "#,
+        );
+        let skipped = changed.restore(&saved.snapshot());
+        assert_eq!(skipped, ["h_total", "h_label"]);
+        match changed.dispatch(Request::ReadHoldingRegisters(0, 5)) {
+            Ok(Response::ReadHoldingRegisters(registers)) => {
+                assert_eq!(registers, [80, 0, 0, 0, 0])
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+}
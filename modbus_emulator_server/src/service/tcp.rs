@@ -1,30 +1,106 @@
-use std::future;
-
-use modbus_register_schema::*;
+use std::future::Future;
+use std::pin::Pin;
 
 use tokio_modbus::prelude::*;
 
-use crate::service::data::ModbusServiceData;
+use tracing;
+
+use super::{fault::FaultInjector, role::RoleAccess};
+use crate::service::units::ModbusServiceUnits;
 
+#[derive(Clone)]
 pub struct ModbusEmulatorTcpService {
-    pub data: ModbusServiceData,
+    pub units: ModbusServiceUnits,
+    pub faults: FaultInjector,
+    /// access of the role of a tls client, every request is served if unset
+    pub access: Option<RoleAccess>,
 }
 
 impl ModbusEmulatorTcpService {
-    pub fn new(schema: RegisterSchema) -> Self {
+    pub fn new(units: ModbusServiceUnits, faults: FaultInjector) -> Self {
         Self {
-            data: ModbusServiceData::new(schema),
+            units,
+            faults,
+            access: None,
         }
     }
+
+    /// Serve only the requests `access` permits, answering the others with
+    /// `IllegalFunction` as modbus/tcp security asks for.
+    pub fn restricted(mut self, access: RoleAccess) -> Self {
+        self.access = Some(access);
+        self
+    }
 }
 
 impl tokio_modbus::server::Service for ModbusEmulatorTcpService {
-    type Request = Request<'static>;
-    type Response = Response;
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
     type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Exception>> + Send>>;
 
     fn call(&self, req: Self::Request) -> Self::Future {
-        self.data.dispatch(req)
+        if let Some(access) = &self.access {
+            if !access.permits(&req.request) {
+                tracing::error!(
+                    "SERVER: ExceptionCode::IllegalFunction - role {:?} may not {:?}",
+                    access.role,
+                    req.request
+                );
+                return Box::pin(async { Err(ExceptionCode::IllegalFunction) });
+            }
+        }
+        let Some(data) = self.units.get(req.slave).cloned() else {
+            tracing::error!(
+                "SERVER: ExceptionCode::GatewayTargetDevice({}) - unknown unit id",
+                req.slave
+            );
+            return Box::pin(async { Err(ExceptionCode::GatewayTargetDevice) });
+        };
+        let faults = self.faults.clone();
+        Box::pin(async move {
+            // a corrupted crc has no meaning without one on the wire
+            let reply = faults.dispatch(req.slave, &data, req.request).await;
+            match reply.result {
+                Some(result) => result.map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tokio_modbus::server::Service;
+
+    use super::*;
+    use crate::service::data::ModbusServiceData;
+
+    fn service() -> ModbusEmulatorTcpService {
+        let schema = toml::from_str(
+            "[[holding_registers]]\nname = \"h_power\"\naddress = 0\ncount = 1\nvalue.U16.default = 7\n",
+        )
+        .unwrap();
+        let units = BTreeMap::from([(1, ModbusServiceData::new(schema, false))]);
+        ModbusEmulatorTcpService::new(ModbusServiceUnits::new(units), FaultInjector::default())
+    }
+
+    #[tokio::test]
+    async fn requests_are_routed_by_unit_id() {
+        let service = service();
+        let read = |slave| SlaveRequest {
+            slave,
+            request: Request::ReadHoldingRegisters(0, 1),
+        };
+        assert_eq!(
+            service.call(read(1)).await,
+            Ok(Some(Response::ReadHoldingRegisters(vec![7])))
+        );
+        assert_eq!(
+            service.call(read(2)).await,
+            Err(ExceptionCode::GatewayTargetDevice)
+        );
     }
 }
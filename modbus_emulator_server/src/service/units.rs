@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::data::ModbusServiceData;
+
+/// Register images of every emulated device, keyed by unit id.
+#[derive(Clone, Debug)]
+pub struct ModbusServiceUnits {
+    units: Arc<BTreeMap<u8, ModbusServiceData>>,
+    any_unit: Option<ModbusServiceData>,
+}
+
+impl ModbusServiceUnits {
+    /// One device answering every unit id.
+    pub fn single(data: ModbusServiceData) -> Self {
+        Self {
+            units: Arc::new(BTreeMap::new()),
+            any_unit: Some(data),
+        }
+    }
+
+    /// Devices answering only their own unit id.
+    pub fn new(units: BTreeMap<u8, ModbusServiceData>) -> Self {
+        Self {
+            units: Arc::new(units),
+            any_unit: None,
+        }
+    }
+
+    pub fn get(&self, unit: u8) -> Option<&ModbusServiceData> {
+        self.units.get(&unit).or(self.any_unit.as_ref())
+    }
+
+    /// Every device, `None` being the one answering every unit id.
+    pub fn iter(&self) -> impl Iterator<Item = (Option<u8>, &ModbusServiceData)> {
+        self.units
+            .iter()
+            .map(|(unit, data)| (Some(*unit), data))
+            .chain(self.any_unit.iter().map(|data| (None, data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use modbus_register_schema::RegisterTable;
+
+    use super::*;
+
+    fn data(name: &str) -> ModbusServiceData {
+        let schema = toml::from_str(&format!(
+            "[[holding_registers]]\nname = \"{name}\"\naddress = 0\ncount = 1\nvalue.U16.default = 0\n"
+        ))
+        .unwrap();
+        ModbusServiceData::new(schema, false)
+    }
+
+    fn name(data: Option<&ModbusServiceData>) -> Option<String> {
+        data.map(|data| {
+            data.with_description(RegisterTable::HoldingRegisters, 0, |desc| desc.name.clone())
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn single_device_answers_every_unit_id() {
+        let units = ModbusServiceUnits::single(data("h_single"));
+        for unit in [0, 1, 247, 255] {
+            assert_eq!(name(units.get(unit)), Some("h_single".to_string()));
+        }
+        assert_eq!(
+            units.iter().map(|(unit, _data)| unit).collect::<Vec<_>>(),
+            [None]
+        );
+    }
+
+    #[test]
+    fn configured_devices_answer_their_own_unit_id() {
+        let units =
+            ModbusServiceUnits::new(BTreeMap::from([(1, data("h_one")), (2, data("h_two"))]));
+        assert_eq!(name(units.get(1)), Some("h_one".to_string()));
+        assert_eq!(name(units.get(2)), Some("h_two".to_string()));
+        assert_eq!(name(units.get(3)), None);
+        assert_eq!(
+            units.iter().map(|(unit, _data)| unit).collect::<Vec<_>>(),
+            [Some(1), Some(2)]
+        );
+    }
+}
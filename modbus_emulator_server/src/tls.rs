@@ -0,0 +1,333 @@
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+
+use tokio::net::{TcpListener, TcpStream};
+
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+use tracing;
+
+use x509_parser::{der_parser::parse_der, parse_x509_certificate};
+
+use crate::frame::mbap;
+use crate::service::{
+    role::{RoleAccess, RoleConfig},
+    tcp::ModbusEmulatorTcpService,
+};
+
+/// Role extension of modbus/tcp security client certificates, an ASN.1
+/// UTF8String naming the role of the client.
+pub const ROLE_OID: &str = "1.3.6.1.4.1.50316.802.1";
+
+/// A client that does not finish its handshake in time is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server side of modbus/tcp security: the server certificate chain and key
+/// of `cert` and `key`, clients must present a certificate issued by a
+/// certificate authority of `ca`. All files are PEM.
+pub fn server_config(cert: &str, key: &str, ca: &str) -> Result<Arc<ServerConfig>, String> {
+    let mut roots = RootCertStore::empty();
+    for ca_cert in load_certs(ca)? {
+        roots.add(ca_cert).map_err(|err| format!("{ca}: {err}"))?;
+    }
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .map_err(|err| format!("{ca}: {err}"))?;
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|err| format!("{cert}: {err}"))?;
+    Ok(Arc::new(config))
+}
+
+pub fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("{path}: {err}"))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{path}: {err}"))?;
+    if certs.is_empty() {
+        return Err(format!("{path}: no certificate found"));
+    }
+    Ok(certs)
+}
+
+pub fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("{path}: {err}"))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| format!("{path}: {err}"))?
+        .ok_or(format!("{path}: no private key found"))
+}
+
+/// Serve mbap frames on every connection accepted by `listener` once its
+/// handshake succeeds. Each client runs its handshake in its own task, a
+/// stalled one holds up no other. The role of the client certificate limits
+/// the requests served by the access `roles` gives it, every request is
+/// served without `roles`.
+pub async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    service: ModbusEmulatorTcpService,
+    roles: Option<RoleConfig>,
+) -> std::io::Result<()> {
+    let roles = Arc::new(roles);
+    loop {
+        let (stream, socket_addr) = listener.accept().await?;
+        let (acceptor, service, roles) = (acceptor.clone(), service.clone(), roles.clone());
+        tokio::spawn(async move {
+            let Some((stream, role)) = accept(&acceptor, stream, socket_addr).await else {
+                return;
+            };
+            let access = match roles.as_ref() {
+                Some(roles) => roles.access(role.as_deref()),
+                None => RoleAccess::read_write(role.as_deref().unwrap_or("")),
+            };
+            let service = service.restricted(access);
+            if let Err(err) = mbap::serve(stream, &service, socket_addr).await {
+                tracing::error!("tls(peer: {}) {}", socket_addr, err);
+            }
+        });
+    }
+}
+
+/// Run the handshake of a client connected from `socket_addr`. Returns the
+/// stream and the role of the client certificate, `None` if the handshake
+/// failed.
+pub async fn accept(
+    acceptor: &TlsAcceptor,
+    stream: TcpStream,
+    socket_addr: SocketAddr,
+) -> Option<(TlsStream<TcpStream>, Option<String>)> {
+    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(err)) => {
+            tracing::error!("tls(peer: {}) handshake failed - {}", socket_addr, err);
+            return None;
+        }
+        Err(_elapsed) => {
+            tracing::error!("tls(peer: {}) handshake timed out", socket_addr);
+            return None;
+        }
+    };
+    let certs = stream.get_ref().1.peer_certificates().unwrap_or_default();
+    let role = match certs.first().map(|cert| peer_role(cert)) {
+        Some(Ok(role)) => role,
+        Some(Err(err)) => {
+            tracing::warn!("tls(peer: {}) role not read - {}", socket_addr, err);
+            None
+        }
+        None => None,
+    };
+    tracing::info!("tls(peer: {}) connected, role: {:?}", socket_addr, role);
+    Some((stream, role))
+}
+
+/// Role of the modbus/tcp security role extension of `cert`, `None` if it
+/// has none.
+pub fn peer_role(cert: &CertificateDer) -> Result<Option<String>, String> {
+    let (_rest, cert) = parse_x509_certificate(cert).map_err(|err| err.to_string())?;
+    let Some(extension) = cert
+        .extensions()
+        .iter()
+        .find(|extension| extension.oid.to_id_string() == ROLE_OID)
+    else {
+        return Ok(None);
+    };
+    let (_rest, value) = parse_der(extension.value).map_err(|err| err.to_string())?;
+    let role = value
+        .as_str()
+        .map_err(|err| format!("role is not a string - {err}"))?;
+    Ok(Some(role.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{
+        BasicConstraints, CertificateParams, CustomExtension, IsCa, KeyPair, PKCS_ECDSA_P256_SHA256,
+    };
+    use rustls::{pki_types::ServerName, ClientConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+    use crate::service::{
+        data::ModbusServiceData, fault::FaultInjector, units::ModbusServiceUnits,
+    };
+
+    struct Issued {
+        cert: rcgen::Certificate,
+        key: KeyPair,
+    }
+
+    fn ca() -> Issued {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Issued { cert, key }
+    }
+
+    fn issue(ca: &Issued, name: &str, role: Option<&str>) -> Issued {
+        let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        if let Some(role) = role {
+            // DER UTF8String of the role
+            let mut content = vec![0x0c, role.len() as u8];
+            content.extend_from_slice(role.as_bytes());
+            params
+                .custom_extensions
+                .push(CustomExtension::from_oid_content(
+                    &[1, 3, 6, 1, 4, 1, 50316, 802, 1],
+                    content,
+                ));
+        }
+        let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let cert = params.signed_by(&key, &ca.cert, &ca.key).unwrap();
+        Issued { cert, key }
+    }
+
+    fn key_der(issued: &Issued) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::try_from(issued.key.serialize_der()).unwrap()
+    }
+
+    fn test_server_config(ca: &Issued) -> Arc<ServerConfig> {
+        let server = issue(ca, "localhost", None);
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert.der().clone()).unwrap();
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .build()
+            .unwrap();
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(vec![server.cert.der().clone()], key_der(&server))
+            .unwrap();
+        Arc::new(config)
+    }
+
+    fn test_client_config(ca: &Issued, client: Option<&Issued>) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert.der().clone()).unwrap();
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match client {
+            Some(client) => builder
+                .with_client_auth_cert(vec![client.cert.der().clone()], key_der(client))
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        Arc::new(config)
+    }
+
+    /// Handshake over a local socket, the role the server read if it
+    /// accepted the client.
+    async fn handshake(ca: &Issued, client: Option<&Issued>) -> Option<Option<String>> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connector = TlsConnector::from(test_client_config(ca, client));
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("localhost").unwrap();
+            if let Ok(mut stream) = connector.connect(name, stream).await {
+                // tls 1.3 reports a rejected client certificate on the first read
+                let _ = stream.write_all(b"ping").await;
+                let _ = stream.read(&mut [0; 4]).await;
+            }
+        });
+        let acceptor = TlsAcceptor::from(test_server_config(ca));
+        let (stream, socket_addr) = listener.accept().await.unwrap();
+        let accepted = accept(&acceptor, stream, socket_addr).await;
+        let role = accepted.map(|(mut stream, role)| {
+            tokio::spawn(async move {
+                let mut ping = [0; 4];
+                stream.read_exact(&mut ping).await.unwrap();
+                stream.write_all(&ping).await.unwrap();
+            });
+            role
+        });
+        client.await.unwrap();
+        role
+    }
+
+    #[test]
+    fn role_is_read_from_the_extension() {
+        let ca = ca();
+        let client = issue(&ca, "client", Some("operator"));
+        assert_eq!(
+            peer_role(client.cert.der()).unwrap(),
+            Some("operator".to_string())
+        );
+        let client = issue(&ca, "client", None);
+        assert_eq!(peer_role(client.cert.der()).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn client_certificate_role_reaches_the_server() {
+        let ca = ca();
+        let client = issue(&ca, "client", Some("viewer"));
+        assert_eq!(
+            handshake(&ca, Some(&client)).await,
+            Some(Some("viewer".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn client_without_certificate_is_refused() {
+        let ca = ca();
+        assert_eq!(handshake(&ca, None).await, None);
+    }
+
+    #[tokio::test]
+    async fn client_of_another_authority_is_refused() {
+        let (ca, other) = (ca(), ca());
+        let client = issue(&other, "client", Some("operator"));
+        assert_eq!(handshake(&ca, Some(&client)).await, None);
+    }
+
+    #[tokio::test]
+    async fn stalled_handshake_does_not_hold_up_other_clients() {
+        let ca = ca();
+        let schema = toml::from_str(
+            r#"
+            [[holding_registers]]
+            name = "h_speed"
+            address = 0
+            count = 1
+            value.U16.default = 42
+            "#,
+        )
+        .unwrap();
+        let units = ModbusServiceUnits::single(ModbusServiceData::new(schema, false));
+        let service = ModbusEmulatorTcpService::new(units, FaultInjector::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = TlsAcceptor::from(test_server_config(&ca));
+        tokio::spawn(serve(listener, acceptor, service, None));
+
+        // connected but silent, its handshake waits for HANDSHAKE_TIMEOUT
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+
+        let client = issue(&ca, "client", None);
+        let connector = TlsConnector::from(test_client_config(&ca, Some(&client)));
+        let exchange = async {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("localhost").unwrap();
+            let mut stream = connector.connect(name, stream).await.unwrap();
+            stream
+                .write_all(&[0, 1, 0, 0, 0, 6, 1, 0x03, 0, 0, 0, 1])
+                .await
+                .unwrap();
+            let mut response = [0; 11];
+            stream.read_exact(&mut response).await.unwrap();
+            response
+        };
+        let response = tokio::time::timeout(Duration::from_secs(2), exchange)
+            .await
+            .expect("served while another handshake stalls");
+        assert_eq!(response, [0, 1, 0, 0, 0, 5, 1, 0x03, 2, 0, 42]);
+    }
+}
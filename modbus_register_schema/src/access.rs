@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// What modbus requests may do with a register description.
+///
+/// Coils and holding registers are writable by default, discrete inputs and
+/// input registers only ever by the server itself.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Access {
+    #[default]
+    #[serde(rename = "rw")]
+    ReadWrite,
+    /// writes are answered with `IllegalDataAddress`
+    #[serde(rename = "ro")]
+    ReadOnly,
+    /// reads are answered with `IllegalDataAddress`, e.g. a password
+    #[serde(rename = "wo")]
+    WriteOnly,
+    /// the first write is taken, later ones are answered with
+    /// `IllegalFunction`
+    #[serde(rename = "write-once")]
+    WriteOnce,
+    /// writes are answered with `IllegalFunction` unless the holding
+    /// register description `unlock` holds `key`
+    #[serde(rename = "locked")]
+    Locked { unlock: String, key: u64 },
+}
+
+impl Access {
+    pub fn is_default(&self) -> bool {
+        *self == Access::ReadWrite
+    }
+}
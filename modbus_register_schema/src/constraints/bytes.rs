@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use super::endian::Endianness;
+use super::error::ConstraintError;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct BytesConstraints {
@@ -8,3 +9,16 @@ pub struct BytesConstraints {
     pub default: Option<Vec<u8>>,
     pub endianness: Option<Endianness>,
 }
+
+impl BytesConstraints {
+    /// Check that `value` fits into `count` registers.
+    pub fn validate(&self, value: &[u8], count: u16) -> Result<(), ConstraintError> {
+        if value.len() > count as usize * 2 {
+            return Err(ConstraintError::TooLong {
+                len: value.len(),
+                max: count as usize * 2,
+            });
+        }
+        Ok(())
+    }
+}
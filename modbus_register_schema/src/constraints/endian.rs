@@ -1,7 +1,48 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+/// Layout of a value across its registers, the letters naming the bytes of a
+/// 32-bit value from most (A) to least (D) significant in wire order. 64-bit
+/// values extend the same pattern to four registers.
+///
+/// `Big` is ABCD. `Little` is CDAB for numbers, least significant register
+/// first, and swaps the bytes within each register for bytes and strings.
+/// `Little` is the default, the layout of descriptions without `endianness`
+/// since the first schemas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Endianness {
+    #[default]
     Little,
     Big,
+    #[serde(rename = "ABCD")]
+    Abcd,
+    #[serde(rename = "DCBA")]
+    Dcba,
+    #[serde(rename = "BADC")]
+    Badc,
+    #[serde(rename = "CDAB")]
+    Cdab,
+}
+
+impl Endianness {
+    /// Bytes within each register are low byte first.
+    pub fn swaps_bytes(self) -> bool {
+        matches!(self, Endianness::Dcba | Endianness::Badc)
+    }
+
+    /// Registers are least significant first.
+    pub fn swaps_words(self) -> bool {
+        matches!(
+            self,
+            Endianness::Little | Endianness::Dcba | Endianness::Cdab
+        )
+    }
+
+    /// Layout for bytes and strings, which have no significance across
+    /// registers and only keep the byte order within them.
+    pub fn byte_layout(self) -> Self {
+        match self {
+            Endianness::Little | Endianness::Dcba | Endianness::Badc => Endianness::Badc,
+            Endianness::Big | Endianness::Abcd | Endianness::Cdab => Endianness::Abcd,
+        }
+    }
 }
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 use super::endian::Endianness;
+use super::error::ConstraintError;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct EnumConstraints<T>
@@ -15,3 +16,18 @@ where
     pub default: Option<T>,
     pub endianness: Option<Endianness>,
 }
+
+impl<T> EnumConstraints<T>
+where
+    T: Eq + std::hash::Hash,
+{
+    /// Check that `value` is one of the values in `kv`.
+    pub fn validate(&self, value: u32) -> Result<(), ConstraintError> {
+        if !self.kv.values().any(|v| *v == value) {
+            return Err(ConstraintError::UnknownEnumValue {
+                value: value.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// A value rejected by the constraints of its register description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstraintError {
+    /// `value` violates the `bound` (`lt`, `lte`, `gt` or `gte`) of `limit`
+    OutOfRange {
+        value: String,
+        bound: &'static str,
+        limit: String,
+    },
+    /// enum value not listed in `kv`
+    UnknownEnumValue { value: String },
+    /// string or bytes longer than the registers of the description
+    TooLong { len: usize, max: usize },
+    /// flags set beyond the declared `flag_names`
+    UnknownFlags { flags: u64, flag_names: usize },
+    /// more flag names than the register width can hold
+    TooManyFlags { flag_names: usize, max: usize },
+    /// engineering `value` whose `raw` value does not fit the register type
+    NotRepresentable { value: String, raw: String },
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstraintError::OutOfRange {
+                value,
+                bound,
+                limit,
+            } => write!(f, "value {value} violates {bound} {limit}"),
+            ConstraintError::UnknownEnumValue { value } => {
+                write!(f, "enum value {value} is not in kv")
+            }
+            ConstraintError::TooLong { len, max } => {
+                write!(f, "len: {len} > max_size: {max}")
+            }
+            ConstraintError::UnknownFlags { flags, flag_names } => {
+                write!(f, "flags {flags:#x} set beyond {flag_names} flag_names")
+            }
+            ConstraintError::TooManyFlags { flag_names, max } => {
+                write!(f, "{flag_names} flag_names > max: {max}")
+            }
+            ConstraintError::NotRepresentable { value, raw } => {
+                write!(f, "value {value} has raw value {raw} outside of the type")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConstraintError {}
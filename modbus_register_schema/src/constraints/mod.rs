@@ -7,11 +7,14 @@ pub use boolean::BooleanConstraints;
 pub mod bytes;
 pub use bytes::BytesConstraints;
 
+pub mod error;
+pub use error::ConstraintError;
+
 pub mod enumeration;
 pub use enumeration::EnumConstraints;
 
 pub mod number;
-pub use number::{NumericConstraints, NumericFlagsConstraints};
+pub use number::{NumericConstraints, NumericFlagsConstraints, RawValue};
 
 pub mod string;
 pub use string::StringConstraints;
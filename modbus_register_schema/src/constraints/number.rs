@@ -1,7 +1,16 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
 use super::endian::Endianness;
+use super::error::ConstraintError;
+use crate::types::ValueFlags;
 
+/// Bounds, defaults and values are raw register values. With `scale` or
+/// `offset` set the engineering value is `raw * scale + offset`, which the
+/// `eng_*` bounds apply to and which is shown with `precision` decimals and
+/// `unit`.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct NumericConstraints<T> {
     pub val: Option<T>,
@@ -11,6 +20,129 @@ pub struct NumericConstraints<T> {
     pub gt: Option<T>,
     pub gte: Option<T>,
     pub endianness: Option<Endianness>,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    pub unit: Option<String>,
+    pub precision: Option<u8>,
+    pub eng_lt: Option<f64>,
+    pub eng_lte: Option<f64>,
+    pub eng_gt: Option<f64>,
+    pub eng_gte: Option<f64>,
+}
+
+impl<T: PartialOrd + Display + RawValue> NumericConstraints<T> {
+    /// Check `value` against the `lt` / `lte` / `gt` / `gte` bounds and its
+    /// engineering value against the `eng_*` bounds.
+    pub fn validate(&self, value: &T) -> Result<(), ConstraintError> {
+        check_bounds(
+            value,
+            &self.lt,
+            &self.lte,
+            &self.gt,
+            &self.gte,
+            ["lt", "lte", "gt", "gte"],
+        )?;
+        check_bounds(
+            &self.to_engineering(*value),
+            &self.eng_lt,
+            &self.eng_lte,
+            &self.eng_gt,
+            &self.eng_gte,
+            ["eng_lt", "eng_lte", "eng_gt", "eng_gte"],
+        )
+    }
+
+    pub fn is_scaled(&self) -> bool {
+        self.scale.is_some() || self.offset.is_some()
+    }
+
+    pub fn to_engineering(&self, raw: T) -> f64 {
+        raw.to_f64() * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0)
+    }
+
+    /// Raw value of an engineering value, rounded for integer types.
+    pub fn from_engineering(&self, value: f64) -> Result<T, ConstraintError> {
+        let raw = (value - self.offset.unwrap_or(0.0)) / self.scale.unwrap_or(1.0);
+        T::from_f64(raw).ok_or(ConstraintError::NotRepresentable {
+            value: value.to_string(),
+            raw: raw.to_string(),
+        })
+    }
+
+    /// `raw` as a plain number: the engineering value with `precision`
+    /// decimals if scaled.
+    pub fn format_number(&self, raw: T) -> String {
+        if !self.is_scaled() {
+            raw.to_string()
+        } else if let Some(precision) = self.precision {
+            format!("{:.*}", precision as usize, self.to_engineering(raw))
+        } else {
+            // trim the float noise of scales like 0.1
+            format!("{}", (self.to_engineering(raw) * 1e9).round() / 1e9)
+        }
+    }
+
+    /// `raw` as shown to users, `format_number` followed by `unit` if any.
+    pub fn format_value(&self, raw: T) -> String {
+        let value = self.format_number(raw);
+        match &self.unit {
+            Some(unit) => format!("{value} {unit}"),
+            None => value,
+        }
+    }
+}
+
+/// Register value types usable for engineering arithmetic.
+pub trait RawValue: Copy {
+    fn to_f64(self) -> f64;
+    /// `None` for NaN or if `value` rounds to a number outside of the type.
+    fn from_f64(value: f64) -> Option<Self>;
+}
+
+macro_rules! impl_raw_value_integer {
+    ($($t:ty),*) => {
+        $(
+            impl RawValue for $t {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn from_f64(value: f64) -> Option<Self> {
+                    let value = value.round();
+                    if value >= <$t>::MIN as f64 && value <= <$t>::MAX as f64 {
+                        Some(value as $t)
+                    } else {
+                        None
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_raw_value_integer!(u8, u16, u32, u64, i16, i32, i64);
+
+impl RawValue for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        if value.is_nan() || (value.is_finite() && value.abs() > f32::MAX as f64) {
+            return None;
+        }
+        Some(value as f32)
+    }
+}
+
+impl RawValue for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Option<Self> {
+        (!value.is_nan()).then_some(value)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -24,3 +156,238 @@ pub struct NumericFlagsConstraints<T, N> {
     pub endianness: Option<Endianness>,
     pub flag_names: Vec<String>,
 }
+
+impl<T: ValueFlags<Value = N>, N: PartialOrd + Display> NumericFlagsConstraints<T, N> {
+    /// Check that `flag_names` fit into the register width.
+    pub fn validate_flag_names(&self) -> Result<(), ConstraintError> {
+        if self.flag_names.len() >= T::BITS as usize {
+            return Err(ConstraintError::TooManyFlags {
+                flag_names: self.flag_names.len(),
+                max: T::BITS as usize - 1,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the value part of `value` against the bounds and reject flags
+    /// without a name.
+    pub fn validate(&self, value: &T) -> Result<(), ConstraintError> {
+        self.validate_flag_names()?;
+        check_bounds(
+            &value.value(),
+            &self.lt,
+            &self.lte,
+            &self.gt,
+            &self.gte,
+            ["lt", "lte", "gt", "gte"],
+        )?;
+        let unknown = value.flag_bits() >> self.flag_names.len();
+        if unknown != 0 {
+            return Err(ConstraintError::UnknownFlags {
+                flags: value.flag_bits(),
+                flag_names: self.flag_names.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn check_bounds<T: PartialOrd + Display>(
+    value: &T,
+    lt: &Option<T>,
+    lte: &Option<T>,
+    gt: &Option<T>,
+    gte: &Option<T>,
+    [lt_name, lte_name, gt_name, gte_name]: [&'static str; 4],
+) -> Result<(), ConstraintError> {
+    let out_of_range = |bound, limit: &T| ConstraintError::OutOfRange {
+        value: value.to_string(),
+        bound,
+        limit: limit.to_string(),
+    };
+    // written so that values without an order, NaN, fail every bound
+    let within = |limit: &T, ordering: &[Ordering]| {
+        value
+            .partial_cmp(limit)
+            .is_some_and(|cmp| ordering.contains(&cmp))
+    };
+    if let Some(lt) = lt {
+        if !within(lt, &[Ordering::Less]) {
+            return Err(out_of_range(lt_name, lt));
+        }
+    }
+    if let Some(lte) = lte {
+        if !within(lte, &[Ordering::Less, Ordering::Equal]) {
+            return Err(out_of_range(lte_name, lte));
+        }
+    }
+    if let Some(gt) = gt {
+        if !within(gt, &[Ordering::Greater]) {
+            return Err(out_of_range(gt_name, gt));
+        }
+    }
+    if let Some(gte) = gte {
+        if !within(gte, &[Ordering::Greater, Ordering::Equal]) {
+            return Err(out_of_range(gte_name, gte));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::u16_flags::U16ValueFlags;
+
+    #[test]
+    fn raw_bounds_are_checked() {
+        let constraints = NumericConstraints::<u16> {
+            gte: Some(10),
+            lt: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(constraints.validate(&10), Ok(()));
+        assert_eq!(constraints.validate(&19), Ok(()));
+        assert_eq!(
+            constraints.validate(&20),
+            Err(ConstraintError::OutOfRange {
+                value: "20".to_string(),
+                bound: "lt",
+                limit: "20".to_string(),
+            })
+        );
+        assert!(constraints.validate(&9).is_err());
+    }
+
+    #[test]
+    fn engineering_bounds_apply_to_the_scaled_value() {
+        let constraints = NumericConstraints::<i16> {
+            scale: Some(0.1),
+            offset: Some(-40.0),
+            eng_lte: Some(85.0),
+            ..Default::default()
+        };
+        // 1250 * 0.1 - 40 = 85
+        assert_eq!(constraints.validate(&1250), Ok(()));
+        assert!(matches!(
+            constraints.validate(&1251),
+            Err(ConstraintError::OutOfRange {
+                bound: "eng_lte",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn engineering_values_round_to_raw_values_of_the_type() {
+        let constraints = NumericConstraints::<u16> {
+            scale: Some(0.1),
+            ..Default::default()
+        };
+        assert_eq!(constraints.from_engineering(21.46), Ok(215));
+        assert!(matches!(
+            constraints.from_engineering(-1.0),
+            Err(ConstraintError::NotRepresentable { .. })
+        ));
+        assert_eq!(constraints.format_value(215), "21.5");
+    }
+
+    #[test]
+    fn flags_beyond_the_flag_names_are_rejected() {
+        let constraints = NumericFlagsConstraints::<U16ValueFlags, u16> {
+            val: None,
+            default: None,
+            lt: None,
+            lte: Some(100),
+            gt: None,
+            gte: None,
+            endianness: None,
+            flag_names: vec!["alarm".to_string(), "warning".to_string()],
+        };
+        // value in the low 2 bits, flags above
+        assert_eq!(
+            constraints.validate(&U16ValueFlags::from_u16(0b0111, 2)),
+            Ok(())
+        );
+        assert!(matches!(
+            constraints.validate(&U16ValueFlags::from_u16(0b10011, 2)),
+            Err(ConstraintError::UnknownFlags { .. })
+        ));
+    }
+
+    #[test]
+    fn nan_fails_every_bound() {
+        let constraints = NumericConstraints::<f32> {
+            gte: Some(-40.0),
+            lte: Some(125.0),
+            ..Default::default()
+        };
+        assert_eq!(constraints.validate(&20.5), Ok(()));
+        assert!(constraints.validate(&f32::NAN).is_err());
+        for bound in ["lt", "lte", "gt", "gte"] {
+            let limit = Some(0.0);
+            let constraints = NumericConstraints::<f64> {
+                lt: limit.filter(|_| bound == "lt"),
+                lte: limit.filter(|_| bound == "lte"),
+                gt: limit.filter(|_| bound == "gt"),
+                gte: limit.filter(|_| bound == "gte"),
+                ..Default::default()
+            };
+            assert!(
+                matches!(
+                    constraints.validate(&f64::NAN),
+                    Err(ConstraintError::OutOfRange { bound: failed, .. }) if failed == bound
+                ),
+                "{bound}"
+            );
+        }
+    }
+
+    #[test]
+    fn nan_fails_engineering_bounds() {
+        let constraints = NumericConstraints::<f64> {
+            scale: Some(2.0),
+            eng_lt: Some(1000.0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            constraints.validate(&f64::NAN),
+            Err(ConstraintError::OutOfRange {
+                bound: "eng_lt",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn infinities_fail_the_bounds_they_exceed() {
+        let constraints = NumericConstraints::<f64> {
+            gt: Some(-1e6),
+            lt: Some(1e6),
+            ..Default::default()
+        };
+        assert!(constraints.validate(&f64::INFINITY).is_err());
+        assert!(constraints.validate(&f64::NEG_INFINITY).is_err());
+        let unbounded = NumericConstraints::<f64>::default();
+        assert_eq!(unbounded.validate(&f64::INFINITY), Ok(()));
+    }
+
+    #[test]
+    fn nan_and_oversized_floats_are_not_representable() {
+        let constraints = NumericConstraints::<f32>::default();
+        assert_eq!(constraints.from_engineering(1.5), Ok(1.5));
+        assert_eq!(
+            constraints.from_engineering(f64::INFINITY),
+            Ok(f32::INFINITY)
+        );
+        for value in [f64::NAN, 1e39, -1e39] {
+            assert!(matches!(
+                constraints.from_engineering(value),
+                Err(ConstraintError::NotRepresentable { .. })
+            ));
+        }
+        let constraints = NumericConstraints::<f64>::default();
+        assert!(constraints.from_engineering(f64::NAN).is_err());
+        assert_eq!(constraints.from_engineering(1e39), Ok(1e39));
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use super::endian::Endianness;
+use super::error::ConstraintError;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct StringConstraints {
@@ -8,3 +9,16 @@ pub struct StringConstraints {
     pub default: Option<String>,
     pub endianness: Option<Endianness>,
 }
+
+impl StringConstraints {
+    /// Check that the utf-8 bytes of `value` fit into `count` registers.
+    pub fn validate(&self, value: &str, count: u16) -> Result<(), ConstraintError> {
+        if value.len() > count as usize * 2 {
+            return Err(ConstraintError::TooLong {
+                len: value.len(),
+                max: count as usize * 2,
+            });
+        }
+        Ok(())
+    }
+}
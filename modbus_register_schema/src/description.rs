@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use super::access::Access;
+use super::constraints::ConstraintError;
+use super::rule::Rule;
+use super::simulation::Simulation;
 use super::value_type::RegisterValueType;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -8,4 +12,95 @@ pub struct RegisterDescription {
     pub address: u16,
     pub count: u16,
     pub value: RegisterValueType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<Simulation>,
+    #[serde(default, skip_serializing_if = "Access::is_default")]
+    pub access: Access,
+    /// a `write-once` description took its write, kept by state files
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub written: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+impl RegisterDescription {
+    /// Number of table addresses the description occupies, one per bit for
+    /// coils and discrete inputs, one per register otherwise.
+    pub fn span(&self) -> u16 {
+        match &self.value {
+            RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+                constraints.max_bits
+            }
+            _ => self.count,
+        }
+    }
+
+    /// Check the current `val` against the constraints of the description,
+    /// an unset `val` is always valid.
+    pub fn validate_value(&self) -> Result<(), ConstraintError> {
+        match &self.value {
+            RegisterValueType::Coils(_constraints) => Ok(()),
+            RegisterValueType::Discrete(_constraints) => Ok(()),
+            RegisterValueType::U8(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U16(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U32(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U64(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::I16(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::I32(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::I64(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::F32(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::F64(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U16Flags(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U32Flags(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::U64Flags(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+            RegisterValueType::Bytes(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v, self.count),
+                None => Ok(()),
+            },
+            RegisterValueType::String(constraints) => match &constraints.val {
+                Some(v) => constraints.validate(v, self.count),
+                None => Ok(()),
+            },
+            RegisterValueType::Enum(constraints) => match constraints.val {
+                Some(v) => constraints.validate(v),
+                None => Ok(()),
+            },
+        }
+    }
 }
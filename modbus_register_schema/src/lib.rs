@@ -1,40 +1,135 @@
+pub mod access;
+pub use access::Access;
 pub mod constraints;
 pub use constraints::{
-    BooleanConstraints, BytesConstraints, Endianness, EnumConstraints, NumericConstraints,
-    StringConstraints,
+    BooleanConstraints, BytesConstraints, ConstraintError, Endianness, EnumConstraints,
+    NumericConstraints, RawValue, StringConstraints,
 };
 pub mod description;
 pub use description::RegisterDescription;
+pub mod rule;
+pub use rule::{Action, Rule, RuleValue};
 pub mod schema;
 pub use schema::RegisterSchema;
+pub mod simulation;
+pub use simulation::{Generator, Simulation};
+pub mod table;
+pub use table::RegisterTable;
 pub mod types;
+pub mod validation;
+pub use validation::{SchemaError, SchemaErrorKind};
 pub mod value_type;
 pub use value_type::RegisterValueType;
 
-pub fn serialize_registers(input_bytes: &[u8], is_big_endian: bool, output_u16_vec: &mut Vec<u16>) {
-    for i in 0..(input_bytes.len() / 2) {
-        let j = i * 2;
-        if j + 1 < input_bytes.len() {
-            output_u16_vec[i] = if is_big_endian {
-                u16::from_be_bytes([input_bytes[j], input_bytes[j + 1]])
+/// Spread `input_bytes`, most significant first for numbers, over registers
+/// in the `endianness` layout. An odd trailing byte is padded with zero,
+/// registers past the output are dropped.
+pub fn serialize_registers(input_bytes: &[u8], endianness: Endianness, output_u16_vec: &mut [u16]) {
+    let mut words = input_bytes
+        .chunks(2)
+        .map(|chunk| {
+            let pair = [chunk[0], chunk.get(1).copied().unwrap_or(0)];
+            if endianness.swaps_bytes() {
+                u16::from_le_bytes(pair)
             } else {
-                u16::from_le_bytes([input_bytes[j], input_bytes[j + 1]])
-            };
-        } else {
-            output_u16_vec[i] = input_bytes[j] as u16;
-        }
+                u16::from_be_bytes(pair)
+            }
+        })
+        .collect::<Vec<u16>>();
+    if endianness.swaps_words() {
+        words.reverse();
+    }
+    for (output, word) in output_u16_vec.iter_mut().zip(words) {
+        *output = word;
     }
 }
 
-pub fn deserialize_registers(input_u16_vec: &[u16], is_big_endian: bool) -> Vec<u8> {
-    input_u16_vec.iter().fold(vec![], |mut x, elem| {
-        if is_big_endian {
-            x.push((elem >> 8) as u8); // high byte
+/// Inverse of `serialize_registers`, numbers come back most significant byte
+/// first.
+pub fn deserialize_registers(input_u16_vec: &[u16], endianness: Endianness) -> Vec<u8> {
+    let mut bytes = input_u16_vec.iter().fold(vec![], |mut x, elem| {
+        if endianness.swaps_bytes() {
             x.push((elem & 0xff) as u8); // low byte
+            x.push((elem >> 8) as u8); // high byte
         } else {
-            x.push((elem & 0xff) as u8); // low byte
             x.push((elem >> 8) as u8); // high byte
+            x.push((elem & 0xff) as u8); // low byte
         }
         x
-    })
+    });
+    if endianness.swaps_words() {
+        bytes = bytes
+            .chunks(2)
+            .rev()
+            .flat_map(|pair| pair.to_vec())
+            .collect();
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUE: [u8; 4] = [0x11, 0x22, 0x33, 0x44];
+
+    fn serialized(bytes: &[u8], endianness: Endianness) -> Vec<u16> {
+        let mut registers = vec![0; bytes.len().div_ceil(2)];
+        serialize_registers(bytes, endianness, &mut registers);
+        registers
+    }
+
+    #[test]
+    fn thirty_two_bit_layouts() {
+        for (endianness, registers) in [
+            (Endianness::Big, [0x1122, 0x3344]),
+            (Endianness::Abcd, [0x1122, 0x3344]),
+            (Endianness::Little, [0x3344, 0x1122]),
+            (Endianness::Cdab, [0x3344, 0x1122]),
+            (Endianness::Dcba, [0x4433, 0x2211]),
+            (Endianness::Badc, [0x2211, 0x4433]),
+        ] {
+            assert_eq!(serialized(&VALUE, endianness), registers, "{endianness:?}");
+            assert_eq!(
+                deserialize_registers(&registers, endianness),
+                VALUE,
+                "{endianness:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sixty_four_bit_values_extend_the_pattern() {
+        let value = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+        assert_eq!(
+            serialized(&value, Endianness::Cdab),
+            [0x7788, 0x5566, 0x3344, 0x1122]
+        );
+        assert_eq!(
+            serialized(&value, Endianness::Dcba),
+            [0x8877, 0x6655, 0x4433, 0x2211]
+        );
+    }
+
+    #[test]
+    fn odd_bytes_are_padded_and_extra_registers_dropped() {
+        assert_eq!(serialized(b"abc", Endianness::Abcd), [0x6162, 0x6300]);
+        assert_eq!(serialized(b"abc", Endianness::Badc), [0x6261, 0x0063]);
+        let mut registers = [0; 1];
+        serialize_registers(&VALUE, Endianness::Big, &mut registers);
+        assert_eq!(registers, [0x1122]);
+    }
+
+    #[test]
+    fn strings_keep_their_register_order() {
+        let text = b"abcd";
+        assert_eq!(
+            serialized(text, Endianness::Little.byte_layout()),
+            [0x6261, 0x6463]
+        );
+        assert_eq!(
+            serialized(text, Endianness::Cdab.byte_layout()),
+            [0x6162, 0x6364]
+        );
+    }
 }
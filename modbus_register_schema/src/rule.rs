@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use super::value_type::RegisterValueType;
+
+/// A reaction of the device to a modbus write of the description the rule
+/// belongs to, e.g. a status following a command register.
+///
+/// Values are raw values as `RegisterValueType::numeric` gives them, coils
+/// and discrete inputs as a bit pattern.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rule {
+    /// fire only if the written value equals `when`, on every write otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<RuleValue>,
+    pub actions: Vec<Action>,
+}
+
+/// What a fired rule does to the description called `target`. The target
+/// may be of any table, its own rules do not fire.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Action {
+    /// store `value`, after `delay_ms` if given
+    Set {
+        target: String,
+        value: RuleValue,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        delay_ms: Option<u64>,
+    },
+    /// store the value of the description `from`, the written one if unset,
+    /// after `delay_ms` if given
+    Copy {
+        target: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        delay_ms: Option<u64>,
+    },
+    /// move toward the written value by `step` every `interval_ms`
+    Ramp {
+        target: String,
+        step: f64,
+        interval_ms: u64,
+    },
+}
+
+impl Action {
+    pub fn target(&self) -> &str {
+        match self {
+            Action::Set { target, .. }
+            | Action::Copy { target, .. }
+            | Action::Ramp { target, .. } => target,
+        }
+    }
+}
+
+/// A raw number, or the name of an enum value.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum RuleValue {
+    Number(f64),
+    Name(String),
+}
+
+impl RuleValue {
+    /// The raw value for a description of type `value`, `None` for a name
+    /// that is not in the `kv` of an enum.
+    pub fn resolve(&self, value: &RegisterValueType) -> Option<f64> {
+        match (self, value) {
+            (RuleValue::Number(number), _) => Some(*number),
+            (RuleValue::Name(name), RegisterValueType::Enum(constraints)) => {
+                constraints.kv.get(name).map(|&index| f64::from(index))
+            }
+            (RuleValue::Name(_), _) => None,
+        }
+    }
+}
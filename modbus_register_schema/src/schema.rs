@@ -4,6 +4,7 @@ use config_file_types;
 use serde::{Deserialize, Serialize};
 
 use super::description::RegisterDescription;
+use super::table::RegisterTable;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, ConfigFile)]
 #[config_file_ext("toml")]
@@ -20,3 +21,23 @@ pub struct RegisterSchema {
     #[serde(skip)]
     pub path: String,
 }
+
+impl RegisterSchema {
+    pub fn registers(&self, table: RegisterTable) -> &Vec<RegisterDescription> {
+        match table {
+            RegisterTable::Coils => &self.coils,
+            RegisterTable::DiscreteInputs => &self.discrete_inputs,
+            RegisterTable::InputRegisters => &self.input_registers,
+            RegisterTable::HoldingRegisters => &self.holding_registers,
+        }
+    }
+
+    pub fn registers_mut(&mut self, table: RegisterTable) -> &mut Vec<RegisterDescription> {
+        match table {
+            RegisterTable::Coils => &mut self.coils,
+            RegisterTable::DiscreteInputs => &mut self.discrete_inputs,
+            RegisterTable::InputRegisters => &mut self.input_registers,
+            RegisterTable::HoldingRegisters => &mut self.holding_registers,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+/// Generated values for a register description, updated by the server every
+/// `interval_ms` milliseconds.
+///
+/// Coils and discrete inputs take the generated value as a bit pattern, bit 0
+/// being the first bit of the description. Bytes and strings cannot be
+/// simulated.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Simulation {
+    pub interval_ms: u64,
+    pub generator: Generator,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Generator {
+    /// `offset + amplitude * sin(2 * pi * t / period_ms)`
+    Sine {
+        amplitude: f64,
+        offset: f64,
+        period_ms: u64,
+    },
+    /// linear from `from` to `to` over `period_ms`, then starting over
+    Ramp { from: f64, to: f64, period_ms: u64 },
+    /// a random step in `[-step, step]` per tick, kept within `[min, max]`
+    RandomWalk {
+        start: f64,
+        step: f64,
+        min: f64,
+        max: f64,
+    },
+    /// `high` for the first `duty` fraction of every period, `low` otherwise
+    Square {
+        low: f64,
+        high: f64,
+        period_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duty: Option<f64>,
+    },
+    /// one value per tick, repeating the sequence
+    Steps { values: Vec<f64> },
+    /// `step` added per tick, wrapping around from above `max` to `min`
+    Counter { min: f64, max: f64, step: f64 },
+}
+
+impl Generator {
+    /// Check the parameters, every number finite, ranges not inverted and
+    /// periods positive.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let finite = |values: &[f64]| values.iter().all(|value| value.is_finite());
+        match self {
+            Generator::Sine {
+                amplitude,
+                offset,
+                period_ms,
+            } => {
+                if !finite(&[*amplitude, *offset]) {
+                    return Err("sine amplitude and offset must be finite");
+                }
+                if *period_ms == 0 {
+                    return Err("sine period_ms must be positive");
+                }
+            }
+            Generator::Ramp {
+                from,
+                to,
+                period_ms,
+            } => {
+                if !finite(&[*from, *to]) {
+                    return Err("ramp from and to must be finite");
+                }
+                if *period_ms == 0 {
+                    return Err("ramp period_ms must be positive");
+                }
+            }
+            Generator::RandomWalk {
+                start,
+                step,
+                min,
+                max,
+            } => {
+                if !finite(&[*start, *step, *min, *max]) {
+                    return Err("random walk start, step, min and max must be finite");
+                }
+                if min > max {
+                    return Err("random walk min must not exceed max");
+                }
+            }
+            Generator::Square {
+                low,
+                high,
+                period_ms,
+                duty,
+            } => {
+                if !finite(&[*low, *high]) {
+                    return Err("square low and high must be finite");
+                }
+                if *period_ms == 0 {
+                    return Err("square period_ms must be positive");
+                }
+                if duty.is_some_and(|duty| !(0.0..=1.0).contains(&duty)) {
+                    return Err("square duty must be within [0, 1]");
+                }
+            }
+            Generator::Steps { values } => {
+                if values.is_empty() {
+                    return Err("steps values must not be empty");
+                }
+                if !finite(values) {
+                    return Err("steps values must be finite");
+                }
+            }
+            Generator::Counter { min, max, step } => {
+                if !finite(&[*min, *max, *step]) {
+                    return Err("counter min, max and step must be finite");
+                }
+                if min > max {
+                    return Err("counter min must not exceed max");
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// The four modbus data tables of a schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterTable {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+impl RegisterTable {
+    pub const ALL: [RegisterTable; 4] = [
+        RegisterTable::Coils,
+        RegisterTable::DiscreteInputs,
+        RegisterTable::InputRegisters,
+        RegisterTable::HoldingRegisters,
+    ];
+
+    /// Name of the table as used for the schema keys.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegisterTable::Coils => "coils",
+            RegisterTable::DiscreteInputs => "discrete_inputs",
+            RegisterTable::InputRegisters => "input_registers",
+            RegisterTable::HoldingRegisters => "holding_registers",
+        }
+    }
+}
+
+impl std::fmt::Display for RegisterTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
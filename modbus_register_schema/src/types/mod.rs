@@ -1,3 +1,15 @@
 pub mod u16_flags;
 pub mod u32_flags;
 pub mod u64_flags;
+
+/// A register split into a value in the low bits and named flags above it.
+pub trait ValueFlags {
+    type Value;
+
+    /// width of the whole register in bits
+    const BITS: u8;
+
+    fn value(&self) -> Self::Value;
+
+    fn flag_bits(&self) -> u64;
+}
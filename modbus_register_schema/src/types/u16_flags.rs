@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use serde_derive::*;
 
+use super::ValueFlags;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct U16ValueFlags {
     pub value: u16,
@@ -14,7 +16,7 @@ pub struct U16ValueFlags {
 impl U16ValueFlags {
     pub fn from_u16(data: u16, max_flags: u8) -> Self {
         let value = data & ((1 << max_flags) - 1); // low bits -> value
-        let flag = (data >> max_flags) as u16; // high bits -> flags
+        let flag = data >> max_flags; // high bits -> flags
 
         if max_flags < 16 {
             return U16ValueFlags {
@@ -34,6 +36,20 @@ impl U16ValueFlags {
     }
 }
 
+impl ValueFlags for U16ValueFlags {
+    type Value = u16;
+
+    const BITS: u8 = 16;
+
+    fn value(&self) -> u16 {
+        self.value
+    }
+
+    fn flag_bits(&self) -> u64 {
+        self.flags.bits() as u64
+    }
+}
+
 bitflags! {
     #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
     #[serde(transparent)]
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use serde_derive::*;
 
+use super::ValueFlags;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct U32ValueFlags {
     pub value: u32,
@@ -14,7 +16,7 @@ pub struct U32ValueFlags {
 impl U32ValueFlags {
     pub fn from_u32(data: u32, max_flags: u8) -> Self {
         let value = data & ((1 << max_flags) - 1); // low bits -> value
-        let flag = (data >> max_flags) as u32; // high bits -> flags
+        let flag = data >> max_flags; // high bits -> flags
 
         if max_flags < 32 {
             return U32ValueFlags {
@@ -34,6 +36,20 @@ impl U32ValueFlags {
     }
 }
 
+impl ValueFlags for U32ValueFlags {
+    type Value = u32;
+
+    const BITS: u8 = 32;
+
+    fn value(&self) -> u32 {
+        self.value
+    }
+
+    fn flag_bits(&self) -> u64 {
+        self.flags.bits() as u64
+    }
+}
+
 bitflags! {
     #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
     #[serde(transparent)]
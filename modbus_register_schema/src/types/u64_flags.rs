@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use serde_derive::*;
 
+use super::ValueFlags;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct U64ValueFlags {
     pub value: u64,
@@ -14,7 +16,7 @@ pub struct U64ValueFlags {
 impl U64ValueFlags {
     pub fn from_u64(data: u64, max_flags: u8) -> Self {
         let value = data & ((1 << max_flags) - 1); // low bits -> value
-        let flag = (data >> max_flags) as u64; // high bits -> flags
+        let flag = data >> max_flags; // high bits -> flags
 
         if max_flags < 64 {
             return U64ValueFlags {
@@ -34,6 +36,20 @@ impl U64ValueFlags {
     }
 }
 
+impl ValueFlags for U64ValueFlags {
+    type Value = u64;
+
+    const BITS: u8 = 64;
+
+    fn value(&self) -> u64 {
+        self.value
+    }
+
+    fn flag_bits(&self) -> u64 {
+        self.flags.bits()
+    }
+}
+
 bitflags! {
     #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
     #[serde(transparent)]
@@ -0,0 +1,643 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use super::access::Access;
+use super::constraints::{ConstraintError, NumericConstraints, NumericFlagsConstraints, RawValue};
+use super::description::RegisterDescription;
+use super::rule::{Action, RuleValue};
+use super::schema::RegisterSchema;
+use super::table::RegisterTable;
+use super::types::{
+    u16_flags::U16ValueFlags, u32_flags::U32ValueFlags, u64_flags::U64ValueFlags, ValueFlags,
+};
+use super::value_type::RegisterValueType;
+
+/// A problem of one register description in a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaError {
+    pub table: RegisterTable,
+    pub name: String,
+    pub address: u16,
+    pub kind: SchemaErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaErrorKind {
+    /// the address range overlaps the one of description `other`
+    Overlap {
+        other: String,
+    },
+    /// another description of the table has the same name
+    DuplicateName,
+    /// the address range runs past the end of the table
+    AddressOverflow {
+        span: u16,
+    },
+    /// `count` does not match the registers of the value type
+    WrongCount {
+        count: u16,
+        expected: u16,
+    },
+    /// bytes or string without any register
+    NoRegisters,
+    /// the value type does not belong into the table
+    WrongTable,
+    /// coils or discrete inputs `val` holds fewer than `max_bits` bits
+    ShortBits {
+        bits: usize,
+        max_bits: u16,
+    },
+    /// enum `default` is not a key of `kv`
+    UnknownEnumDefault {
+        default: String,
+    },
+    /// `scale` of zero, no raw value maps to an engineering value
+    ZeroScale,
+    /// more `flag_names` than the flag width holds
+    InvalidFlagNames(ConstraintError),
+    InvalidDefault(ConstraintError),
+    InvalidValue(ConstraintError),
+    InvalidSimulation(&'static str),
+    InvalidAccess(&'static str),
+    /// `locked` access names no holding register description as `unlock`
+    UnknownUnlock {
+        unlock: String,
+    },
+    /// a rule action names no description as `target` or `from`
+    UnknownRuleTarget {
+        target: String,
+    },
+    /// a rule names an enum value the description does not have
+    UnknownRuleValue {
+        value: String,
+    },
+    InvalidRule(&'static str),
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} (address {}): {}",
+            self.table, self.name, self.address, self.kind
+        )
+    }
+}
+
+impl Display for SchemaErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaErrorKind::Overlap { other } => write!(f, "overlaps {other}"),
+            SchemaErrorKind::DuplicateName => write!(f, "duplicate name"),
+            SchemaErrorKind::AddressOverflow { span } => {
+                write!(f, "{span} addresses run past 65535")
+            }
+            SchemaErrorKind::WrongCount { count, expected } => {
+                write!(f, "count {count} != {expected} registers of the value type")
+            }
+            SchemaErrorKind::NoRegisters => write!(f, "count must be at least 1"),
+            SchemaErrorKind::WrongTable => write!(f, "value type does not belong to the table"),
+            SchemaErrorKind::ShortBits { bits, max_bits } => {
+                write!(f, "val holds {bits} bits < max_bits: {max_bits}")
+            }
+            SchemaErrorKind::UnknownEnumDefault { default } => {
+                write!(f, "default {default} is not in kv")
+            }
+            SchemaErrorKind::ZeroScale => write!(f, "scale must not be zero"),
+            SchemaErrorKind::InvalidFlagNames(err) => write!(f, "flag_names: {err}"),
+            SchemaErrorKind::InvalidDefault(err) => write!(f, "default: {err}"),
+            SchemaErrorKind::InvalidValue(err) => write!(f, "val: {err}"),
+            SchemaErrorKind::InvalidSimulation(reason) => write!(f, "simulation: {reason}"),
+            SchemaErrorKind::InvalidAccess(reason) => write!(f, "access: {reason}"),
+            SchemaErrorKind::UnknownUnlock { unlock } => {
+                write!(f, "unlock {unlock} is not a holding register")
+            }
+            SchemaErrorKind::UnknownRuleTarget { target } => {
+                write!(f, "rule: no description {target}")
+            }
+            SchemaErrorKind::UnknownRuleValue { value } => {
+                write!(f, "rule: {value} is not in kv")
+            }
+            SchemaErrorKind::InvalidRule(reason) => write!(f, "rule: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl RegisterSchema {
+    /// Check every description and the address layout of every table.
+    pub fn validate(&self) -> Result<(), Vec<SchemaError>> {
+        let mut errors = vec![];
+        let holding_names = self
+            .holding_registers
+            .iter()
+            .map(|desc| desc.name.as_str())
+            .collect::<HashSet<_>>();
+        // rule targets are looked up by name in table order, like the server does
+        let mut descriptions = HashMap::new();
+        for table in RegisterTable::ALL {
+            for desc in self.registers(table) {
+                descriptions.entry(desc.name.as_str()).or_insert(desc);
+            }
+        }
+        for table in RegisterTable::ALL {
+            let registers = self.registers(table);
+            let error = |desc: &RegisterDescription, kind| SchemaError {
+                table,
+                name: desc.name.clone(),
+                address: desc.address,
+                kind,
+            };
+
+            let mut names = HashSet::new();
+            for desc in registers {
+                if !names.insert(desc.name.as_str()) {
+                    errors.push(error(desc, SchemaErrorKind::DuplicateName));
+                }
+                for kind in check_description(desc, table) {
+                    errors.push(error(desc, kind));
+                }
+                if let Access::Locked { unlock, .. } = &desc.access {
+                    if !holding_names.contains(unlock.as_str()) {
+                        let unlock = unlock.clone();
+                        errors.push(error(desc, SchemaErrorKind::UnknownUnlock { unlock }));
+                    }
+                }
+                for kind in check_rules(desc, table, &descriptions) {
+                    errors.push(error(desc, kind));
+                }
+            }
+
+            // end of the farthest reaching description so far, in address order
+            let mut sorted = registers.iter().collect::<Vec<_>>();
+            sorted.sort_by_key(|desc| desc.address);
+            let mut reach: Option<(u32, &RegisterDescription)> = None;
+            for desc in sorted {
+                if let Some((end, other)) = reach {
+                    if end > desc.address as u32 {
+                        let other = other.name.clone();
+                        errors.push(error(desc, SchemaErrorKind::Overlap { other }));
+                    }
+                }
+                let end = desc.address as u32 + desc.span() as u32;
+                if reach.is_none_or(|(reach_end, _)| end > reach_end) {
+                    reach = Some((end, desc));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn check_description(desc: &RegisterDescription, table: RegisterTable) -> Vec<SchemaErrorKind> {
+    let mut errors = vec![];
+
+    let in_table = match &desc.value {
+        RegisterValueType::Coils(_) => table == RegisterTable::Coils,
+        RegisterValueType::Discrete(_) => table == RegisterTable::DiscreteInputs,
+        _ => matches!(
+            table,
+            RegisterTable::InputRegisters | RegisterTable::HoldingRegisters
+        ),
+    };
+    if !in_table {
+        errors.push(SchemaErrorKind::WrongTable);
+    }
+
+    if desc.address as u32 + desc.span() as u32 > 0x10000 {
+        errors.push(SchemaErrorKind::AddressOverflow { span: desc.span() });
+    }
+
+    match desc.value.register_count() {
+        Some(expected) if desc.count != expected => {
+            errors.push(SchemaErrorKind::WrongCount {
+                count: desc.count,
+                expected,
+            });
+        }
+        None if matches!(
+            desc.value,
+            RegisterValueType::Bytes(_) | RegisterValueType::String(_)
+        ) && desc.count == 0 =>
+        {
+            errors.push(SchemaErrorKind::NoRegisters);
+        }
+        _ => {}
+    }
+
+    match &desc.value {
+        RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+            let bits = constraints.val.len() * 8;
+            if bits < constraints.max_bits as usize {
+                errors.push(SchemaErrorKind::ShortBits {
+                    bits,
+                    max_bits: constraints.max_bits,
+                });
+            }
+        }
+        RegisterValueType::U8(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::U16(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::U32(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::U64(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::I16(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::I32(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::I64(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::F32(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::F64(constraints) => check_numeric(constraints, &mut errors),
+        RegisterValueType::U16Flags(constraints) => {
+            check_flags(constraints, U16ValueFlags::from_u16, &mut errors)
+        }
+        RegisterValueType::U32Flags(constraints) => {
+            check_flags(constraints, U32ValueFlags::from_u32, &mut errors)
+        }
+        RegisterValueType::U64Flags(constraints) => {
+            check_flags(constraints, U64ValueFlags::from_u64, &mut errors)
+        }
+        RegisterValueType::Bytes(constraints) => {
+            if let Some(default) = &constraints.default {
+                if let Err(err) = constraints.validate(default, desc.count) {
+                    errors.push(SchemaErrorKind::InvalidDefault(err));
+                }
+            }
+        }
+        RegisterValueType::String(constraints) => {
+            if let Some(default) = &constraints.default {
+                if let Err(err) = constraints.validate(default, desc.count) {
+                    errors.push(SchemaErrorKind::InvalidDefault(err));
+                }
+            }
+        }
+        RegisterValueType::Enum(constraints) => {
+            if let Some(default) = &constraints.default {
+                if !constraints.kv.contains_key(default) {
+                    errors.push(SchemaErrorKind::UnknownEnumDefault {
+                        default: default.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    // checking flags `val` would only repeat a flag_names error
+    let flag_names_invalid = errors
+        .iter()
+        .any(|kind| matches!(kind, SchemaErrorKind::InvalidFlagNames(_)));
+    if !flag_names_invalid {
+        if let Err(err) = desc.validate_value() {
+            errors.push(SchemaErrorKind::InvalidValue(err));
+        }
+    }
+
+    if let Some(simulation) = &desc.simulation {
+        if simulation.interval_ms == 0 {
+            errors.push(SchemaErrorKind::InvalidSimulation(
+                "interval_ms must be positive",
+            ));
+        }
+        if let RegisterValueType::Bytes(_) | RegisterValueType::String(_) = desc.value {
+            errors.push(SchemaErrorKind::InvalidSimulation(
+                "bytes and strings cannot be simulated",
+            ));
+        }
+        if let Err(reason) = simulation.generator.validate() {
+            errors.push(SchemaErrorKind::InvalidSimulation(reason));
+        }
+    }
+
+    let read_only_table = matches!(
+        table,
+        RegisterTable::DiscreteInputs | RegisterTable::InputRegisters
+    );
+    if read_only_table && !matches!(desc.access, Access::ReadWrite | Access::ReadOnly) {
+        errors.push(SchemaErrorKind::InvalidAccess(
+            "discrete inputs and input registers cannot be written",
+        ));
+    }
+
+    errors
+}
+
+fn check_rules(
+    desc: &RegisterDescription,
+    table: RegisterTable,
+    descriptions: &HashMap<&str, &RegisterDescription>,
+) -> Vec<SchemaErrorKind> {
+    let mut errors = vec![];
+    if desc.rules.is_empty() {
+        return errors;
+    }
+    if matches!(
+        table,
+        RegisterTable::DiscreteInputs | RegisterTable::InputRegisters
+    ) {
+        errors.push(SchemaErrorKind::InvalidRule(
+            "discrete inputs and input registers are never written by requests",
+        ));
+    }
+
+    // a name must be a value of the enum it is compared to or stored into
+    let check_value = |value: &RuleValue, other: &RegisterDescription, errors: &mut Vec<_>| {
+        if let RuleValue::Name(name) = value {
+            if value.resolve(&other.value).is_none() {
+                errors.push(SchemaErrorKind::UnknownRuleValue {
+                    value: name.clone(),
+                });
+            }
+        }
+    };
+    // bytes and strings hold no number to store or copy
+    let lookup = |name: &str, errors: &mut Vec<_>| {
+        let Some(other) = descriptions.get(name) else {
+            errors.push(SchemaErrorKind::UnknownRuleTarget {
+                target: name.to_string(),
+            });
+            return None;
+        };
+        if let RegisterValueType::Bytes(_) | RegisterValueType::String(_) = other.value {
+            errors.push(SchemaErrorKind::InvalidRule(
+                "bytes and strings cannot be set or copied by rules",
+            ));
+            return None;
+        }
+        Some(*other)
+    };
+
+    for rule in &desc.rules {
+        if let Some(when) = &rule.when {
+            check_value(when, desc, &mut errors);
+        }
+        for action in &rule.actions {
+            let target = lookup(action.target(), &mut errors);
+            match action {
+                Action::Set { value, .. } => {
+                    if let Some(target) = target {
+                        check_value(value, target, &mut errors);
+                    }
+                }
+                Action::Copy { from, .. } => {
+                    if let Some(from) = from {
+                        lookup(from, &mut errors);
+                    }
+                }
+                Action::Ramp {
+                    step, interval_ms, ..
+                } => {
+                    if *step <= 0.0 || *interval_ms == 0 {
+                        errors.push(SchemaErrorKind::InvalidRule(
+                            "ramp step and interval_ms must be positive",
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+fn check_numeric<T: PartialOrd + Display + RawValue>(
+    constraints: &NumericConstraints<T>,
+    errors: &mut Vec<SchemaErrorKind>,
+) {
+    if constraints.scale == Some(0.0) {
+        errors.push(SchemaErrorKind::ZeroScale);
+    }
+    if let Some(default) = &constraints.default {
+        if let Err(err) = constraints.validate(default) {
+            errors.push(SchemaErrorKind::InvalidDefault(err));
+        }
+    }
+}
+
+fn check_flags<T: ValueFlags<Value = N>, N: PartialOrd + Display + Copy>(
+    constraints: &NumericFlagsConstraints<T, N>,
+    from_raw: fn(N, u8) -> T,
+    errors: &mut Vec<SchemaErrorKind>,
+) {
+    if let Err(err) = constraints.validate_flag_names() {
+        errors.push(SchemaErrorKind::InvalidFlagNames(err));
+        return;
+    }
+    if let Some(default) = constraints.default {
+        let value = from_raw(default, constraints.flag_names.len() as u8);
+        if let Err(err) = constraints.validate(&value) {
+            errors.push(SchemaErrorKind::InvalidDefault(err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name and kind of every error of the toml `schema`.
+    fn errors(schema: &str) -> Vec<(String, SchemaErrorKind)> {
+        let schema = RegisterSchema::loads(schema, false).unwrap();
+        match schema.validate() {
+            Ok(()) => vec![],
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (error.name, error.kind))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn valid_schemas_pass() {
+        let schema = r#"
+            [[coils]]
+            name = "c_pump"
+            address = 0
+            count = 1
+            value.Coils = { val = [0, 0], max_bits = 16 }
+
+            [[holding_registers]]
+            name = "h_power"
+            address = 0
+            count = 2
+            value.U32.default = 7
+
+            [[holding_registers]]
+            name = "h_mode"
+            address = 2
+            count = 2
+            value.Enum.default = "off"
+            value.Enum.kv = { off = 0, on = 1 }
+        "#;
+        assert_eq!(errors(schema), []);
+    }
+
+    #[test]
+    fn overlapping_addresses_are_rejected() {
+        let schema = r#"
+            [[holding_registers]]
+            name = "h_power"
+            address = 0
+            count = 2
+            value.U32.default = 7
+
+            [[holding_registers]]
+            name = "h_energy"
+            address = 1
+            count = 1
+            value.U16.default = 0
+        "#;
+        assert_eq!(
+            errors(schema),
+            [(
+                "h_energy".to_string(),
+                SchemaErrorKind::Overlap {
+                    other: "h_power".to_string()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn counts_must_match_the_type_width() {
+        let schema = r#"
+            [[holding_registers]]
+            name = "h_energy"
+            address = 0
+            count = 1
+            value.F64.default = 0.0
+        "#;
+        assert_eq!(
+            errors(schema),
+            [(
+                "h_energy".to_string(),
+                SchemaErrorKind::WrongCount {
+                    count: 1,
+                    expected: 4
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn enum_defaults_must_be_in_kv() {
+        let schema = r#"
+            [[holding_registers]]
+            name = "h_mode"
+            address = 0
+            count = 2
+            value.Enum.default = "auto"
+            value.Enum.kv = { off = 0, on = 1 }
+        "#;
+        assert_eq!(
+            errors(schema),
+            [(
+                "h_mode".to_string(),
+                SchemaErrorKind::UnknownEnumDefault {
+                    default: "auto".to_string()
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn flag_names_must_fit_the_register() {
+        let names = (0..16)
+            .map(|i| format!("\"f{i}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let schema = format!(
+            r#"
+            [[holding_registers]]
+            name = "h_status"
+            address = 0
+            count = 1
+            value.U16Flags.flag_names = [{names}]
+            "#
+        );
+        let errors = errors(&schema);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(matches!(
+            errors[0].1,
+            SchemaErrorKind::InvalidFlagNames(ConstraintError::TooManyFlags {
+                flag_names: 16,
+                max: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn bit_values_must_hold_max_bits() {
+        let schema = r#"
+            [[coils]]
+            name = "c_valves"
+            address = 0
+            count = 1
+            value.Coils = { val = [0], max_bits = 12 }
+        "#;
+        assert_eq!(
+            errors(schema),
+            [(
+                "c_valves".to_string(),
+                SchemaErrorKind::ShortBits {
+                    bits: 8,
+                    max_bits: 12
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn names_are_unique_per_table() {
+        let schema = r#"
+            [[input_registers]]
+            name = "speed"
+            address = 0
+            count = 1
+            value.U16.default = 0
+
+            [[holding_registers]]
+            name = "speed"
+            address = 0
+            count = 1
+            value.U16.default = 0
+
+            [[holding_registers]]
+            name = "speed"
+            address = 1
+            count = 1
+            value.U16.default = 0
+        "#;
+        let errors = errors(schema);
+        assert_eq!(
+            errors,
+            [("speed".to_string(), SchemaErrorKind::DuplicateName)]
+        );
+    }
+
+    #[test]
+    fn errors_name_table_and_address() {
+        let schema = RegisterSchema::loads(
+            r#"
+            [[holding_registers]]
+            name = "h_energy"
+            address = 4
+            count = 1
+            value.U32.default = 0
+            "#,
+            false,
+        )
+        .unwrap();
+        let errors = schema.validate().unwrap_err();
+        assert_eq!(errors[0].table, RegisterTable::HoldingRegisters);
+        assert_eq!(errors[0].address, 4);
+        assert_eq!(
+            errors[0].to_string(),
+            format!(
+                "{}.h_energy (address 4): count 1 != 2 registers of the value type",
+                RegisterTable::HoldingRegisters
+            )
+        );
+    }
+}
@@ -14,6 +14,11 @@ pub enum RegisterValueType {
     U16(NumericConstraints<u16>),
     U32(NumericConstraints<u32>),
     U64(NumericConstraints<u64>),
+    I16(NumericConstraints<i16>),
+    I32(NumericConstraints<i32>),
+    I64(NumericConstraints<i64>),
+    F32(NumericConstraints<f32>),
+    F64(NumericConstraints<f64>),
     U16Flags(NumericFlagsConstraints<U16ValueFlags, u16>),
     U32Flags(NumericFlagsConstraints<U32ValueFlags, u32>),
     U64Flags(NumericFlagsConstraints<U64ValueFlags, u64>),
@@ -21,3 +26,215 @@ pub enum RegisterValueType {
     String(StringConstraints),
     Enum(EnumConstraints<String>),
 }
+
+impl RegisterValueType {
+    /// Current value (`val`, else `default`) as a number, coils and discrete
+    /// inputs as a bit pattern of their first 64 bits. `None` for bytes,
+    /// strings and unset values.
+    pub fn numeric(&self) -> Option<f64> {
+        match self {
+            RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+                let bits = constraints.max_bits.min(64);
+                let pattern = (0..bits)
+                    .filter(|&i| {
+                        constraints
+                            .val
+                            .get(i as usize / 8)
+                            .is_some_and(|byte| byte & (1 << (i % 8)) != 0)
+                    })
+                    .fold(0u64, |acc, i| acc | (1 << i));
+                Some(pattern as f64)
+            }
+            RegisterValueType::U8(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::U16(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::U32(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::U64(constraints) => {
+                constraints.val.or(constraints.default).map(|v| v as f64)
+            }
+            RegisterValueType::I16(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::I32(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::I64(constraints) => {
+                constraints.val.or(constraints.default).map(|v| v as f64)
+            }
+            RegisterValueType::F32(constraints) => {
+                constraints.val.or(constraints.default).map(f64::from)
+            }
+            RegisterValueType::F64(constraints) => constraints.val.or(constraints.default),
+            RegisterValueType::U16Flags(constraints) => constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u16())
+                .or(constraints.default)
+                .map(f64::from),
+            RegisterValueType::U32Flags(constraints) => constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u32())
+                .or(constraints.default)
+                .map(f64::from),
+            RegisterValueType::U64Flags(constraints) => constraints
+                .val
+                .as_ref()
+                .map(|vf| vf.to_u64())
+                .or(constraints.default)
+                .map(|v| v as f64),
+            RegisterValueType::Bytes(_) | RegisterValueType::String(_) => None,
+            RegisterValueType::Enum(constraints) => constraints
+                .val
+                .or_else(|| {
+                    constraints
+                        .default
+                        .as_ref()
+                        .and_then(|name| constraints.kv.get(name).copied())
+                })
+                .map(f64::from),
+        }
+    }
+
+    /// Store `value` into `val`, integer types round and saturate it to their
+    /// range, coils and discrete inputs take it as a bit pattern. Returns
+    /// `false` if the type holds no number.
+    pub fn set_numeric(&mut self, value: f64) -> bool {
+        match self {
+            RegisterValueType::Coils(constraints) | RegisterValueType::Discrete(constraints) => {
+                let pattern = value.round() as u64;
+                constraints
+                    .val
+                    .resize((constraints.max_bits as usize).div_ceil(8), 0);
+                for i in 0..constraints.max_bits.min(64) {
+                    constraints.set_bit(i, pattern & (1 << i) != 0);
+                }
+            }
+            RegisterValueType::U8(constraints) => constraints.val = Some(value.round() as u8),
+            RegisterValueType::U16(constraints) => constraints.val = Some(value.round() as u16),
+            RegisterValueType::U32(constraints) => constraints.val = Some(value.round() as u32),
+            RegisterValueType::U64(constraints) => constraints.val = Some(value.round() as u64),
+            RegisterValueType::I16(constraints) => constraints.val = Some(value.round() as i16),
+            RegisterValueType::I32(constraints) => constraints.val = Some(value.round() as i32),
+            RegisterValueType::I64(constraints) => constraints.val = Some(value.round() as i64),
+            RegisterValueType::F32(constraints) => constraints.val = Some(value as f32),
+            RegisterValueType::F64(constraints) => constraints.val = Some(value),
+            RegisterValueType::U16Flags(constraints) => {
+                if constraints.validate_flag_names().is_err() {
+                    return false;
+                }
+                let max_flags = constraints.flag_names.len() as u8;
+                constraints.val = Some(U16ValueFlags::from_u16(value.round() as u16, max_flags));
+            }
+            RegisterValueType::U32Flags(constraints) => {
+                if constraints.validate_flag_names().is_err() {
+                    return false;
+                }
+                let max_flags = constraints.flag_names.len() as u8;
+                constraints.val = Some(U32ValueFlags::from_u32(value.round() as u32, max_flags));
+            }
+            RegisterValueType::U64Flags(constraints) => {
+                if constraints.validate_flag_names().is_err() {
+                    return false;
+                }
+                let max_flags = constraints.flag_names.len() as u8;
+                constraints.val = Some(U64ValueFlags::from_u64(value.round() as u64, max_flags));
+            }
+            RegisterValueType::Bytes(_) | RegisterValueType::String(_) => return false,
+            RegisterValueType::Enum(constraints) => constraints.val = Some(value.round() as u32),
+        }
+        true
+    }
+}
+
+impl RegisterValueType {
+    /// Name of the type as used for the schema keys.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegisterValueType::Coils(_) => "Coils",
+            RegisterValueType::Discrete(_) => "Discrete",
+            RegisterValueType::U8(_) => "U8",
+            RegisterValueType::U16(_) => "U16",
+            RegisterValueType::U32(_) => "U32",
+            RegisterValueType::U64(_) => "U64",
+            RegisterValueType::I16(_) => "I16",
+            RegisterValueType::I32(_) => "I32",
+            RegisterValueType::I64(_) => "I64",
+            RegisterValueType::F32(_) => "F32",
+            RegisterValueType::F64(_) => "F64",
+            RegisterValueType::U16Flags(_) => "U16Flags",
+            RegisterValueType::U32Flags(_) => "U32Flags",
+            RegisterValueType::U64Flags(_) => "U64Flags",
+            RegisterValueType::Bytes(_) => "Bytes",
+            RegisterValueType::String(_) => "String",
+            RegisterValueType::Enum(_) => "Enum",
+        }
+    }
+
+    /// Take over the `val` of `other` if it has the same type, keeping the
+    /// constraints of `self`. Returns `false` for different types.
+    pub fn copy_val(&mut self, other: &RegisterValueType) -> bool {
+        match (self, other) {
+            (RegisterValueType::Coils(to), RegisterValueType::Coils(from))
+            | (RegisterValueType::Discrete(to), RegisterValueType::Discrete(from)) => {
+                // keep the bit count of `self`
+                to.val = from.val.clone();
+                to.val.resize((to.max_bits as usize).div_ceil(8), 0);
+            }
+            (RegisterValueType::U8(to), RegisterValueType::U8(from)) => to.val = from.val,
+            (RegisterValueType::U16(to), RegisterValueType::U16(from)) => to.val = from.val,
+            (RegisterValueType::U32(to), RegisterValueType::U32(from)) => to.val = from.val,
+            (RegisterValueType::U64(to), RegisterValueType::U64(from)) => to.val = from.val,
+            (RegisterValueType::I16(to), RegisterValueType::I16(from)) => to.val = from.val,
+            (RegisterValueType::I32(to), RegisterValueType::I32(from)) => to.val = from.val,
+            (RegisterValueType::I64(to), RegisterValueType::I64(from)) => to.val = from.val,
+            (RegisterValueType::F32(to), RegisterValueType::F32(from)) => to.val = from.val,
+            (RegisterValueType::F64(to), RegisterValueType::F64(from)) => to.val = from.val,
+            (RegisterValueType::U16Flags(to), RegisterValueType::U16Flags(from)) => {
+                to.val = from.val.clone()
+            }
+            (RegisterValueType::U32Flags(to), RegisterValueType::U32Flags(from)) => {
+                to.val = from.val.clone()
+            }
+            (RegisterValueType::U64Flags(to), RegisterValueType::U64Flags(from)) => {
+                to.val = from.val.clone()
+            }
+            (RegisterValueType::Bytes(to), RegisterValueType::Bytes(from)) => {
+                to.val = from.val.clone()
+            }
+            (RegisterValueType::String(to), RegisterValueType::String(from)) => {
+                to.val = from.val.clone()
+            }
+            (RegisterValueType::Enum(to), RegisterValueType::Enum(from)) => to.val = from.val,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Registers a value of the type occupies, `None` for coils and discrete
+    /// inputs and for the variable length bytes and strings.
+    pub fn register_count(&self) -> Option<u16> {
+        match self {
+            RegisterValueType::Coils(_) | RegisterValueType::Discrete(_) => None,
+            RegisterValueType::Bytes(_) | RegisterValueType::String(_) => None,
+            RegisterValueType::U8(_)
+            | RegisterValueType::U16(_)
+            | RegisterValueType::I16(_)
+            | RegisterValueType::U16Flags(_) => Some(1),
+            RegisterValueType::U32(_)
+            | RegisterValueType::I32(_)
+            | RegisterValueType::F32(_)
+            | RegisterValueType::U32Flags(_)
+            | RegisterValueType::Enum(_) => Some(2),
+            RegisterValueType::U64(_)
+            | RegisterValueType::I64(_)
+            | RegisterValueType::F64(_)
+            | RegisterValueType::U64Flags(_) => Some(4),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::crc16;
+
+/// Longest ascii frame of the spec, `:` to CRLF.
+const MAX_FRAME_CHARS: usize = 513;
+
+/// Carries rtu frames as modbus ascii frames, `:`, the hex of the slave id,
+/// pdu and LRC, then CRLF. Reads turn ascii frames into rtu frames with
+/// their crc and each flush sends the rtu frame written since the previous
+/// one as ascii, so the rtu framing runs on top unchanged.
+#[derive(Debug)]
+pub struct AsciiLine<T> {
+    inner: T,
+    /// ascii read but not yet decoded
+    received: Vec<u8>,
+    /// rtu bytes decoded but not yet read
+    decoded: Vec<u8>,
+    /// rtu bytes written since the last flush
+    pending: Vec<u8>,
+    /// ascii frame being sent and how much of it went out
+    outgoing: Vec<u8>,
+    sent: usize,
+}
+
+impl<T> AsciiLine<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            received: vec![],
+            decoded: vec![],
+            pending: vec![],
+            outgoing: vec![],
+            sent: 0,
+        }
+    }
+}
+
+/// Two's complement of the byte sum.
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+/// Take the next ascii frame with a valid LRC off `buf` as an rtu frame,
+/// skipping noise and broken frames.
+fn next_frame(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    loop {
+        let start = buf.iter().position(|&c| c == b':');
+        let Some(start) = start else {
+            buf.clear();
+            return None;
+        };
+        buf.drain(..start);
+        let Some(end) = buf.iter().position(|&c| c == b'\n') else {
+            if buf.len() > MAX_FRAME_CHARS {
+                tracing::warn!("ascii frame without end, dropped");
+                buf.clear();
+            }
+            return None;
+        };
+        // a `:` always starts a new frame, an unfinished one is dropped
+        let start = buf[..end].iter().rposition(|&c| c == b':').unwrap_or(0);
+        buf.drain(..start);
+        let line = buf.drain(..=end - start).collect::<Vec<u8>>();
+        let hex = line[1..].trim_ascii_end();
+        let Some(bytes) = decode_hex(hex) else {
+            tracing::warn!("invalid ascii frame {:?}", String::from_utf8_lossy(&line));
+            continue;
+        };
+        // slave id, function code and LRC at least
+        if bytes.len() < 3 {
+            tracing::warn!("short ascii frame {:?}", String::from_utf8_lossy(&line));
+            continue;
+        }
+        let (body, check) = bytes.split_at(bytes.len() - 1);
+        if lrc(body) != check[0] {
+            tracing::warn!("lrc mismatch, ascii frame dropped");
+            continue;
+        }
+        let mut frame = body.to_vec();
+        frame.extend(crc16(body).to_le_bytes());
+        return Some(frame);
+    }
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    let pairs = hex.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// The ascii frame of an rtu frame, a bad crc becomes a bad LRC.
+fn encode_frame(frame: &[u8]) -> Vec<u8> {
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    let mut check = lrc(body);
+    if crc16(body).to_le_bytes() != crc {
+        check = check.wrapping_add(1);
+    }
+    let mut ascii = vec![b':'];
+    for byte in body.iter().chain([&check]) {
+        ascii.extend(format!("{byte:02X}").bytes());
+    }
+    ascii.extend(b"\r\n");
+    ascii
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for AsciiLine<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.decoded.is_empty() {
+            let mut chunk = [0u8; 256];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+            this.received.extend_from_slice(chunk.filled());
+            while let Some(frame) = next_frame(&mut this.received) {
+                this.decoded.extend(frame);
+            }
+        }
+        let n = this.decoded.len().min(buf.remaining());
+        buf.put_slice(&this.decoded[..n]);
+        this.decoded.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for AsciiLine<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().pending.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.outgoing.is_empty() && !this.pending.is_empty() {
+            // slave id, function code and crc at least
+            if this.pending.len() >= 4 {
+                this.outgoing = encode_frame(&this.pending);
+                this.sent = 0;
+            }
+            this.pending.clear();
+        }
+        while this.sent < this.outgoing.len() {
+            let n = ready!(Pin::new(&mut this.inner).poll_write(cx, &this.outgoing[this.sent..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.sent += n;
+        }
+        this.outgoing.clear();
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// `body` followed by its crc.
+    fn rtu(body: &[u8]) -> Vec<u8> {
+        let mut frame = body.to_vec();
+        frame.extend(crc16(body).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn lrc_of_the_spec_example() {
+        // read 1 holding register of slave 1 is `:010300000001FB`
+        assert_eq!(lrc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]), 0xFB);
+    }
+
+    #[test]
+    fn ascii_frames_become_rtu_frames() {
+        let mut buf = b":010300000001FB\r\n".to_vec();
+        assert_eq!(
+            next_frame(&mut buf),
+            Some(rtu(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn noise_and_unfinished_frames_are_dropped() {
+        let mut buf = b"xx:0103:010300000001FB\r\n".to_vec();
+        assert_eq!(
+            next_frame(&mut buf),
+            Some(rtu(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]))
+        );
+    }
+
+    #[test]
+    fn bad_lrc_and_bad_hex_are_dropped() {
+        let mut buf = b":010300000001FC\r\n:0103000000G1FB\r\n".to_vec();
+        assert_eq!(next_frame(&mut buf), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn incomplete_frames_wait_for_their_end() {
+        let mut buf = b":0103000000".to_vec();
+        assert_eq!(next_frame(&mut buf), None);
+        assert_eq!(buf, b":0103000000");
+    }
+
+    #[test]
+    fn rtu_frames_are_sent_as_ascii() {
+        let frame = rtu(&[0x01, 0x03, 0x02, 0x00, 0x2A]);
+        assert_eq!(encode_frame(&frame), b":010302002AD0\r\n");
+        // a corrupted crc goes out as a corrupted lrc
+        let mut corrupt = frame.clone();
+        corrupt[5] ^= 0xFF;
+        assert_eq!(encode_frame(&corrupt), b":010302002AD1\r\n");
+    }
+
+    #[tokio::test]
+    async fn line_reads_and_writes_rtu_frames() {
+        let (near, mut far) = tokio::io::duplex(64);
+        let mut line = AsciiLine::new(near);
+
+        far.write_all(b":010300000001FB\r\n").await.unwrap();
+        let mut read = [0u8; 8];
+        line.read_exact(&mut read).await.unwrap();
+        assert_eq!(read.to_vec(), rtu(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]));
+
+        line.write_all(&rtu(&[0x01, 0x03, 0x02, 0x00, 0x2A]))
+            .await
+            .unwrap();
+        line.flush().await.unwrap();
+        let mut sent = [0u8; 15];
+        far.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b":010302002AD0\r\n");
+    }
+}
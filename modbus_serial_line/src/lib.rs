@@ -0,0 +1,244 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+use tokio_serial::{SerialPort, SerialStream};
+
+mod ascii;
+
+pub use ascii::{lrc, AsciiLine};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum FlowControl {
+    None,
+    /// XON/XOFF
+    Software,
+    /// RTS/CTS
+    Hardware,
+}
+
+/// Line settings of a serial port.
+#[derive(Clone, Debug)]
+pub struct SerialParams {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    /// raise RTS while sending, for rs-485 transceivers without automatic
+    /// direction control
+    pub rts_toggle: bool,
+    /// silence between frames, 3.5 characters if unset
+    pub inter_frame: Option<Duration>,
+}
+
+impl SerialParams {
+    /// Time on the wire of one character: start bit, data bits, parity bit
+    /// and stop bits.
+    pub fn char_time(&self) -> Duration {
+        let parity = if self.parity == Parity::None { 0 } else { 1 };
+        let bits = 1 + self.data_bits as u64 + parity + self.stop_bits as u64;
+        Duration::from_micros(bits * 1_000_000 / self.baud_rate.max(1) as u64)
+    }
+
+    /// The configured silence between frames, else t3.5 of the modbus serial
+    /// line spec, fixed at 1750us above 19200 baud.
+    pub fn inter_frame(&self) -> Duration {
+        match self.inter_frame {
+            Some(inter_frame) => inter_frame,
+            None if self.baud_rate > 19200 => Duration::from_micros(1750),
+            None => self.char_time() * 7 / 2,
+        }
+    }
+
+    fn builder(&self, path: &str) -> tokio_serial::SerialPortBuilder {
+        let data_bits = match self.data_bits {
+            5 => tokio_serial::DataBits::Five,
+            6 => tokio_serial::DataBits::Six,
+            7 => tokio_serial::DataBits::Seven,
+            _ => tokio_serial::DataBits::Eight,
+        };
+        let parity = match self.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Even => tokio_serial::Parity::Even,
+            Parity::Odd => tokio_serial::Parity::Odd,
+        };
+        let stop_bits = match self.stop_bits {
+            2 => tokio_serial::StopBits::Two,
+            _ => tokio_serial::StopBits::One,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => tokio_serial::FlowControl::None,
+            FlowControl::Software => tokio_serial::FlowControl::Software,
+            FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+        };
+        tokio_serial::new(path, self.baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .flow_control(flow_control)
+    }
+}
+
+/// A serial port keeping the inter-frame silence before every frame it sends
+/// and raising RTS for the duration of a frame if asked to. A frame ends with
+/// a flush.
+#[derive(Debug)]
+pub struct SerialLine {
+    port: SerialStream,
+    char_time: Duration,
+    inter_frame: Duration,
+    rts_toggle: bool,
+    /// when the line last carried a byte in either direction
+    last_activity: Instant,
+    sending: bool,
+    sent: usize,
+    /// when the last character of the frame being flushed leaves the port
+    frame_end: Option<Instant>,
+    wait: Option<Pin<Box<Sleep>>>,
+}
+
+impl SerialLine {
+    pub fn open(path: &str, params: &SerialParams) -> tokio_serial::Result<Self> {
+        let mut port = SerialStream::open(&params.builder(path))?;
+        if params.rts_toggle {
+            port.write_request_to_send(false)?;
+        }
+        Ok(Self {
+            port,
+            char_time: params.char_time(),
+            inter_frame: params.inter_frame(),
+            rts_toggle: params.rts_toggle,
+            last_activity: Instant::now(),
+            sending: false,
+            sent: 0,
+            frame_end: None,
+            wait: None,
+        })
+    }
+
+    /// Wait until `deadline`, keeping the timer across polls.
+    fn poll_wait(&mut self, cx: &mut Context<'_>, deadline: Instant) -> Poll<()> {
+        if deadline <= Instant::now() {
+            self.wait = None;
+            return Poll::Ready(());
+        }
+        let wait = self
+            .wait
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        ready!(wait.as_mut().poll(cx));
+        self.wait = None;
+        Poll::Ready(())
+    }
+}
+
+impl AsyncRead for SerialLine {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.port).poll_read(cx, buf))?;
+        if buf.filled().len() > filled {
+            this.last_activity = Instant::now();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for SerialLine {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.sending {
+            let deadline = this.last_activity + this.inter_frame;
+            ready!(this.poll_wait(cx, deadline));
+            if this.rts_toggle {
+                this.port.write_request_to_send(true)?;
+            }
+            this.sending = true;
+            this.sent = 0;
+        }
+        let n = ready!(Pin::new(&mut this.port).poll_write(cx, buf))?;
+        this.sent += n;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.port).poll_flush(cx))?;
+        if this.sending {
+            // the port buffers what was written, the frame is on the wire
+            // once the last character has been shifted out
+            let (char_time, sent) = (this.char_time, this.sent as u32);
+            let end = *this
+                .frame_end
+                .get_or_insert_with(|| Instant::now() + char_time * sent);
+            if this.rts_toggle {
+                ready!(this.poll_wait(cx, end));
+                this.port.write_request_to_send(false)?;
+            }
+            this.last_activity = end;
+            this.frame_end = None;
+            this.sending = false;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().port).poll_shutdown(cx)
+    }
+}
+
+/// Crc of the modbus serial line spec, sent low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_of_the_spec_example() {
+        // read 10 holding registers of slave 1, crc C5 CD on the wire
+        assert_eq!(
+            crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(),
+            [0xC5, 0xCD]
+        );
+    }
+}